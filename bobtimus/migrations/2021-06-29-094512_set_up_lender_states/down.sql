@@ -0,0 +1 @@
+DROP TABLE lender_states;
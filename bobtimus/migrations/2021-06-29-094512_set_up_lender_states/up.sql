@@ -0,0 +1,5 @@
+CREATE TABLE lender_states
+(
+       id               TEXT NOT NULL PRIMARY KEY,
+       state            TEXT NOT NULL
+);
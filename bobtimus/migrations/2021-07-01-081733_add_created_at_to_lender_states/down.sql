@@ -0,0 +1,8 @@
+CREATE TABLE lender_states_backup
+(
+       id               TEXT NOT NULL PRIMARY KEY,
+       state            TEXT NOT NULL
+);
+INSERT INTO lender_states_backup SELECT id, state FROM lender_states;
+DROP TABLE lender_states;
+ALTER TABLE lender_states_backup RENAME TO lender_states;
@@ -0,0 +1 @@
+ALTER TABLE lender_states ADD COLUMN created_at BIGINT NOT NULL DEFAULT 0;
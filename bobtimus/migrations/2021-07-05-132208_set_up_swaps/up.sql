@@ -0,0 +1,13 @@
+CREATE TABLE swaps
+(
+       txid                     TEXT NOT NULL PRIMARY KEY,
+       direction                TEXT NOT NULL,
+       taker_asset_id           TEXT NOT NULL,
+       taker_amount             BIGINT NOT NULL,
+       maker_asset_id           TEXT NOT NULL,
+       maker_amount             BIGINT NOT NULL,
+       rate                     BIGINT NOT NULL,
+       taker_address            TEXT NOT NULL,
+       created_at               BIGINT NOT NULL,
+       status                   TEXT NOT NULL
+);
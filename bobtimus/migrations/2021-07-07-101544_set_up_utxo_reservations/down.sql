@@ -0,0 +1 @@
+DROP TABLE utxo_reservations;
@@ -0,0 +1,8 @@
+CREATE TABLE utxo_reservations
+(
+       txid             TEXT NOT NULL,
+       vout             INTEGER NOT NULL,
+       swap_txid        TEXT NOT NULL,
+       expires_at       BIGINT NOT NULL,
+       PRIMARY KEY (txid, vout)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE liquidations_backup
+(
+       id               TEXT NOT NULL PRIMARY KEY,
+       tx_hex           TEXT NOT NULL,
+       locktime         BIGINT NOT NULL
+);
+INSERT INTO liquidations_backup SELECT id, tx_hex, locktime FROM liquidations;
+DROP TABLE liquidations;
+ALTER TABLE liquidations_backup RENAME TO liquidations;
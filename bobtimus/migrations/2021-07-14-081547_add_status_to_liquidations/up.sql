@@ -0,0 +1 @@
+ALTER TABLE liquidations ADD COLUMN status TEXT NOT NULL DEFAULT 'pending';
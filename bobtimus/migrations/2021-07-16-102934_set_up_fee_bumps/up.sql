@@ -0,0 +1,7 @@
+CREATE TABLE fee_bumps
+(
+       child_txid       TEXT NOT NULL PRIMARY KEY,
+       parent_txid      TEXT NOT NULL,
+       fee              BIGINT NOT NULL,
+       created_at       BIGINT NOT NULL
+);
@@ -0,0 +1,19 @@
+CREATE TABLE lender_states_backup
+(
+       id               TEXT NOT NULL PRIMARY KEY,
+       state            TEXT NOT NULL,
+       created_at       BIGINT NOT NULL DEFAULT 0
+);
+INSERT INTO lender_states_backup SELECT id, state, created_at FROM lender_states;
+DROP TABLE lender_states;
+ALTER TABLE lender_states_backup RENAME TO lender_states;
+CREATE TABLE liquidations_backup
+(
+       id               TEXT NOT NULL PRIMARY KEY,
+       tx_hex           TEXT NOT NULL,
+       locktime         BIGINT NOT NULL,
+       status           TEXT NOT NULL DEFAULT 'pending'
+);
+INSERT INTO liquidations_backup SELECT id, tx_hex, locktime, status FROM liquidations;
+DROP TABLE liquidations;
+ALTER TABLE liquidations_backup RENAME TO liquidations;
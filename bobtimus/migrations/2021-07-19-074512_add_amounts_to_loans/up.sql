@@ -0,0 +1,4 @@
+ALTER TABLE lender_states ADD COLUMN collateral_amount BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE lender_states ADD COLUMN principal_amount BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE liquidations ADD COLUMN collateral_amount BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE liquidations ADD COLUMN principal_amount BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+DROP TABLE price_attestations;
+DROP TABLE oracle_keys;
@@ -0,0 +1,13 @@
+CREATE TABLE oracle_keys
+(
+       address          TEXT NOT NULL PRIMARY KEY
+);
+CREATE TABLE price_attestations
+(
+       sequence         BIGINT NOT NULL PRIMARY KEY,
+       pair             TEXT NOT NULL,
+       ask              BIGINT NOT NULL,
+       bid              BIGINT NOT NULL,
+       timestamp        BIGINT NOT NULL,
+       signature        TEXT NOT NULL
+);
@@ -0,0 +1,16 @@
+CREATE TABLE swaps_backup
+(
+       txid                     TEXT NOT NULL PRIMARY KEY,
+       direction                TEXT NOT NULL,
+       taker_asset_id           TEXT NOT NULL,
+       taker_amount             BIGINT NOT NULL,
+       maker_asset_id           TEXT NOT NULL,
+       maker_amount             BIGINT NOT NULL,
+       rate                     BIGINT NOT NULL,
+       taker_address            TEXT NOT NULL,
+       created_at               BIGINT NOT NULL,
+       status                   TEXT NOT NULL
+);
+INSERT INTO swaps_backup SELECT txid, direction, taker_asset_id, taker_amount, maker_asset_id, maker_amount, rate, taker_address, created_at, status FROM swaps;
+DROP TABLE swaps;
+ALTER TABLE swaps_backup RENAME TO swaps;
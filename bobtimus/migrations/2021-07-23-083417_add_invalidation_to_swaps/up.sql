@@ -0,0 +1 @@
+ALTER TABLE swaps ADD COLUMN invalidation_txid TEXT;
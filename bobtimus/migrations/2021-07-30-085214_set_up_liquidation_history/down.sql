@@ -0,0 +1,2 @@
+INSERT INTO liquidations SELECT id, tx_hex, locktime, status, collateral_amount, principal_amount FROM liquidation_history;
+DROP TABLE liquidation_history;
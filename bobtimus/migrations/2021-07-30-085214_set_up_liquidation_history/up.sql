@@ -0,0 +1,12 @@
+CREATE TABLE liquidation_history
+(
+       id                       TEXT NOT NULL PRIMARY KEY,
+       tx_hex                   TEXT NOT NULL,
+       locktime                 BIGINT NOT NULL,
+       status                   TEXT NOT NULL,
+       collateral_amount        BIGINT NOT NULL,
+       principal_amount         BIGINT NOT NULL,
+       settled_at               BIGINT NOT NULL
+);
+INSERT INTO liquidation_history SELECT id, tx_hex, locktime, status, collateral_amount, principal_amount, 0 FROM liquidations WHERE status IN ('repaid', 'confirmed');
+DELETE FROM liquidations WHERE status IN ('repaid', 'confirmed');
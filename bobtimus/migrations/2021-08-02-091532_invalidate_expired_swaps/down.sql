@@ -0,0 +1 @@
+UPDATE swaps SET status = 'expired' WHERE status = 'invalidated' AND invalidation_txid IS NULL;
@@ -0,0 +1 @@
+UPDATE swaps SET status = 'invalidated' WHERE status = 'expired';
@@ -1,4 +1,5 @@
-use anyhow::{anyhow, Context, Result};
+use crate::quote::Direction;
+use anyhow::{anyhow, bail, Context, Result};
 use elements::bitcoin::{Amount, Denomination};
 use rust_decimal::{prelude::ToPrimitive, Decimal};
 use serde::{Deserialize, Serialize};
@@ -22,6 +23,15 @@ impl Rate {
         bid: LiquidUsdt(Amount::ZERO),
     };
 
+    /// The price per L-BTC which applies to a taker trading in the
+    /// given direction.
+    pub fn price(&self, direction: Direction) -> LiquidUsdt {
+        match direction {
+            Direction::Buy => self.ask,
+            Direction::Sell => self.bid,
+        }
+    }
+
     pub fn buy_quote(&self, base: LiquidBtc) -> Result<LiquidUsdt> {
         let sats = base.0.as_sat();
         let btc = Decimal::from(sats)
@@ -53,6 +63,42 @@ impl Rate {
 
         Ok(btc)
     }
+
+    /// The amount of L-USDt the taker has to pay us to buy exactly
+    /// `base` at our ask, rounded up.
+    pub fn sell_base_cost(&self, base: LiquidBtc) -> Result<LiquidUsdt> {
+        let satodollars = mul_div_ceil(
+            base.0.as_sat(),
+            self.ask.as_satodollar(),
+            Amount::ONE_BTC.as_sat(),
+        )?;
+
+        Ok(LiquidUsdt::from_satodollar(satodollars))
+    }
+
+    /// The amount of L-BTC the taker has to sell us to receive
+    /// exactly `quote` at our bid, rounded up.
+    pub fn buy_quote_cost(&self, quote: LiquidUsdt) -> Result<LiquidBtc> {
+        let sats = mul_div_ceil(
+            quote.as_satodollar(),
+            Amount::ONE_BTC.as_sat(),
+            self.bid.as_satodollar(),
+        )?;
+
+        Ok(LiquidBtc::from(Amount::from_sat(sats)))
+    }
+}
+
+/// Compute `a * b / c`, rounding up.
+fn mul_div_ceil(a: u64, b: u64, c: u64) -> Result<u64> {
+    if c == 0 {
+        bail!("division by zero");
+    }
+
+    let (c, product) = (u128::from(c), u128::from(a) * u128::from(b));
+    let quotient = (product + c - 1) / c;
+
+    u64::try_from(quotient).context("amount overflow")
 }
 
 #[derive(Clone, Copy, PartialEq, Serialize, Default)]
@@ -81,7 +127,10 @@ impl LiquidUsdt {
         Ok(Self(amount))
     }
 
-    fn serialize_to_nominal<S>(amount: &LiquidUsdt, serializer: S) -> Result<S::Ok, S::Error>
+    pub(crate) fn serialize_to_nominal<S>(
+        amount: &LiquidUsdt,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
@@ -128,6 +177,17 @@ impl From<LiquidBtc> for Amount {
     }
 }
 
+#[cfg(test)]
+impl Rate {
+    /// A rate with `ask` and `bid` given in whole L-USDt per L-BTC.
+    pub fn from_whole_usdt(ask: u64, bid: u64) -> Self {
+        Rate {
+            ask: LiquidUsdt::from_satodollar(ask * Amount::ONE_BTC.as_sat()),
+            bid: LiquidUsdt::from_satodollar(bid * Amount::ONE_BTC.as_sat()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,6 +219,43 @@ mod tests {
         assert_eq!(btc_amount, LiquidBtc(Amount::from_btc(0.5).unwrap()))
     }
 
+    #[test]
+    fn sell_base_cost_is_inverse_of_sell_base() {
+        let rate = Rate {
+            ask: LiquidUsdt::try_from(19_313.52).unwrap(),
+            bid: LiquidUsdt::try_from(19_213.52).unwrap(),
+        };
+
+        let btc_amount = LiquidBtc(Amount::from_btc(0.5).unwrap());
+        let usdt_amount = rate.sell_base_cost(btc_amount).unwrap();
+
+        assert_eq!(
+            usdt_amount,
+            LiquidUsdt::from_str_in_dollar("9656.76").unwrap()
+        )
+    }
+
+    #[test]
+    fn costs_are_rounded_up() {
+        let rate = Rate {
+            ask: LiquidUsdt::from_satodollar(3),
+            bid: LiquidUsdt::from_satodollar(3),
+        };
+
+        let usdt_amount = rate.sell_base_cost(LiquidBtc(Amount::from_sat(1))).unwrap();
+        let btc_amount = rate.buy_quote_cost(LiquidUsdt::from_satodollar(1)).unwrap();
+
+        assert_eq!(usdt_amount, LiquidUsdt::from_satodollar(1));
+        assert_eq!(btc_amount, LiquidBtc(Amount::from_sat(33_333_334)));
+    }
+
+    #[test]
+    fn cost_with_zero_bid_fails() {
+        let rate = Rate::ZERO;
+
+        assert!(rate.buy_quote_cost(LiquidUsdt::from_satodollar(1)).is_err());
+    }
+
     #[test]
     fn rate_serialized_with_nominal_unit() {
         let rate = Rate {
@@ -0,0 +1,1360 @@
+use crate::{
+    database::{queries, Sqlite, SwapStatus, UtxoReservationForm},
+    elements_rpc::Client,
+    quote::UnknownQuote,
+    record_swaps, release_inputs, unix_timestamp, Bobtimus, LatestRate, PreparedSwap,
+};
+use anyhow::{bail, Context, Result};
+use elements::{
+    bitcoin::{Amount, PublicKey},
+    confidential::{Asset, AssetBlindingFactor, Nonce, Value, ValueBlindingFactor},
+    encode::serialize_hex,
+    secp256k1_zkp::{
+        rand::{CryptoRng, RngCore},
+        Message, Signature, SECP256K1,
+    },
+    sighash::SigHashCache,
+    Address, AddressParams, AssetId, OutPoint, SigHashType, Transaction, TxIn, TxOut, TxOutSecrets,
+    TxOutWitness, Txid,
+};
+use estimate_transaction_size::avg_vbytes;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("inputs are too small to cover the swap amount and fee")]
+pub struct TakerInputsTooSmall;
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("unknown batch transaction {0}")]
+pub struct UnknownBatch(pub Txid);
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("swap batching is not enabled")]
+pub struct BatchingDisabled;
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("invalid signature for input {0}")]
+pub struct InvalidSignature(pub OutPoint);
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("signatures must be for the inputs of a single taker")]
+pub struct ForeignSignatures;
+
+/// How far a batch swap transaction is from being broadcast.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BatchStatus {
+    pub txid: Txid,
+    /// Number of takers whose signatures we are still waiting for.
+    /// The transaction is broadcast once this reaches zero.
+    pub missing_signatures: usize,
+    /// The hex of the transaction which replaced the batch after a
+    /// taker was dropped from it, which has to be signed instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaced_by: Option<String>,
+}
+
+struct BatchRequest {
+    swap: PreparedSwap,
+    response: oneshot::Sender<Result<(Transaction, Option<TxOutSecrets>)>>,
+}
+
+/// Swap requests waiting to be included in a batch, see
+/// [`build_batches`].
+pub struct BatchRequests(mpsc::UnboundedReceiver<BatchRequest>);
+
+/// A batch swap transaction which is waiting for the signatures of
+/// its takers.
+struct PendingBatch {
+    transaction: Transaction,
+    swaps: Vec<PreparedSwap>,
+    signed: Vec<bool>,
+    /// The secrets of the output paying us the service fee of each
+    /// swap, if it has one.
+    service_fee_secrets: Vec<Option<TxOutSecrets>>,
+    /// Our inputs, which stay locked and reserved until the batch is
+    /// broadcast or abandoned.
+    our_inputs: Vec<OutPoint>,
+    /// Seconds since the UNIX epoch after which the batch is
+    /// abandoned, together with the reservations of our inputs.
+    expires_at: u64,
+}
+
+impl PendingBatch {
+    /// Keep the witnesses of a single taker's inputs in `transaction`,
+    /// if they sign the batch transaction.
+    fn add_signatures(&mut self, transaction: &Transaction) -> Result<()> {
+        // Only the witnesses we have not seen yet were added by the
+        // taker posting them
+        let added = transaction
+            .input
+            .iter()
+            .zip(self.transaction.input.iter())
+            .filter(|(theirs, ours)| {
+                !theirs.witness.script_witness.is_empty()
+                    && theirs.witness.script_witness != ours.witness.script_witness
+            })
+            .map(|(_, ours)| self.taker_of(ours.previous_output).ok_or(ForeignSignatures))
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        let taker = match added.len() {
+            0 => return Ok(()),
+            1 => added.into_iter().next().expect("one taker"),
+            _ => bail!(ForeignSignatures),
+        };
+
+        let mut witnesses = Vec::new();
+        for input in self.swaps[taker].inputs.iter() {
+            let index = self
+                .transaction
+                .input
+                .iter()
+                .position(|txin| txin.previous_output == input.txin)
+                .expect("inputs of taker are in batch");
+            let witness = &transaction.input[index].witness.script_witness;
+
+            verify_witness(&self.transaction, index, &input.txout, witness)
+                .context(InvalidSignature(input.txin))?;
+            witnesses.push((index, witness.clone()));
+        }
+
+        for (index, witness) in witnesses {
+            self.transaction.input[index].witness.script_witness = witness;
+        }
+        self.signed[taker] = true;
+
+        Ok(())
+    }
+
+    fn missing_signatures(&self) -> usize {
+        self.signed.iter().filter(|signed| !**signed).count()
+    }
+
+    /// The index of the swap whose taker spends `outpoint`.
+    fn taker_of(&self, outpoint: OutPoint) -> Option<usize> {
+        self.swaps
+            .iter()
+            .position(|swap| swap.inputs.iter().any(|input| input.txin == outpoint))
+    }
+}
+
+#[derive(Default)]
+struct Batches {
+    pending: HashMap<Txid, PendingBatch>,
+    /// The txids of the batches which replaced the ones which failed
+    /// to be broadcast.
+    replaced_by: HashMap<Txid, Txid>,
+}
+
+/// Merges swap requests arriving within a short window into a single
+/// transaction.
+///
+/// Compared to one transaction per swap, we only need one change
+/// output per asset for the whole batch. Each taker signs their own
+/// inputs of the combined transaction and we only sign ours, and
+/// broadcast, once every taker has done so.
+#[derive(Clone)]
+pub struct SwapBatcher {
+    requests: mpsc::UnboundedSender<BatchRequest>,
+    batches: Arc<Mutex<Batches>>,
+}
+
+impl SwapBatcher {
+    pub fn new() -> (Self, BatchRequests) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let batcher = Self {
+            requests: sender,
+            batches: Arc::new(Mutex::new(Batches::default())),
+        };
+
+        (batcher, BatchRequests(receiver))
+    }
+
+    /// Include `swap` in the next batch and wait for the resulting
+    /// transaction, which is not signed by anyone yet, together with
+    /// the secrets of the output paying us its service fee.
+    pub async fn submit(&self, swap: PreparedSwap) -> Result<(Transaction, Option<TxOutSecrets>)> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests
+            .send(BatchRequest {
+                swap,
+                response: sender,
+            })
+            .map_err(|_| anyhow::anyhow!("swap batching has stopped"))?;
+
+        receiver.await.context("batch was dropped")?
+    }
+
+    /// The batch transaction `txid`, with the signatures added so far.
+    pub async fn transaction(&self, txid: Txid) -> Option<Transaction> {
+        self.batches
+            .lock()
+            .await
+            .pending
+            .get(&txid)
+            .map(|batch| batch.transaction.clone())
+    }
+
+    /// How far the batch `txid`, or the batch which replaced it, is
+    /// from being broadcast.
+    pub async fn status(&self, txid: Txid) -> Result<BatchStatus> {
+        let batches = self.batches.lock().await;
+        let current = batches.replaced_by.get(&txid).copied().unwrap_or(txid);
+        let batch = batches.pending.get(&current).ok_or(UnknownBatch(txid))?;
+
+        Ok(BatchStatus {
+            txid,
+            missing_signatures: batch.missing_signatures(),
+            replaced_by: (current != txid).then(|| serialize_hex(&batch.transaction)),
+        })
+    }
+
+    /// Add a taker's signatures to the batch transaction they were
+    /// handed.
+    ///
+    /// `transaction` may only add witnesses to the inputs of a single
+    /// taker, all of which have to be signed. If the batch still fails
+    /// to be broadcast once everyone has signed, the takers whose
+    /// inputs have been spent in the meantime are dropped from it and
+    /// the others have to sign the replacement.
+    pub async fn add_signatures<R, RS>(
+        &self,
+        bobtimus: &Mutex<Bobtimus<R, RS>>,
+        transaction: Transaction,
+    ) -> Result<BatchStatus>
+    where
+        R: RngCore + CryptoRng,
+        RS: LatestRate,
+    {
+        let txid = transaction.txid();
+
+        let complete = {
+            let mut batches = self.batches.lock().await;
+            let batch = batches
+                .pending
+                .get_mut(&txid)
+                // Expired batches are about to be abandoned
+                .filter(|batch| unix_timestamp() <= batch.expires_at)
+                .ok_or(UnknownBatch(txid))?;
+
+            batch.add_signatures(&transaction)?;
+
+            let missing_signatures = batch.missing_signatures();
+            if missing_signatures > 0 {
+                return Ok(BatchStatus {
+                    txid,
+                    missing_signatures,
+                    replaced_by: None,
+                });
+            }
+
+            batches.pending.remove(&txid).expect("batch is pending")
+        };
+
+        let mut bobtimus = bobtimus.lock().await;
+        if let Err(e) = bobtimus.broadcast_batch(&complete.transaction).await {
+            tracing::error!("Failed to broadcast batch {}: {:#}", txid, e);
+
+            return match self.drop_offending_takers(&mut bobtimus, complete).await? {
+                Some(_) => self.status(txid).await,
+                None => Err(e.context("failed to broadcast batch")),
+            };
+        }
+        bobtimus.record_batch(&complete).await?;
+
+        Ok(BatchStatus {
+            txid,
+            missing_signatures: 0,
+            replaced_by: None,
+        })
+    }
+
+    /// Rebuild a batch which failed to be broadcast without the takers
+    /// whose inputs are no longer unspent, returning the txid of the
+    /// replacement.
+    ///
+    /// If no taker is to blame, the batch is abandoned as a whole.
+    async fn drop_offending_takers<R, RS>(
+        &self,
+        bobtimus: &mut Bobtimus<R, RS>,
+        batch: PendingBatch,
+    ) -> Result<Option<Txid>>
+    where
+        R: RngCore + CryptoRng,
+        RS: LatestRate,
+    {
+        let txid = batch.transaction.txid();
+        release_inputs(&bobtimus.elementsd, &bobtimus.db, batch.our_inputs).await?;
+
+        let number_of_swaps = batch.swaps.len();
+        let mut remaining = Vec::new();
+        for swap in batch.swaps {
+            let mut unspent = true;
+            for input in swap.inputs.iter() {
+                unspent &= bobtimus.elementsd.is_unspent(input.txin, true).await?;
+            }
+
+            if unspent {
+                remaining.push(swap);
+            } else {
+                tracing::info!(
+                    "Dropping taker {} from batch {} since their inputs were spent",
+                    swap.address,
+                    txid
+                );
+            }
+        }
+        if remaining.is_empty() || remaining.len() == number_of_swaps {
+            return Ok(None);
+        }
+
+        let fee_rate = bobtimus.fee_rate_service.fee_rate().await?;
+        let swaps = remaining
+            .into_iter()
+            .map(|swap| {
+                let outputs = TakerOutputs::new(&swap, fee_rate, bobtimus.btc_asset_id)?;
+
+                Ok((swap, outputs))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let replacement = bobtimus.pending_batch(swaps, fee_rate).await?;
+        let replacement_txid = replacement.transaction.txid();
+
+        let mut batches = self.batches.lock().await;
+        for replaced_by in batches.replaced_by.values_mut() {
+            if *replaced_by == txid {
+                *replaced_by = replacement_txid;
+            }
+        }
+        batches.replaced_by.insert(txid, replacement_txid);
+        batches.pending.insert(replacement_txid, replacement);
+
+        Ok(Some(replacement_txid))
+    }
+
+    async fn add_batch(&self, batch: PendingBatch) {
+        self.batches
+            .lock()
+            .await
+            .pending
+            .insert(batch.transaction.txid(), batch);
+    }
+
+    /// Release our inputs of the batches which have not been signed
+    /// by all of their takers before they expired.
+    async fn abandon_expired_batches(&self, elementsd: &Client, db: &Sqlite) -> Result<()> {
+        let now = unix_timestamp();
+        let expired = {
+            let mut batches = self.batches.lock().await;
+            let Batches {
+                pending,
+                replaced_by,
+            } = &mut *batches;
+
+            let txids = pending
+                .iter()
+                .filter(|(_, batch)| now > batch.expires_at)
+                .map(|(txid, _)| *txid)
+                .collect::<Vec<_>>();
+            let expired = txids
+                .into_iter()
+                .filter_map(|txid| pending.remove(&txid))
+                .collect::<Vec<_>>();
+            replaced_by.retain(|_, txid| pending.contains_key(txid));
+
+            expired
+        };
+
+        for batch in expired {
+            release_inputs(elementsd, db, batch.our_inputs).await?;
+
+            tracing::info!(
+                "Abandoned batch {} which was not signed in time",
+                batch.transaction.txid()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl<R, RS> Bobtimus<R, RS>
+where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    /// Build a transaction settling all the `swaps` at once.
+    ///
+    /// Our inputs are selected and locked, and returned together with
+    /// the transaction. The service fee of each swap is paid to its
+    /// own blinded output, whose secrets are returned per swap so
+    /// that its taker can verify it.
+    ///
+    /// Nobody has signed the transaction yet.
+    async fn batch_transaction(
+        &mut self,
+        swaps: &[(&PreparedSwap, &TakerOutputs)],
+        fee_rate: Amount,
+    ) -> Result<(Transaction, Vec<OutPoint>, Vec<Option<TxOutSecrets>>)> {
+        let btc_asset_id = self.btc_asset_id;
+
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        let mut service_fees = Vec::new();
+        let mut taker_fees = 0;
+        // How much of each asset the swaps leave us with, before
+        // adding our inputs and paying our share of the fee
+        let mut balances = HashMap::<AssetId, i128>::new();
+        balances.insert(btc_asset_id, 0);
+
+        for (index, (swap, taker_outputs)) in swaps.iter().enumerate() {
+            inputs.extend(swap.inputs.iter().map(|input| (input.txin, input.secrets)));
+            outputs.extend(taker_outputs.outputs.iter().cloned());
+            taker_fees += taker_outputs.fee.as_sat();
+
+            *balances.entry(swap.taker.0).or_default() += i128::from(swap.taker.1.as_sat());
+            *balances.entry(swap.maker.0).or_default() -= i128::from(swap.maker.1.as_sat());
+
+            let (service_fee_asset, service_fee) = swap.service_fee;
+            if service_fee > Amount::ZERO {
+                *balances.entry(service_fee_asset).or_default() -= i128::from(service_fee.as_sat());
+                service_fees.push((index, swap.service_fee));
+            }
+        }
+
+        // We get one output per asset, merging what we receive with
+        // our change
+        let number_of_outputs = (outputs.len() + balances.len() + service_fees.len()) as u64;
+        let our_fee = move |number_of_inputs: usize| {
+            let vbytes = number_of_inputs as u64 * avg_vbytes::INPUT
+                + number_of_outputs * avg_vbytes::OUTPUT
+                + avg_vbytes::FEE;
+
+            i128::from((fee_rate.as_sat() * vbytes).saturating_sub(taker_fees))
+        };
+
+        let mut our_inputs = Vec::new();
+        let transaction = async {
+            let mut deficits = balances
+                .iter()
+                .filter(|(asset, balance)| **asset != btc_asset_id && **balance < 0)
+                .map(|(asset, balance)| (*asset, -*balance))
+                .collect::<Vec<_>>();
+            // Assume that one L-BTC input will be needed to pay the fee
+            let btc_deficit = our_fee(inputs.len() + deficits.len() + 1) - balances[&btc_asset_id];
+            if btc_deficit > 0 {
+                deficits.push((btc_asset_id, btc_deficit));
+            }
+
+            for (asset, deficit) in deficits {
+                let amount = Amount::from_sat(u64::try_from(deficit)?);
+                let selected = Self::find_inputs(&self.elementsd, asset, amount, true)
+                    .await
+                    .with_context(|| format!("could not find inputs for asset {}", asset))?;
+
+                for input in selected {
+                    our_inputs.push(input.txin);
+
+                    let secrets = input
+                        .original_txout
+                        .unblind(SECP256K1, input.blinding_key)
+                        .with_context(|| format!("failed to unblind our input {}", input.txin))?;
+                    *balances.entry(asset).or_default() += i128::from(secrets.value);
+                    inputs.push((input.txin, secrets));
+                }
+            }
+            *balances.entry(btc_asset_id).or_default() -= our_fee(inputs.len());
+
+            let address = self
+                .elementsd
+                .get_new_segwit_confidential_address()
+                .await
+                .context("failed to get redeem address")?;
+            for (asset, balance) in balances.iter() {
+                match u64::try_from(*balance) {
+                    Ok(0) => {}
+                    Ok(amount) => outputs.push((address.clone(), *asset, Amount::from_sat(amount))),
+                    Err(_) => bail!("not enough of asset {} to settle the batch", asset),
+                }
+            }
+
+            let service_fee_address = match &self.service_fee_address {
+                Some(address) => address.clone(),
+                None if service_fees.is_empty() => address,
+                None => self
+                    .elementsd
+                    .get_new_segwit_confidential_address()
+                    .await
+                    .context("failed to get service fee address")?,
+            };
+
+            let fee = u64::try_from(our_fee(inputs.len()))? + taker_fees;
+            let transaction = Transaction {
+                version: 2,
+                lock_time: 0,
+                input: inputs
+                    .iter()
+                    .map(|(outpoint, _)| TxIn {
+                        previous_output: *outpoint,
+                        is_pegin: false,
+                        has_issuance: false,
+                        script_sig: Default::default(),
+                        sequence: 0xFFFF_FFFF,
+                        asset_issuance: Default::default(),
+                        witness: Default::default(),
+                    })
+                    .collect(),
+                output: outputs
+                    .iter()
+                    .map(|(address, asset, amount)| unblinded_txout(address, *asset, *amount))
+                    .chain(service_fees.iter().map(|(_, (asset, amount))| {
+                        unblinded_txout(&service_fee_address, *asset, *amount)
+                    }))
+                    .chain(std::iter::once(TxOut::new_fee(fee, btc_asset_id)))
+                    .collect(),
+            };
+
+            let secrets = inputs
+                .iter()
+                .map(|(_, secrets)| *secrets)
+                .collect::<Vec<_>>();
+
+            let transaction = self
+                .elementsd
+                .blind_raw_transaction_with_secrets(&transaction, &secrets)
+                .await
+                .context("failed to blind batch transaction")?;
+
+            // We can only reveal the secrets of the service fee
+            // outputs if we can unblind them ourselves
+            let blinding_key = match service_fee_address.blinding_pubkey {
+                Some(_) => Some(
+                    self.elementsd
+                        .dump_blinding_key(&service_fee_address)
+                        .await
+                        .context("service fee address does not belong to our wallet")?,
+                ),
+                None => None,
+            };
+            let mut service_fee_secrets = vec![None; swaps.len()];
+            for (position, (index, (asset, amount))) in service_fees.iter().enumerate() {
+                let txout = &transaction.output[outputs.len() + position];
+                let secrets = match blinding_key {
+                    Some(blinding_key) => txout
+                        .unblind(SECP256K1, blinding_key)
+                        .context("failed to unblind service fee output")?,
+                    None => TxOutSecrets::new(
+                        *asset,
+                        AssetBlindingFactor::zero(),
+                        amount.as_sat(),
+                        ValueBlindingFactor::zero(),
+                    ),
+                };
+                service_fee_secrets[*index] = Some(secrets);
+            }
+
+            Ok((transaction, service_fee_secrets))
+        }
+        .await;
+
+        match transaction {
+            Ok((transaction, service_fee_secrets)) => {
+                Ok((transaction, our_inputs, service_fee_secrets))
+            }
+            Err(e) => {
+                self.elementsd.unlock_utxos(our_inputs).await?;
+
+                Err(e)
+            }
+        }
+    }
+
+    /// Build a batch transaction settling `swaps`, which is handed to
+    /// their takers to sign, and reserve our inputs in it until the
+    /// batch expires.
+    async fn pending_batch(
+        &mut self,
+        swaps: Vec<(PreparedSwap, TakerOutputs)>,
+        fee_rate: Amount,
+    ) -> Result<PendingBatch> {
+        let (transaction, our_inputs, service_fee_secrets) = {
+            let swaps = swaps
+                .iter()
+                .map(|(swap, outputs)| (swap, outputs))
+                .collect::<Vec<_>>();
+
+            self.batch_transaction(&swaps, fee_rate).await?
+        };
+
+        let txid = transaction.txid();
+        let expires_at = unix_timestamp() + self.swap_expiry.as_secs();
+        let reserved = self
+            .db
+            .do_in_transaction(|conn| {
+                for outpoint in our_inputs.iter() {
+                    UtxoReservationForm::new(*outpoint, txid, expires_at)?.insert(conn)?;
+                }
+
+                Ok(())
+            })
+            .await;
+        if let Err(e) = reserved {
+            self.elementsd.unlock_utxos(our_inputs).await?;
+
+            return Err(e.context("failed to reserve our inputs of batch"));
+        }
+
+        let swaps = swaps.into_iter().map(|(swap, _)| swap).collect::<Vec<_>>();
+
+        Ok(PendingBatch {
+            transaction,
+            signed: vec![false; swaps.len()],
+            swaps,
+            service_fee_secrets,
+            our_inputs,
+            expires_at,
+        })
+    }
+
+    /// Build and sign the transaction of a swap which baru cannot
+    /// build, like a batch with a single taker. That is the case if
+    /// the swap pays us a service fee or if Alice only revealed the
+    /// secrets of her inputs.
+    ///
+    /// The secrets of the output paying us the service fee are
+    /// returned together with the transaction.
+    pub(crate) async fn swap_transaction_without_baru(
+        &mut self,
+        swap: PreparedSwap,
+    ) -> Result<(Transaction, Option<TxOutSecrets>)> {
+        let fee_rate = self.fee_rate_service.fee_rate().await?;
+        let taker_outputs = TakerOutputs::new(&swap, fee_rate, self.btc_asset_id)?;
+        let (transaction, our_inputs, service_fee_secrets) = self
+            .batch_transaction(&[(&swap, &taker_outputs)], fee_rate)
+            .await?;
+
+        let transaction = async {
+            let transaction = self.elementsd.sign_raw_transaction(&transaction).await?;
+
+            let created_at = unix_timestamp();
+            let expires_at = created_at + self.swap_expiry.as_secs();
+            self.db
+                .do_in_transaction(|conn| {
+                    record_swaps(
+                        conn,
+                        transaction.txid(),
+                        std::slice::from_ref(&swap),
+                        created_at,
+                        &our_inputs,
+                        expires_at,
+                    )
+                })
+                .await
+                .context("failed to record swap")?;
+
+            Result::<_, anyhow::Error>::Ok(transaction)
+        }
+        .await;
+
+        if transaction.is_err() {
+            if let Err(e) = self.elementsd.unlock_utxos(our_inputs).await {
+                tracing::error!("Failed to unlock inputs of failed swap: {:#}", e);
+            }
+        }
+
+        let service_fee_secrets = service_fee_secrets.into_iter().next().flatten();
+
+        transaction.map(|transaction| (transaction, service_fee_secrets))
+    }
+
+    /// Sign our inputs of a batch transaction which all the takers
+    /// have signed and broadcast it.
+    async fn broadcast_batch(&self, transaction: &Transaction) -> Result<()> {
+        let transaction = self.elementsd.sign_raw_transaction(transaction).await?;
+        self.elementsd.send_raw_transaction(&transaction).await?;
+
+        Ok(())
+    }
+
+    /// Record the swaps of a batch we have broadcast.
+    ///
+    /// Our inputs have been reserved since the batch was built, which
+    /// lets us track its confirmation like any other swap.
+    async fn record_batch(&self, batch: &PendingBatch) -> Result<()> {
+        let txid = batch.transaction.txid();
+        self.db
+            .do_in_transaction(|conn| {
+                record_swaps(
+                    conn,
+                    txid,
+                    &batch.swaps,
+                    unix_timestamp(),
+                    &[],
+                    batch.expires_at,
+                )?;
+                queries::update_swap_status(conn, txid, SwapStatus::Broadcast)
+            })
+            .await?;
+
+        tracing::info!(
+            "Broadcast batch {} settling {} swaps",
+            txid,
+            batch.swaps.len()
+        );
+
+        Ok(())
+    }
+}
+
+/// The outputs of a taker in a batch transaction and their share of
+/// its fee.
+struct TakerOutputs {
+    outputs: Vec<(Address, AssetId, Amount)>,
+    fee: Amount,
+}
+
+impl TakerOutputs {
+    /// Takers who sell L-BTC pay for their own inputs and outputs,
+    /// like in a swap which is not batched. Otherwise we pay.
+    fn new(swap: &PreparedSwap, fee_rate: Amount, btc_asset_id: AssetId) -> Result<Self> {
+        let fee = if swap.taker.0 == btc_asset_id {
+            // A receive and a change output
+            let vbytes = swap.inputs.len() as u64 * avg_vbytes::INPUT + 2 * avg_vbytes::OUTPUT;
+
+            Amount::from_sat(fee_rate.as_sat() * vbytes)
+        } else {
+            Amount::ZERO
+        };
+
+        let change = taker_change(
+            swap.inputs.iter().map(|input| &input.secrets),
+            &[swap.taker, (btc_asset_id, fee)],
+        )?;
+
+        let outputs = std::iter::once(swap.maker)
+            .chain(change)
+            .map(|(asset, amount)| (swap.address.clone(), asset, amount))
+            .collect();
+
+        Ok(Self { outputs, fee })
+    }
+}
+
+/// What is left of the `inputs` of a taker per asset after `spending`
+/// from them.
+fn taker_change<'a>(
+    inputs: impl Iterator<Item = &'a TxOutSecrets>,
+    spending: &[(AssetId, Amount)],
+) -> Result<Vec<(AssetId, Amount)>> {
+    let mut balances = HashMap::<AssetId, u64>::new();
+    for secrets in inputs {
+        *balances.entry(secrets.asset).or_default() += secrets.value;
+    }
+
+    for (asset, amount) in spending.iter().filter(|(_, amount)| *amount > Amount::ZERO) {
+        let balance = balances.get_mut(asset).ok_or(TakerInputsTooSmall)?;
+        *balance = balance
+            .checked_sub(amount.as_sat())
+            .ok_or(TakerInputsTooSmall)?;
+    }
+
+    let mut change = balances
+        .into_iter()
+        .filter(|(_, balance)| *balance > 0)
+        .map(|(asset, balance)| (asset, Amount::from_sat(balance)))
+        .collect::<Vec<_>>();
+    change.sort_by_key(|(asset, _)| asset.to_string());
+
+    Ok(change)
+}
+
+/// Check that `witness` spends the P2WPKH output `txout` as input
+/// `index` of `transaction`, signing all of the transaction.
+fn verify_witness(
+    transaction: &Transaction,
+    index: usize,
+    txout: &TxOut,
+    witness: &[Vec<u8>],
+) -> Result<()> {
+    let (signature, public_key) = match witness {
+        [signature, public_key] => (signature, public_key),
+        _ => bail!("expected a signature and a public key"),
+    };
+
+    let public_key = PublicKey::from_slice(public_key)?;
+    if Address::p2wpkh(&public_key, None, &AddressParams::ELEMENTS).script_pubkey()
+        != txout.script_pubkey
+    {
+        bail!("public key does not match the spent output");
+    }
+
+    let (sighash_type, signature) = signature.split_last().context("empty signature")?;
+    if *sighash_type != SigHashType::All as u8 {
+        bail!("signature does not sign all of the transaction");
+    }
+
+    let script_code = Address::p2pkh(&public_key, None, &AddressParams::ELEMENTS).script_pubkey();
+    let sighash = SigHashCache::new(transaction).segwitv0_sighash(
+        index,
+        &script_code,
+        txout.value,
+        SigHashType::All,
+    );
+    SECP256K1.verify(
+        &Message::from(sighash),
+        &Signature::from_der(signature)?,
+        &public_key.key,
+    )?;
+
+    Ok(())
+}
+
+/// An output which elementsd blinds to the blinding key of `address`,
+/// if it is confidential.
+fn unblinded_txout(address: &Address, asset: AssetId, amount: Amount) -> TxOut {
+    TxOut {
+        asset: Asset::Explicit(asset),
+        value: Value::Explicit(amount.as_sat()),
+        nonce: address
+            .blinding_pubkey
+            .map_or(Nonce::Null, Nonce::Confidential),
+        script_pubkey: address.script_pubkey(),
+        witness: TxOutWitness::default(),
+    }
+}
+
+/// Collect the swap requests arriving within `window` of each other
+/// into a single transaction, which is handed to all their takers.
+///
+/// Batches which are not signed by all of their takers before they
+/// expire are abandoned.
+pub async fn build_batches<R, RS>(
+    bobtimus: Arc<Mutex<Bobtimus<R, RS>>>,
+    batcher: SwapBatcher,
+    requests: BatchRequests,
+    window: Duration,
+) where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    let BatchRequests(mut requests) = requests;
+    let mut expiry_check = tokio::time::interval(window.max(Duration::from_secs(1)));
+
+    loop {
+        let first = tokio::select! {
+            request = requests.recv() => match request {
+                Some(request) => request,
+                None => return,
+            },
+            _ = expiry_check.tick() => {
+                let (elementsd, db) = {
+                    let bobtimus = bobtimus.lock().await;
+                    (bobtimus.elementsd.clone(), bobtimus.db.clone())
+                };
+                if let Err(e) = batcher.abandon_expired_batches(&elementsd, &db).await {
+                    tracing::error!("Failed to abandon expired batches: {:#}", e);
+                }
+
+                continue;
+            }
+        };
+
+        let mut batch = vec![first];
+        let deadline = tokio::time::sleep(window);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(request) => batch.push(request),
+                    None => break,
+                },
+                _ = &mut deadline => break,
+            }
+        }
+
+        let mut bobtimus = bobtimus.lock().await;
+        build_batch(&mut bobtimus, &batcher, batch).await;
+    }
+}
+
+async fn build_batch<R, RS>(
+    bobtimus: &mut Bobtimus<R, RS>,
+    batcher: &SwapBatcher,
+    requests: Vec<BatchRequest>,
+) where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    let fee_rate = match bobtimus.fee_rate_service.fee_rate().await {
+        Ok(fee_rate) => fee_rate,
+        Err(e) => {
+            for request in requests {
+                let _ = request
+                    .response
+                    .send(Err(anyhow::anyhow!("failed to get fee rate: {:#}", e)));
+            }
+
+            return;
+        }
+    };
+
+    // Takers who cannot pay for their share, or whose quote has been
+    // used in the meantime, are left out of the batch
+    let mut accepted = Vec::new();
+    let mut used_quotes = HashSet::new();
+    for request in requests {
+        if let Some(quote_id) = &request.swap.quote_id {
+            if !bobtimus.quotes.contains_key(quote_id) || used_quotes.contains(quote_id) {
+                let _ = request
+                    .response
+                    .send(Err(UnknownQuote(quote_id.clone()).into()));
+                continue;
+            }
+        }
+
+        match TakerOutputs::new(&request.swap, fee_rate, bobtimus.btc_asset_id) {
+            Ok(outputs) => {
+                used_quotes.extend(request.swap.quote_id.clone());
+                accepted.push((request, outputs));
+            }
+            Err(e) => {
+                let _ = request.response.send(Err(e));
+            }
+        }
+    }
+    if accepted.is_empty() {
+        return;
+    }
+
+    let (swaps, responses): (Vec<_>, Vec<_>) = accepted
+        .into_iter()
+        .map(|(request, outputs)| ((request.swap, outputs), request.response))
+        .unzip();
+
+    let batch = match bobtimus.pending_batch(swaps, fee_rate).await {
+        Ok(batch) => batch,
+        Err(e) => {
+            tracing::error!(
+                "Failed to build batch of {} swaps: {:#}",
+                responses.len(),
+                e
+            );
+            for response in responses {
+                let _ = response.send(Err(anyhow::anyhow!("failed to build batch: {:#}", e)));
+            }
+
+            return;
+        }
+    };
+
+    let transaction = batch.transaction.clone();
+    let service_fee_secrets = batch.service_fee_secrets.clone();
+    batcher.add_batch(batch).await;
+    for quote_id in used_quotes {
+        bobtimus.quotes.remove(&quote_id);
+    }
+
+    for (response, secrets) in responses.into_iter().zip(service_fee_secrets) {
+        let _ = response.send(Ok((transaction.clone(), secrets)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        elements_rpc::ElementsRpc,
+        pair::{PairId, LBTC_LUSDT},
+        quote::{Direction, SwapMode},
+        tests::{
+            bobtimus, extract_input, lbtc_lusdt_pair, make_confidential_address, make_keypair,
+        },
+        AliceInput, CreateSwapPayload, LiquidUsdt, UnblindedInput,
+    };
+    use baru::swap::sign_with_key;
+    use elements::secp256k1_zkp::SecretKey;
+    use elements_harness::Elementsd;
+    use std::str::FromStr;
+    use testcontainers::clients::Cli;
+
+    fn asset(hex: &str) -> AssetId {
+        AssetId::from_str(&hex.repeat(32)).unwrap()
+    }
+
+    fn secrets(asset: AssetId, value: u64) -> TxOutSecrets {
+        TxOutSecrets::new(
+            asset,
+            AssetBlindingFactor::zero(),
+            value,
+            ValueBlindingFactor::zero(),
+        )
+    }
+
+    #[test]
+    fn taker_gets_change_per_asset() {
+        let (btc, usdt) = (asset("01"), asset("02"));
+        let inputs = [secrets(btc, 1_000), secrets(btc, 500), secrets(usdt, 300)];
+
+        let change = taker_change(
+            inputs.iter(),
+            &[(btc, Amount::from_sat(1_200)), (btc, Amount::from_sat(100))],
+        )
+        .unwrap();
+
+        assert_eq!(
+            change,
+            vec![(btc, Amount::from_sat(200)), (usdt, Amount::from_sat(300))]
+        );
+    }
+
+    #[test]
+    fn taker_inputs_must_cover_amount_and_fee() {
+        let btc = asset("01");
+        let inputs = [secrets(btc, 1_000)];
+
+        let result = taker_change(
+            inputs.iter(),
+            &[(btc, Amount::from_sat(950)), (btc, Amount::from_sat(100))],
+        );
+
+        assert!(result.unwrap_err().is::<TakerInputsTooSmall>());
+    }
+
+    #[test]
+    fn signatures_of_a_taker_are_merged() {
+        let (alice, alice_sk) = taker_swap(0);
+        let (carol, _) = taker_swap(1);
+        let mut batch = pending_batch(vec![alice, carol]);
+
+        let signed = sign(
+            &batch.transaction,
+            OutPoint::new(Txid::default(), 0),
+            &alice_sk,
+        );
+        batch.add_signatures(&signed).unwrap();
+
+        assert_eq!(batch.signed, vec![true, false]);
+        assert_eq!(batch.missing_signatures(), 1);
+        assert_eq!(batch.transaction.input[0], signed.input[0]);
+    }
+
+    #[test]
+    fn invalid_signatures_are_rejected() {
+        let (alice, _) = taker_swap(0);
+        let (carol, carol_sk) = taker_swap(1);
+        let mut batch = pending_batch(vec![alice, carol]);
+
+        // Carol's key does not match Alice's output
+        let signed = sign(
+            &batch.transaction,
+            OutPoint::new(Txid::default(), 0),
+            &carol_sk,
+        );
+        let result = batch.add_signatures(&signed);
+
+        assert!(result.unwrap_err().is::<InvalidSignature>());
+        assert_eq!(batch.signed, vec![false, false]);
+        assert!(batch.transaction.input[0].witness.script_witness.is_empty());
+    }
+
+    #[test]
+    fn signatures_for_inputs_of_others_are_rejected() {
+        let (alice, alice_sk) = taker_swap(0);
+        let (carol, carol_sk) = taker_swap(1);
+        let mut batch = pending_batch(vec![alice, carol]);
+
+        let both = sign(
+            &sign(
+                &batch.transaction,
+                OutPoint::new(Txid::default(), 0),
+                &alice_sk,
+            ),
+            OutPoint::new(Txid::default(), 1),
+            &carol_sk,
+        );
+        let mut ours = sign(
+            &batch.transaction,
+            OutPoint::new(Txid::default(), 0),
+            &alice_sk,
+        );
+        ours.input[2].witness.script_witness = vec![vec![1, 2, 3]];
+
+        for transaction in [both, ours].iter() {
+            let result = batch.add_signatures(transaction);
+
+            assert!(result.unwrap_err().is::<ForeignSignatures>());
+            assert_eq!(batch.signed, vec![false, false]);
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_is_broadcast_once_every_taker_has_signed() {
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+
+        let seller = Taker::fund(&client, btc_asset_id, Amount::from_btc(2.0).unwrap()).await;
+        let buyer = Taker::fund(&client, usdt_asset_id, Amount::from_btc(30_000.0).unwrap()).await;
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let mut bob = bobtimus(
+            &client,
+            Sqlite::new_ephemeral_db().unwrap(),
+            btc_asset_id,
+            usdt_asset_id,
+            lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+        );
+
+        let sell = bob
+            .prepare_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Sell,
+                seller.payload(Amount::ONE_BTC),
+            )
+            .await
+            .unwrap();
+        let buy = bob
+            .prepare_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Buy,
+                buyer.payload(LiquidUsdt::from_str_in_dollar("20000.0").unwrap().into()),
+            )
+            .await
+            .unwrap();
+
+        let fee_rate = bob.fee_rate_service.fee_rate().await.unwrap();
+        let swaps = vec![sell, buy]
+            .into_iter()
+            .map(|swap| {
+                let outputs = TakerOutputs::new(&swap, fee_rate, btc_asset_id).unwrap();
+
+                (swap, outputs)
+            })
+            .collect();
+        let batch = bob.pending_batch(swaps, fee_rate).await.unwrap();
+        let transaction = batch.transaction.clone();
+        let txid = transaction.txid();
+
+        let reservations = bob
+            .db
+            .do_in_transaction(|conn| queries::get_utxo_reservations(conn))
+            .await
+            .unwrap();
+        assert!(!reservations.is_empty());
+        assert!(reservations
+            .iter()
+            .all(|reservation| reservation.swap_txid == txid));
+
+        let (batcher, _requests) = SwapBatcher::new();
+        batcher.add_batch(batch).await;
+        let bob = Mutex::new(bob);
+
+        let status = batcher
+            .add_signatures(&bob, seller.sign(&transaction))
+            .await
+            .unwrap();
+        assert_eq!(status.missing_signatures, 1);
+
+        let status = batcher
+            .add_signatures(&bob, buyer.sign(&transaction))
+            .await
+            .unwrap();
+        assert_eq!(status.missing_signatures, 0);
+
+        let broadcast = client.get_raw_transaction(txid).await.unwrap();
+        let swaps = bob
+            .lock()
+            .await
+            .db
+            .do_in_transaction(|conn| queries::get_swaps_by_status(conn, SwapStatus::Broadcast))
+            .await
+            .unwrap();
+        assert_eq!(swaps.len(), 2);
+        for swap in swaps {
+            let taker = match swap.direction {
+                Direction::Sell => &seller,
+                Direction::Buy => &buyer,
+            };
+
+            assert_eq!(
+                taker.received(&broadcast, swap.maker_asset_id),
+                vec![swap.maker_amount.as_sat()]
+            );
+        }
+    }
+
+    /// A taker with a single confidential input, who receives to the
+    /// address of that input.
+    struct Taker {
+        outpoint: OutPoint,
+        txout: TxOut,
+        secrets: TxOutSecrets,
+        address: Address,
+        secret_key: SecretKey,
+        blinding_key: SecretKey,
+    }
+
+    impl Taker {
+        async fn fund(client: &Client, asset: AssetId, amount: Amount) -> Self {
+            let (address, secret_key, _, blinding_key, _) = make_confidential_address();
+            let txid = client
+                .send_asset_to_address(&address, amount, Some(asset))
+                .await
+                .unwrap();
+            let (outpoint, txout) = extract_input(
+                &client.get_raw_transaction(txid).await.unwrap(),
+                address.clone(),
+            )
+            .unwrap();
+            let secrets = txout.unblind(SECP256K1, blinding_key).unwrap();
+
+            Self {
+                outpoint,
+                txout,
+                secrets,
+                address,
+                secret_key,
+                blinding_key,
+            }
+        }
+
+        fn payload(&self, amount: Amount) -> CreateSwapPayload {
+            CreateSwapPayload {
+                alice_inputs: vec![AliceInput::Secrets {
+                    outpoint: self.outpoint,
+                    secrets: self.secrets,
+                }],
+                address: self.address.clone(),
+                amount: amount.as_sat(),
+                mode: SwapMode::ExactInput,
+                quote_id: None,
+            }
+        }
+
+        fn sign(&self, transaction: &Transaction) -> Transaction {
+            sign_input(
+                transaction,
+                self.outpoint,
+                self.txout.value,
+                &self.secret_key,
+            )
+        }
+
+        /// The amounts of `asset` the taker receives in `transaction`.
+        fn received(&self, transaction: &Transaction, asset: AssetId) -> Vec<u64> {
+            transaction
+                .output
+                .iter()
+                .filter(|txout| txout.script_pubkey == self.address.script_pubkey())
+                .filter_map(|txout| txout.unblind(SECP256K1, self.blinding_key).ok())
+                .filter(|secrets| secrets.asset == asset)
+                .map(|secrets| secrets.value)
+                .collect()
+        }
+    }
+
+    /// A swap of a taker spending an explicit P2WPKH output at `vout`,
+    /// together with the key which can sign for it.
+    fn taker_swap(vout: u32) -> (PreparedSwap, SecretKey) {
+        let (btc, usdt) = (asset("01"), asset("02"));
+        let (secret_key, public_key) = make_keypair();
+        let address = Address::p2wpkh(&public_key, None, &AddressParams::ELEMENTS);
+
+        let swap = PreparedSwap {
+            direction: Direction::Sell,
+            inputs: vec![UnblindedInput {
+                txin: OutPoint::new(Txid::default(), vout),
+                txout: unblinded_txout(&address, btc, Amount::from_sat(1_000)),
+                secrets: secrets(btc, 1_000),
+                blinding_key: None,
+            }],
+            address,
+            taker: (btc, Amount::from_sat(1_000)),
+            maker: (usdt, Amount::from_sat(20_000)),
+            service_fee: (usdt, Amount::ZERO),
+            rate: LiquidUsdt::from_str_in_dollar("20.0").unwrap(),
+            quote_id: None,
+        };
+
+        (swap, secret_key)
+    }
+
+    /// A batch spending the inputs of the takers of `swaps` and one of
+    /// ours.
+    fn pending_batch(swaps: Vec<PreparedSwap>) -> PendingBatch {
+        let ours = OutPoint::new(Txid::default(), 99);
+        let input = swaps
+            .iter()
+            .flat_map(|swap| swap.inputs.iter().map(|input| input.txin))
+            .chain(std::iter::once(ours))
+            .map(|previous_output| TxIn {
+                previous_output,
+                is_pegin: false,
+                has_issuance: false,
+                script_sig: Default::default(),
+                sequence: 0xFFFF_FFFF,
+                asset_issuance: Default::default(),
+                witness: Default::default(),
+            })
+            .collect();
+
+        PendingBatch {
+            transaction: Transaction {
+                version: 2,
+                lock_time: 0,
+                input,
+                output: vec![TxOut::new_fee(100, asset("01"))],
+            },
+            signed: vec![false; swaps.len()],
+            service_fee_secrets: vec![None; swaps.len()],
+            swaps,
+            our_inputs: vec![ours],
+            expires_at: u64::MAX,
+        }
+    }
+
+    /// Sign the input spending the output of a `taker_swap` at
+    /// `outpoint` in `transaction`.
+    fn sign(transaction: &Transaction, outpoint: OutPoint, secret_key: &SecretKey) -> Transaction {
+        sign_input(transaction, outpoint, Value::Explicit(1_000), secret_key)
+    }
+
+    fn sign_input(
+        transaction: &Transaction,
+        outpoint: OutPoint,
+        value: Value,
+        secret_key: &SecretKey,
+    ) -> Transaction {
+        let mut transaction = transaction.clone();
+        let index = transaction
+            .input
+            .iter()
+            .position(|txin| txin.previous_output == outpoint)
+            .unwrap();
+
+        let witness = {
+            let mut cache = SigHashCache::new(&transaction);
+            sign_with_key(&SECP256K1, &mut cache, index, secret_key, value)
+        };
+        transaction.input[index].witness.script_witness = witness;
+
+        transaction
+    }
+}
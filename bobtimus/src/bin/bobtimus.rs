@@ -1,6 +1,17 @@
 use anyhow::Result;
 use bobtimus::{
-    cli::Config, database::Sqlite, elements_rpc::Client, http, kraken, liquidate_loans, Bobtimus,
+    batch::{build_batches, SwapBatcher},
+    cli::Config,
+    database::{queries, Sqlite},
+    elements_rpc::Client,
+    expire_loan_handshakes,
+    fee_bump::bump_stuck_transactions_continuously,
+    fee_rate, fixed_rate, http,
+    inventory::{self, InventorySkew},
+    kraken, liquidate_loans, liquidate_loans_continuously, loan_book,
+    oracle::{publish_attestations, Oracle},
+    pair::{RateSource, TradingPair},
+    relock_reserved_utxos, track_utxo_reservations, Bobtimus,
 };
 use elements::{
     bitcoin::secp256k1::Secp256k1,
@@ -17,30 +28,140 @@ async fn main() -> Result<()> {
         Config::Start {
             elementsd_url,
             api_port,
+            admin_port,
             usdt_asset_id,
             db_file,
+            loan_handshake_ttl,
+            quote_ttl,
+            swap_expiry,
+            fee_conf_target,
+            min_fee_rate,
+            max_fee_rate,
+            trading_pairs,
+            target_btc_ratio,
+            max_skew,
+            inventory_refresh_interval,
+            liquidation_poll_interval,
+            loan_terms,
+            fee_bump_after,
+            fee_bump_poll_interval,
+            loan_ltv_threshold,
+            attestation_interval,
+            attestation_retention,
+            swap_batch_window,
+            service_fee_address,
         } => {
             let db = Sqlite::new(db_file.as_path())?;
+            let lender_states = db
+                .do_in_transaction(|conn| queries::get_lender_states(conn))
+                .await?;
 
             let elementsd = Client::new(elementsd_url.into())?;
             let btc_asset_id = elementsd.get_bitcoin_asset_id().await?;
 
-            let rate_service = kraken::RateService::new().await?;
-            let subscription = rate_service.subscribe();
+            let fee_rate_service = fee_rate::Service::new(
+                elementsd.clone(),
+                fee_conf_target,
+                min_fee_rate,
+                max_fee_rate,
+            )?;
+
+            relock_reserved_utxos(&elementsd, &db).await?;
+            tokio::spawn(track_utxo_reservations(
+                elementsd.clone(),
+                db.clone(),
+                fee_rate_service.clone(),
+                btc_asset_id,
+                swap_expiry,
+            ));
+            tokio::spawn(liquidate_loans_continuously(
+                elementsd.clone(),
+                db.clone(),
+                liquidation_poll_interval,
+            ));
+            tokio::spawn(bump_stuck_transactions_continuously(
+                elementsd.clone(),
+                db.clone(),
+                fee_rate_service.clone(),
+                btc_asset_id,
+                fee_bump_after,
+                fee_bump_poll_interval,
+            ));
+
+            let mut pairs = HashMap::new();
+            let mut subscriptions = HashMap::new();
+            for config in trading_pairs {
+                let subscription = match &config.rate_source {
+                    RateSource::Kraken(kraken_pair) => {
+                        kraken::RateService::new(kraken_pair).await?.subscribe()
+                    }
+                    RateSource::Fixed { ask, bid } => {
+                        fixed_rate::Service::from_nominal(*ask, *bid)?.subscribe()
+                    }
+                };
+                let skew = InventorySkew::for_pair(&config, target_btc_ratio, max_skew)?;
+                let rate_service = inventory::RateService::new(
+                    subscription,
+                    elementsd.clone(),
+                    config.base_asset_id.unwrap_or(btc_asset_id),
+                    config.quote_asset_id,
+                    skew,
+                    inventory_refresh_interval,
+                );
+
+                subscriptions.insert(config.id.clone(), rate_service.subscribe());
+                pairs.insert(
+                    config.id.clone(),
+                    TradingPair::new(config, btc_asset_id, rate_service)?,
+                );
+            }
+
+            let oracle = Oracle::new(&elementsd, db.clone()).await?;
+            tokio::spawn(publish_attestations(
+                oracle.clone(),
+                subscriptions.clone(),
+                attestation_interval,
+                attestation_retention,
+            ));
 
             let bobtimus = Bobtimus {
                 rng: StdRng::from_rng(&mut thread_rng()).unwrap(),
-                rate_service,
                 secp: Secp256k1::new(),
                 elementsd,
                 btc_asset_id,
                 usdt_asset_id,
+                pairs,
                 db,
-                lender_states: HashMap::new(),
+                lender_states,
+                loan_handshake_ttl,
+                quotes: HashMap::new(),
+                quote_ttl,
+                swap_expiry,
+                fee_rate_service,
+                loan_terms,
+                loan_ltv_threshold,
+                service_fee_address,
             };
             let bobtimus = Arc::new(Mutex::new(bobtimus));
 
-            warp::serve(http::routes(bobtimus, subscription))
+            tokio::spawn(expire_loan_handshakes(bobtimus.clone(), loan_handshake_ttl));
+            tokio::spawn(
+                warp::serve(http::admin_routes(bobtimus.clone())).run(([127, 0, 0, 1], admin_port)),
+            );
+
+            let batcher = swap_batch_window.map(|window| {
+                let (batcher, requests) = SwapBatcher::new();
+                tokio::spawn(build_batches(
+                    bobtimus.clone(),
+                    batcher.clone(),
+                    requests,
+                    window,
+                ));
+
+                batcher
+            });
+
+            warp::serve(http::routes(bobtimus, subscriptions, oracle, batcher))
                 .run(([127, 0, 0, 1], api_port))
                 .await;
         }
@@ -53,6 +174,22 @@ async fn main() -> Result<()> {
 
             liquidate_loans(&elementsd, db).await?;
         }
+        Config::LoanBook {
+            elementsd_url,
+            db_file,
+            kraken_pair,
+            loan_ltv_threshold,
+            json,
+        } => {
+            loan_book::print(
+                elementsd_url,
+                db_file.as_path(),
+                &kraken_pair,
+                loan_ltv_threshold,
+                json,
+            )
+            .await?;
+        }
     }
 
     Ok(())
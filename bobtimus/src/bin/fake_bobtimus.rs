@@ -1,9 +1,15 @@
 use anyhow::Result;
 use bobtimus::{
+    batch::{build_batches, SwapBatcher},
     cli::Config,
-    database::Sqlite,
+    database::{queries, Sqlite},
     elements_rpc::{Client, ElementsRpc},
-    fixed_rate, http, liquidate_loans, Bobtimus, LiquidUsdt,
+    expire_loan_handshakes, fee_rate, fixed_rate, http,
+    inventory::{self, InventorySkew},
+    liquidate_loans, liquidate_loans_continuously, loan_book,
+    oracle::{publish_attestations, Oracle},
+    pair::{RateSource, TradingPair},
+    relock_reserved_utxos, track_utxo_reservations, Bobtimus, LiquidUsdt,
 };
 use elements::{
     bitcoin::{secp256k1::Secp256k1, Amount},
@@ -22,30 +28,131 @@ async fn main() -> Result<()> {
         Config::Start {
             elementsd_url,
             api_port,
+            admin_port,
             usdt_asset_id,
             db_file,
+            loan_handshake_ttl,
+            quote_ttl,
+            swap_expiry,
+            fee_conf_target,
+            min_fee_rate,
+            max_fee_rate,
+            trading_pairs,
+            target_btc_ratio,
+            max_skew,
+            inventory_refresh_interval,
+            liquidation_poll_interval,
+            loan_terms,
+            loan_ltv_threshold,
+            attestation_interval,
+            attestation_retention,
+            swap_batch_window,
+            service_fee_address,
+            ..
         } => {
             let db = Sqlite::new(db_file.as_path())?;
+            let lender_states = db
+                .do_in_transaction(|conn| queries::get_lender_states(conn))
+                .await?;
 
             let elementsd = Client::new(elementsd_url.into())?;
             let btc_asset_id = elementsd.get_bitcoin_asset_id().await?;
 
-            let rate_service = fixed_rate::Service::new();
-            let subscription = rate_service.subscribe();
+            let fee_rate_service = fee_rate::Service::new(
+                elementsd.clone(),
+                fee_conf_target,
+                min_fee_rate,
+                max_fee_rate,
+            )?;
+
+            relock_reserved_utxos(&elementsd, &db).await?;
+            tokio::spawn(track_utxo_reservations(
+                elementsd.clone(),
+                db.clone(),
+                fee_rate_service.clone(),
+                btc_asset_id,
+                swap_expiry,
+            ));
+            tokio::spawn(liquidate_loans_continuously(
+                elementsd.clone(),
+                db.clone(),
+                liquidation_poll_interval,
+            ));
+
+            // Rates are never fetched from an exchange, so that we
+            // can trade without depending on external services
+            let mut pairs = HashMap::new();
+            let mut subscriptions = HashMap::new();
+            for config in trading_pairs {
+                let subscription = match &config.rate_source {
+                    RateSource::Kraken(_) => fixed_rate::Service::new().subscribe(),
+                    RateSource::Fixed { ask, bid } => {
+                        fixed_rate::Service::from_nominal(*ask, *bid)?.subscribe()
+                    }
+                };
+                let skew = InventorySkew::for_pair(&config, target_btc_ratio, max_skew)?;
+                let rate_service = inventory::RateService::new(
+                    subscription,
+                    elementsd.clone(),
+                    config.base_asset_id.unwrap_or(btc_asset_id),
+                    config.quote_asset_id,
+                    skew,
+                    inventory_refresh_interval,
+                );
+
+                subscriptions.insert(config.id.clone(), rate_service.subscribe());
+                pairs.insert(
+                    config.id.clone(),
+                    TradingPair::new(config, btc_asset_id, rate_service)?,
+                );
+            }
+
+            let oracle = Oracle::new(&elementsd, db.clone()).await?;
+            tokio::spawn(publish_attestations(
+                oracle.clone(),
+                subscriptions.clone(),
+                attestation_interval,
+                attestation_retention,
+            ));
 
             let bobtimus = Bobtimus {
                 rng: StdRng::from_rng(&mut thread_rng()).unwrap(),
-                rate_service,
                 secp: Secp256k1::new(),
                 elementsd,
                 btc_asset_id,
                 usdt_asset_id,
+                pairs,
                 db,
-                lender_states: HashMap::new(),
+                lender_states,
+                loan_handshake_ttl,
+                quotes: HashMap::new(),
+                quote_ttl,
+                swap_expiry,
+                fee_rate_service,
+                loan_terms,
+                loan_ltv_threshold,
+                service_fee_address,
             };
             let bobtimus = Arc::new(Mutex::new(bobtimus));
 
-            let routes = http::routes(bobtimus.clone(), subscription);
+            tokio::spawn(expire_loan_handshakes(bobtimus.clone(), loan_handshake_ttl));
+            tokio::spawn(
+                warp::serve(http::admin_routes(bobtimus.clone())).run(([127, 0, 0, 1], admin_port)),
+            );
+
+            let batcher = swap_batch_window.map(|window| {
+                let (batcher, requests) = SwapBatcher::new();
+                tokio::spawn(build_batches(
+                    bobtimus.clone(),
+                    batcher.clone(),
+                    requests,
+                    window,
+                ));
+
+                batcher
+            });
+
+            let routes = http::routes(bobtimus.clone(), subscriptions, oracle, batcher);
 
             let cors = warp::cors().allow_any_origin();
 
@@ -72,6 +179,22 @@ async fn main() -> Result<()> {
 
             liquidate_loans(&elementsd, db).await?;
         }
+        Config::LoanBook {
+            elementsd_url,
+            db_file,
+            kraken_pair,
+            loan_ltv_threshold,
+            json,
+        } => {
+            loan_book::print(
+                elementsd_url,
+                db_file.as_path(),
+                &kraken_pair,
+                loan_ltv_threshold,
+                json,
+            )
+            .await?;
+        }
     };
 
     Ok(())
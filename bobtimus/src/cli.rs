@@ -1,11 +1,22 @@
-use crate::USDT_ASSET_ID;
-use anyhow::{Context, Result};
+use crate::{
+    limits::{Limits, TradeLimits},
+    pair::{PairId, RateSource, TradingPairConfig, LBTC_LUSDT},
+    pricing_models::{LoanTerms, RiskAppetite},
+    USDT_ASSET_ID,
+};
+use anyhow::{bail, Context, Result};
 use directories::ProjectDirs;
-use elements::AssetId;
+use elements::{bitcoin::Amount, Address, AssetId};
 use reqwest::Url;
-use std::path::PathBuf;
+use rust_decimal::Decimal;
+use std::{collections::HashSet, fs, path::PathBuf, time::Duration};
 use structopt::StructOpt;
 
+/// The lowest loan-to-value ratio we are willing to offer for a loan
+/// term. Anything lower means that the simulated volatility over the
+/// term is too high for lending to be worthwhile.
+const MIN_LOAN_LTV: f64 = 0.1;
+
 #[derive(structopt::StructOpt, Debug)]
 #[structopt(name = "bobtimus", about = "Auto-trader for L-BTC/L-USDt")]
 pub enum Command {
@@ -14,6 +25,10 @@ pub enum Command {
         elementsd_url: Url,
         #[structopt(default_value = "3030")]
         api_port: u16,
+        /// Port on which the admin API, e.g. the loan book, is served.
+        /// It is only bound to localhost and must not be exposed.
+        #[structopt(default_value = "3031", long = "admin-port")]
+        admin_port: u16,
         #[structopt(
         default_value = USDT_ASSET_ID,
         long = "usdt"
@@ -21,6 +36,108 @@ pub enum Command {
         usdt_asset_id: AssetId,
         #[structopt(short, parse(from_os_str))]
         db_file: Option<PathBuf>,
+        /// Number of seconds a borrower has to finalize a loan after
+        /// we respond to their loan request.
+        #[structopt(default_value = "60", long = "loan-handshake-ttl")]
+        loan_handshake_ttl_secs: u64,
+        /// Number of seconds for which a quote can be used to create
+        /// a swap.
+        #[structopt(default_value = "30", long = "quote-ttl")]
+        quote_ttl_secs: u64,
+        /// Number of seconds after which we invalidate a swap
+        /// transaction if the taker has not broadcast it.
+        #[structopt(default_value = "60", long = "swap-expiry")]
+        swap_expiry_secs: u64,
+        /// Number of blocks within which we want our transactions to
+        /// be confirmed.
+        #[structopt(default_value = "1", long = "fee-conf-target")]
+        fee_conf_target: u16,
+        /// Lowest fee rate we use for our transactions, in sat/vB.
+        #[structopt(default_value = "1", long = "min-fee-rate")]
+        min_fee_rate: u64,
+        /// Highest fee rate we use for our transactions, in sat/vB.
+        #[structopt(default_value = "50", long = "max-fee-rate")]
+        max_fee_rate: u64,
+        /// JSON file with the list of trading pairs we offer. If not
+        /// set, we only offer L-BTC/L-USDt, configured with the other
+        /// options.
+        #[structopt(long = "trading-pairs", parse(from_os_str))]
+        trading_pairs_file: Option<PathBuf>,
+        /// Smallest amount of L-USDt, in satodollar, a taker can buy
+        /// L-BTC with. If not set, there is no minimum.
+        #[structopt(long = "min-buy-amount")]
+        min_buy_amount: Option<u64>,
+        /// Largest amount of L-USDt, in satodollar, a taker can buy
+        /// L-BTC with. If not set, there is no maximum.
+        #[structopt(long = "max-buy-amount")]
+        max_buy_amount: Option<u64>,
+        /// Smallest amount of L-BTC, in satoshi, a taker can sell. If
+        /// not set, there is no minimum.
+        #[structopt(long = "min-sell-amount")]
+        min_sell_amount: Option<u64>,
+        /// Largest amount of L-BTC, in satoshi, a taker can sell. If
+        /// not set, there is no maximum.
+        #[structopt(long = "max-sell-amount")]
+        max_sell_amount: Option<u64>,
+        /// Share of our inventory's value which we aim to hold in
+        /// the base asset of a trading pair, e.g. L-BTC, unless the
+        /// pair sets its own target.
+        #[structopt(default_value = "0.5", long = "target-btc-ratio")]
+        target_btc_ratio: Decimal,
+        /// Maximum relative amount by which the rate is skewed when our
+        /// inventory is lopsided. Set to 0 to disable skewing.
+        #[structopt(default_value = "0", long = "max-skew")]
+        max_skew: Decimal,
+        /// Number of seconds between updates of our inventory.
+        #[structopt(default_value = "30", long = "inventory-refresh-interval")]
+        inventory_refresh_interval_secs: u64,
+        /// Maximum number of seconds between checks for loans to
+        /// liquidate, in case we miss a new block.
+        #[structopt(default_value = "60", long = "liquidation-poll-interval")]
+        liquidation_poll_interval_secs: u64,
+        /// How much risk we are willing to take when lending, one of
+        /// `low`, `moderate` or `high`.
+        #[structopt(default_value = "moderate", long = "loan-risk-appetite")]
+        loan_risk_appetite: RiskAppetite,
+        /// Comma-separated loan durations, in days, borrowers can
+        /// choose from. Each one is priced separately.
+        #[structopt(default_value = "7,30,90", long = "loan-terms", use_delimiter = true)]
+        loan_term_days: Vec<u32>,
+        /// Expected daily volatility of the price of L-BTC, used to
+        /// derive the loan-to-value ratio and interest rate we offer.
+        #[structopt(default_value = "0.046", long = "loan-daily-volatility")]
+        loan_daily_volatility: f64,
+        /// Number of seconds one of our transactions can stay
+        /// unconfirmed before we bump its fee with a child
+        /// transaction.
+        #[structopt(default_value = "600", long = "fee-bump-after")]
+        fee_bump_after_secs: u64,
+        /// Number of seconds between checks for transactions whose
+        /// fee needs to be bumped.
+        #[structopt(default_value = "60", long = "fee-bump-poll-interval")]
+        fee_bump_poll_interval_secs: u64,
+        /// Loan-to-value ratio above which open loans are flagged in
+        /// the loan book.
+        #[structopt(default_value = "0.9", long = "loan-ltv-threshold")]
+        loan_ltv_threshold: f64,
+        /// Number of seconds between the signed price attestations we
+        /// publish for each trading pair.
+        #[structopt(default_value = "60", long = "attestation-interval")]
+        attestation_interval_secs: u64,
+        /// Number of seconds for which published price attestations
+        /// are kept before they are deleted.
+        #[structopt(default_value = "2592000", long = "attestation-retention")]
+        attestation_retention_secs: u64,
+        /// Number of seconds during which batched swap requests are
+        /// collected into a single transaction. If not set, swaps
+        /// cannot be batched.
+        #[structopt(long = "swap-batch-window")]
+        swap_batch_window_secs: Option<u64>,
+        /// Address to which the service fees of swaps are paid. If not
+        /// set, each swap pays to a new address of our wallet. A
+        /// confidential address must belong to our wallet.
+        #[structopt(long = "service-fee-address")]
+        service_fee_address: Option<Address>,
     },
     LiquidateLoans {
         #[structopt(default_value = "http://127.0.0.1:7042", long = "elementsd")]
@@ -28,19 +145,62 @@ pub enum Command {
         #[structopt(short, parse(from_os_str))]
         db_file: Option<PathBuf>,
     },
+    /// Print our open loans, valued at the current Kraken rate.
+    LoanBook {
+        #[structopt(default_value = "http://127.0.0.1:7042", long = "elementsd")]
+        elementsd_url: Url,
+        #[structopt(short, parse(from_os_str))]
+        db_file: Option<PathBuf>,
+        /// Kraken ticker used to value the loans.
+        #[structopt(default_value = "XBT/USD", long = "kraken-pair")]
+        kraken_pair: String,
+        /// Loan-to-value ratio above which open loans are flagged.
+        #[structopt(default_value = "0.9", long = "loan-ltv-threshold")]
+        loan_ltv_threshold: f64,
+        /// Print the loan book as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
 }
 
 pub enum Config {
     Start {
         elementsd_url: Url,
         api_port: u16,
+        admin_port: u16,
         usdt_asset_id: AssetId,
         db_file: PathBuf,
+        loan_handshake_ttl: Duration,
+        quote_ttl: Duration,
+        swap_expiry: Duration,
+        fee_conf_target: u16,
+        min_fee_rate: Amount,
+        max_fee_rate: Amount,
+        trading_pairs: Vec<TradingPairConfig>,
+        target_btc_ratio: Decimal,
+        max_skew: Decimal,
+        inventory_refresh_interval: Duration,
+        liquidation_poll_interval: Duration,
+        loan_terms: Vec<LoanTerms>,
+        fee_bump_after: Duration,
+        fee_bump_poll_interval: Duration,
+        loan_ltv_threshold: f64,
+        attestation_interval: Duration,
+        attestation_retention: Duration,
+        swap_batch_window: Option<Duration>,
+        service_fee_address: Option<Address>,
     },
     LiquidateLoans {
         elementsd_url: Url,
         db_file: PathBuf,
     },
+    LoanBook {
+        elementsd_url: Url,
+        db_file: PathBuf,
+        kraken_pair: String,
+        loan_ltv_threshold: f64,
+        json: bool,
+    },
 }
 
 impl Config {
@@ -49,13 +209,85 @@ impl Config {
             Command::Start {
                 elementsd_url,
                 api_port,
+                admin_port,
                 usdt_asset_id,
                 db_file,
+                loan_handshake_ttl_secs,
+                quote_ttl_secs,
+                swap_expiry_secs,
+                fee_conf_target,
+                min_fee_rate,
+                max_fee_rate,
+                trading_pairs_file,
+                min_buy_amount,
+                max_buy_amount,
+                min_sell_amount,
+                max_sell_amount,
+                target_btc_ratio,
+                max_skew,
+                inventory_refresh_interval_secs,
+                liquidation_poll_interval_secs,
+                loan_risk_appetite,
+                loan_term_days,
+                loan_daily_volatility,
+                fee_bump_after_secs,
+                fee_bump_poll_interval_secs,
+                loan_ltv_threshold,
+                attestation_interval_secs,
+                attestation_retention_secs,
+                swap_batch_window_secs,
+                service_fee_address,
             } => Config::Start {
                 elementsd_url,
                 api_port,
+                admin_port,
                 usdt_asset_id,
                 db_file: resolve_db_file(db_file)?,
+                loan_handshake_ttl: Duration::from_secs(loan_handshake_ttl_secs),
+                quote_ttl: Duration::from_secs(quote_ttl_secs),
+                swap_expiry: Duration::from_secs(swap_expiry_secs),
+                fee_conf_target,
+                min_fee_rate: Amount::from_sat(min_fee_rate),
+                max_fee_rate: Amount::from_sat(max_fee_rate),
+                trading_pairs: match trading_pairs_file {
+                    Some(file) => read_trading_pairs(file)?,
+                    None => vec![TradingPairConfig {
+                        id: PairId::new(LBTC_LUSDT),
+                        base_asset_id: None,
+                        quote_asset_id: usdt_asset_id,
+                        base_precision: 8,
+                        quote_precision: 8,
+                        rate_source: RateSource::Kraken("XBT/USD".to_string()),
+                        limits: TradeLimits {
+                            buy: Limits::new(
+                                min_buy_amount.unwrap_or(0),
+                                max_buy_amount.unwrap_or(u64::MAX),
+                            )?,
+                            sell: Limits::new(
+                                min_sell_amount.unwrap_or(0),
+                                max_sell_amount.unwrap_or(u64::MAX),
+                            )?,
+                        },
+                        service_fee: None,
+                        target_base_ratio: None,
+                    }],
+                },
+                target_btc_ratio,
+                max_skew,
+                inventory_refresh_interval: Duration::from_secs(inventory_refresh_interval_secs),
+                liquidation_poll_interval: Duration::from_secs(liquidation_poll_interval_secs),
+                loan_terms: suggest_loan_terms(
+                    loan_risk_appetite,
+                    loan_term_days,
+                    loan_daily_volatility,
+                )?,
+                fee_bump_after: Duration::from_secs(fee_bump_after_secs),
+                fee_bump_poll_interval: Duration::from_secs(fee_bump_poll_interval_secs),
+                loan_ltv_threshold: validate_ltv_threshold(loan_ltv_threshold)?,
+                attestation_interval: Duration::from_secs(attestation_interval_secs),
+                attestation_retention: Duration::from_secs(attestation_retention_secs),
+                swap_batch_window: swap_batch_window_secs.map(Duration::from_secs),
+                service_fee_address,
             },
             Command::LiquidateLoans {
                 elementsd_url,
@@ -64,12 +296,84 @@ impl Config {
                 elementsd_url,
                 db_file: resolve_db_file(db_file)?,
             },
+            Command::LoanBook {
+                elementsd_url,
+                db_file,
+                kraken_pair,
+                loan_ltv_threshold,
+                json,
+            } => Config::LoanBook {
+                elementsd_url,
+                db_file: resolve_db_file(db_file)?,
+                kraken_pair,
+                loan_ltv_threshold: validate_ltv_threshold(loan_ltv_threshold)?,
+                json,
+            },
         };
 
         Ok(config)
     }
 }
 
+fn suggest_loan_terms(
+    risk_appetite: RiskAppetite,
+    term_days: Vec<u32>,
+    daily_volatility: f64,
+) -> Result<Vec<LoanTerms>> {
+    if term_days.is_empty() {
+        bail!("at least one loan term must be offered")
+    }
+
+    let mut unique_term_days = HashSet::new();
+    term_days
+        .into_iter()
+        .map(|days| {
+            if !unique_term_days.insert(days) {
+                bail!("loan term of {} days is configured more than once", days)
+            }
+
+            let terms = LoanTerms::suggest(risk_appetite, days, daily_volatility)?;
+            if terms.max_ltv < MIN_LOAN_LTV {
+                bail!(
+                    "loan term of {} days only allows a loan-to-value ratio of {}, below the minimum of {}",
+                    days,
+                    terms.max_ltv,
+                    MIN_LOAN_LTV
+                )
+            }
+
+            Ok(terms)
+        })
+        .collect()
+}
+
+fn validate_ltv_threshold(ltv_threshold: f64) -> Result<f64> {
+    if ltv_threshold.is_nan() || ltv_threshold <= 0.0 {
+        bail!(
+            "loan-to-value threshold must be positive, got {}",
+            ltv_threshold
+        )
+    }
+
+    Ok(ltv_threshold)
+}
+
+fn read_trading_pairs(file: PathBuf) -> Result<Vec<TradingPairConfig>> {
+    let content = fs::read_to_string(&file)
+        .with_context(|| format!("failed to read trading pairs from {}", file.display()))?;
+    let trading_pairs = serde_json::from_str::<Vec<TradingPairConfig>>(&content)
+        .with_context(|| format!("invalid trading pairs in {}", file.display()))?;
+
+    let mut ids = HashSet::new();
+    for pair in trading_pairs.iter() {
+        if !ids.insert(&pair.id) {
+            bail!("trading pair {} is configured more than once", pair.id)
+        }
+    }
+
+    Ok(trading_pairs)
+}
+
 fn resolve_db_file(db_file: Option<PathBuf>) -> Result<PathBuf, anyhow::Error> {
     Ok(match db_file {
         None => {
@@ -1,11 +1,23 @@
-use std::{convert::TryFrom, path::Path, sync::Arc};
+use std::{convert::TryFrom, fmt, path::Path, str::FromStr, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use diesel::{prelude::*, Connection, SqliteConnection};
-use elements::{encode::serialize_hex, Transaction, Txid};
+use elements::{
+    bitcoin::Amount, encode::serialize_hex, Address, AssetId, OutPoint, Transaction, Txid,
+};
+use serde::Serialize;
 use tokio::sync::Mutex;
 
-use crate::schema::liquidations;
+use crate::{
+    oracle::Attestation,
+    pair::PairId,
+    quote::Direction,
+    schema::{
+        fee_bumps, lender_states, liquidation_history, liquidations, oracle_keys,
+        price_attestations, swaps, utxo_reservations,
+    },
+    LiquidUsdt, PendingLoan,
+};
 
 embed_migrations!("./migrations");
 
@@ -72,19 +84,31 @@ pub struct LiquidationForm {
     id: String,
     tx_hex: String,
     locktime: i64,
+    status: String,
+    collateral_amount: i64,
+    principal_amount: i64,
 }
 
 impl LiquidationForm {
-    pub fn new(loan_txid: Txid, liquidation_tx: &Transaction, locktime: u32) -> Self {
+    pub fn new(
+        loan_txid: Txid,
+        liquidation_tx: &Transaction,
+        locktime: u32,
+        collateral_amount: Amount,
+        principal_amount: Amount,
+    ) -> Result<Self> {
         let id = loan_txid.to_string();
         let tx_hex = serialize_hex(liquidation_tx);
         let locktime = i64::try_from(locktime).expect("every u32 fits into a i64");
 
-        Self {
+        Ok(Self {
             id,
             tx_hex,
             locktime,
-        }
+            status: LiquidationStatus::Pending.to_string(),
+            collateral_amount: i64::try_from(collateral_amount.as_sat())?,
+            principal_amount: i64::try_from(principal_amount.as_sat())?,
+        })
     }
 
     pub fn insert(self, conn: &SqliteConnection) -> Result<()> {
@@ -96,39 +120,826 @@ impl LiquidationForm {
     }
 }
 
+#[derive(Insertable)]
+#[table_name = "lender_states"]
+pub struct LenderStateForm {
+    id: String,
+    state: String,
+    created_at: i64,
+    collateral_amount: i64,
+    principal_amount: i64,
+}
+
+impl LenderStateForm {
+    pub fn new(loan_txid: Txid, pending_loan: &PendingLoan) -> Result<Self> {
+        let id = loan_txid.to_string();
+        let state = serde_json::to_string(&pending_loan.lender)?;
+        let created_at = i64::try_from(pending_loan.created_at)?;
+
+        Ok(Self {
+            id,
+            state,
+            created_at,
+            collateral_amount: i64::try_from(pending_loan.collateral_amount.as_sat())?,
+            principal_amount: i64::try_from(pending_loan.principal_amount.as_sat())?,
+        })
+    }
+
+    pub fn insert(self, conn: &SqliteConnection) -> Result<()> {
+        diesel::insert_into(lender_states::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// The state of the liquidation of a loan we have made.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiquidationStatus {
+    /// The loan has not been repaid and the liquidation transaction
+    /// has not been broadcast yet.
+    Pending,
+    /// The borrower spent the collateral before we liquidated it.
+    Repaid,
+    /// We have broadcast the liquidation transaction.
+    Broadcast,
+    /// The liquidation transaction has been included in a block.
+    Confirmed,
+    /// Our last attempt to broadcast the liquidation transaction
+    /// failed. It is retried on the next run.
+    Failed,
+}
+
+impl LiquidationStatus {
+    /// Whether the loan is settled and needs no more attention.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            LiquidationStatus::Repaid | LiquidationStatus::Confirmed
+        )
+    }
+}
+
+impl fmt::Display for LiquidationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiquidationStatus::Pending => write!(f, "pending"),
+            LiquidationStatus::Repaid => write!(f, "repaid"),
+            LiquidationStatus::Broadcast => write!(f, "broadcast"),
+            LiquidationStatus::Confirmed => write!(f, "confirmed"),
+            LiquidationStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl FromStr for LiquidationStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(LiquidationStatus::Pending),
+            "repaid" => Ok(LiquidationStatus::Repaid),
+            "broadcast" => Ok(LiquidationStatus::Broadcast),
+            "confirmed" => Ok(LiquidationStatus::Confirmed),
+            "failed" => Ok(LiquidationStatus::Failed),
+            _ => bail!("unknown liquidation status {}", s),
+        }
+    }
+}
+
+/// A loan we have made, as recorded for its liquidation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Liquidation {
+    pub loan_txid: Txid,
+    pub transaction: Transaction,
+    pub locktime: u32,
+    pub status: LiquidationStatus,
+    /// Amount of the asset the borrower locked up, in its smallest
+    /// unit.
+    pub collateral_amount: Amount,
+    /// Amount of the asset we lent out, in its smallest unit.
+    pub principal_amount: Amount,
+}
+
+impl Liquidation {
+    /// The collateral output of the loan, which is spent either by
+    /// the borrower's repayment or by our liquidation transaction.
+    pub fn collateral_outpoint(&self) -> Result<OutPoint> {
+        self.transaction
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .find(|outpoint| outpoint.txid == self.loan_txid)
+            .with_context(|| {
+                format!(
+                    "liquidation transaction does not spend loan {}",
+                    self.loan_txid
+                )
+            })
+    }
+}
+
+/// The state of a swap transaction which we have signed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwapStatus {
+    /// We have signed our inputs and handed the transaction over to
+    /// the taker.
+    Created,
+    /// The transaction has been seen spending our inputs.
+    Broadcast,
+    /// The transaction has been included in a block.
+    Confirmed,
+    /// The transaction was not broadcast before the reservation of
+    /// our inputs expired, so we spent one of them to make sure it
+    /// can never be.
+    Invalidated,
+}
+
+impl fmt::Display for SwapStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwapStatus::Created => write!(f, "created"),
+            SwapStatus::Broadcast => write!(f, "broadcast"),
+            SwapStatus::Confirmed => write!(f, "confirmed"),
+            SwapStatus::Invalidated => write!(f, "invalidated"),
+        }
+    }
+}
+
+impl FromStr for SwapStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "created" => Ok(SwapStatus::Created),
+            "broadcast" => Ok(SwapStatus::Broadcast),
+            "confirmed" => Ok(SwapStatus::Confirmed),
+            "invalidated" => Ok(SwapStatus::Invalidated),
+            _ => bail!("unknown swap status {}", s),
+        }
+    }
+}
+
+/// A swap as recorded in our trade ledger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swap {
+    pub txid: Txid,
+    pub direction: Direction,
+    pub taker_asset_id: AssetId,
+    pub taker_amount: Amount,
+    pub maker_asset_id: AssetId,
+    pub maker_amount: Amount,
+    /// The service fee we charged on top of the spread, in the quote
+    /// asset of the trading pair.
+    pub service_fee: Amount,
+    /// The price per L-BTC applied to the swap.
+    pub rate: LiquidUsdt,
+    pub taker_address: Address,
+    /// Seconds since the UNIX epoch at which we signed the swap
+    /// transaction.
+    pub created_at: u64,
+    pub status: SwapStatus,
+    /// The transaction with which we spent one of our inputs, if the
+    /// swap was invalidated. Unknown for swaps whose inputs were
+    /// released without being spent.
+    pub invalidation_txid: Option<Txid>,
+    /// The position of the taker among the takers of a batch swap
+    /// transaction, zero if the swap was not batched.
+    pub batch_index: u32,
+}
+
+#[derive(Insertable)]
+#[table_name = "swaps"]
+pub struct SwapForm {
+    txid: String,
+    direction: String,
+    taker_asset_id: String,
+    taker_amount: i64,
+    maker_asset_id: String,
+    maker_amount: i64,
+    service_fee: i64,
+    rate: i64,
+    taker_address: String,
+    created_at: i64,
+    status: String,
+    batch_index: i32,
+}
+
+impl SwapForm {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        txid: Txid,
+        direction: Direction,
+        (taker_asset_id, taker_amount): (AssetId, Amount),
+        (maker_asset_id, maker_amount): (AssetId, Amount),
+        service_fee: Amount,
+        rate: LiquidUsdt,
+        taker_address: &Address,
+        created_at: u64,
+        batch_index: u32,
+    ) -> Result<Self> {
+        Ok(Self {
+            txid: txid.to_string(),
+            direction: direction.to_string(),
+            taker_asset_id: taker_asset_id.to_string(),
+            taker_amount: i64::try_from(taker_amount.as_sat())?,
+            maker_asset_id: maker_asset_id.to_string(),
+            maker_amount: i64::try_from(maker_amount.as_sat())?,
+            service_fee: i64::try_from(service_fee.as_sat())?,
+            rate: i64::try_from(rate.as_satodollar())?,
+            taker_address: taker_address.to_string(),
+            created_at: i64::try_from(created_at)?,
+            status: SwapStatus::Created.to_string(),
+            batch_index: i32::try_from(batch_index)?,
+        })
+    }
+
+    pub fn insert(self, conn: &SqliteConnection) -> Result<()> {
+        diesel::insert_into(swaps::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// A maker UTXO which is locked because it is used in a swap
+/// transaction we have handed out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UtxoReservation {
+    pub outpoint: OutPoint,
+    pub swap_txid: Txid,
+    /// Seconds since the UNIX epoch after which the swap transaction
+    /// is invalidated if it has not been broadcast.
+    pub expires_at: u64,
+}
+
+#[derive(Insertable)]
+#[table_name = "utxo_reservations"]
+pub struct UtxoReservationForm {
+    txid: String,
+    vout: i32,
+    swap_txid: String,
+    expires_at: i64,
+}
+
+impl UtxoReservationForm {
+    pub fn new(outpoint: OutPoint, swap_txid: Txid, expires_at: u64) -> Result<Self> {
+        Ok(Self {
+            txid: outpoint.txid.to_string(),
+            vout: i32::try_from(outpoint.vout)?,
+            swap_txid: swap_txid.to_string(),
+            expires_at: i64::try_from(expires_at)?,
+        })
+    }
+
+    pub fn insert(self, conn: &SqliteConnection) -> Result<()> {
+        diesel::insert_into(utxo_reservations::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// A child transaction we have broadcast to speed up the confirmation
+/// of one of our transactions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeBump {
+    pub child_txid: Txid,
+    pub parent_txid: Txid,
+    /// The fee paid by the child transaction.
+    pub fee: Amount,
+    /// Seconds since the UNIX epoch at which we broadcast the child
+    /// transaction.
+    pub created_at: u64,
+}
+
+#[derive(Insertable)]
+#[table_name = "fee_bumps"]
+pub struct FeeBumpForm {
+    child_txid: String,
+    parent_txid: String,
+    fee: i64,
+    created_at: i64,
+}
+
+impl FeeBumpForm {
+    pub fn new(child_txid: Txid, parent_txid: Txid, fee: Amount, created_at: u64) -> Result<Self> {
+        Ok(Self {
+            child_txid: child_txid.to_string(),
+            parent_txid: parent_txid.to_string(),
+            fee: i64::try_from(fee.as_sat())?,
+            created_at: i64::try_from(created_at)?,
+        })
+    }
+
+    pub fn insert(self, conn: &SqliteConnection) -> Result<()> {
+        diesel::insert_into(fee_bumps::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "oracle_keys"]
+pub struct OracleKeyForm {
+    address: String,
+}
+
+impl OracleKeyForm {
+    pub fn new(address: &Address) -> Self {
+        Self {
+            address: address.to_string(),
+        }
+    }
+
+    pub fn insert(self, conn: &SqliteConnection) -> Result<()> {
+        diesel::insert_into(oracle_keys::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "price_attestations"]
+pub struct PriceAttestationForm {
+    sequence: i64,
+    pair: String,
+    ask: i64,
+    bid: i64,
+    timestamp: i64,
+    signature: String,
+}
+
+impl PriceAttestationForm {
+    pub fn new(attestation: &Attestation) -> Result<Self> {
+        Ok(Self {
+            sequence: i64::try_from(attestation.sequence)?,
+            pair: attestation.pair.to_string(),
+            ask: i64::try_from(attestation.ask.as_satodollar())?,
+            bid: i64::try_from(attestation.bid.as_satodollar())?,
+            timestamp: i64::try_from(attestation.timestamp)?,
+            signature: attestation.signature.to_string(),
+        })
+    }
+
+    pub fn insert(self, conn: &SqliteConnection) -> Result<()> {
+        diesel::insert_into(price_attestations::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
 pub mod queries {
     use super::*;
 
-    use elements::encode::deserialize;
+    use elements::{encode::deserialize, secp256k1_zkp::Signature};
+    use std::collections::HashMap;
 
     #[derive(Associations, Clone, Debug, Queryable, PartialEq)]
     #[table_name = "liquidations"]
-    struct Liquidation {
+    struct LiquidationRow {
         id: String,
         tx_hex: String,
         locktime: i64,
+        status: String,
+        collateral_amount: i64,
+        principal_amount: i64,
+    }
+
+    impl LiquidationRow {
+        fn into_liquidation(self) -> Result<Liquidation> {
+            Ok(Liquidation {
+                loan_txid: Txid::from_str(&self.id)?,
+                transaction: deserialize(&hex::decode(self.tx_hex)?)?,
+                locktime: u32::try_from(self.locktime)?,
+                status: LiquidationStatus::from_str(&self.status)?,
+                collateral_amount: Amount::from_sat(u64::try_from(self.collateral_amount)?),
+                principal_amount: Amount::from_sat(u64::try_from(self.principal_amount)?),
+            })
+        }
+    }
+
+    /// All the liquidations which are not settled yet.
+    ///
+    /// Settled liquidations are moved to the history by
+    /// [`settle_liquidation`].
+    pub fn get_active_liquidations(conn: &SqliteConnection) -> Result<Vec<Liquidation>> {
+        let rows = liquidations::table.get_results::<LiquidationRow>(conn)?;
+
+        rows.into_iter()
+            .map(LiquidationRow::into_liquidation)
+            .collect()
     }
 
-    pub fn get_publishable_liquidations_txs(
+    /// Move the liquidation of a loan which has been repaid or
+    /// liquidated into the history.
+    pub fn settle_liquidation(
         conn: &SqliteConnection,
-        blockcount: u32,
-    ) -> Result<Vec<Transaction>> {
-        let txs = liquidations::table
-            .filter(liquidations::locktime.le(blockcount as i64))
-            .get_results::<Liquidation>(conn)?;
+        loan_txid: Txid,
+        status: LiquidationStatus,
+        settled_at: u64,
+    ) -> Result<()> {
+        if !status.is_final() {
+            bail!("liquidation with status {} is not settled", status)
+        }
+
+        let id = loan_txid.to_string();
+        let row = liquidations::table
+            .filter(liquidations::id.eq(&id))
+            .first::<LiquidationRow>(conn)?;
+
+        diesel::insert_into(liquidation_history::table)
+            .values((
+                liquidation_history::id.eq(row.id),
+                liquidation_history::tx_hex.eq(row.tx_hex),
+                liquidation_history::locktime.eq(row.locktime),
+                liquidation_history::status.eq(status.to_string()),
+                liquidation_history::collateral_amount.eq(row.collateral_amount),
+                liquidation_history::principal_amount.eq(row.principal_amount),
+                liquidation_history::settled_at.eq(i64::try_from(settled_at)?),
+            ))
+            .execute(conn)?;
+        diesel::delete(liquidations::table.filter(liquidations::id.eq(id))).execute(conn)?;
+
+        Ok(())
+    }
+
+    /// The liquidations of all the loans which have been repaid or
+    /// liquidated.
+    pub fn get_liquidation_history(conn: &SqliteConnection) -> Result<Vec<Liquidation>> {
+        let rows = liquidation_history::table
+            .select((
+                liquidation_history::id,
+                liquidation_history::tx_hex,
+                liquidation_history::locktime,
+                liquidation_history::status,
+                liquidation_history::collateral_amount,
+                liquidation_history::principal_amount,
+            ))
+            .get_results::<LiquidationRow>(conn)?;
+
+        rows.into_iter()
+            .map(LiquidationRow::into_liquidation)
+            .collect()
+    }
+
+    pub fn update_liquidation_status(
+        conn: &SqliteConnection,
+        loan_txid: Txid,
+        status: LiquidationStatus,
+    ) -> Result<()> {
+        diesel::update(liquidations::table.filter(liquidations::id.eq(loan_txid.to_string())))
+            .set(liquidations::status.eq(status.to_string()))
+            .execute(conn)?;
+
+        Ok(())
+    }
 
-        let txs = txs
+    #[derive(Associations, Clone, Debug, Queryable, PartialEq)]
+    #[table_name = "lender_states"]
+    struct LenderState {
+        id: String,
+        state: String,
+        created_at: i64,
+        collateral_amount: i64,
+        principal_amount: i64,
+    }
+
+    pub fn get_lender_states(conn: &SqliteConnection) -> Result<HashMap<Txid, PendingLoan>> {
+        let states = lender_states::table.get_results::<LenderState>(conn)?;
+
+        let states = states
+            .into_iter()
+            .map(|lender_state| {
+                let txid = Txid::from_str(&lender_state.id)?;
+                let lender = serde_json::from_str(&lender_state.state)?;
+                let created_at = u64::try_from(lender_state.created_at)?;
+                let collateral_amount =
+                    Amount::from_sat(u64::try_from(lender_state.collateral_amount)?);
+                let principal_amount =
+                    Amount::from_sat(u64::try_from(lender_state.principal_amount)?);
+
+                Ok((
+                    txid,
+                    PendingLoan {
+                        lender,
+                        created_at,
+                        collateral_amount,
+                        principal_amount,
+                    },
+                ))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(states)
+    }
+
+    pub fn delete_lender_state(conn: &SqliteConnection, loan_txid: Txid) -> Result<()> {
+        diesel::delete(lender_states::table.filter(lender_states::id.eq(loan_txid.to_string())))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    #[derive(Associations, Clone, Debug, Queryable, PartialEq)]
+    #[table_name = "swaps"]
+    struct SwapRow {
+        txid: String,
+        direction: String,
+        taker_asset_id: String,
+        taker_amount: i64,
+        maker_asset_id: String,
+        maker_amount: i64,
+        rate: i64,
+        taker_address: String,
+        created_at: i64,
+        status: String,
+        invalidation_txid: Option<String>,
+        batch_index: i32,
+        service_fee: i64,
+    }
+
+    impl TryFrom<SwapRow> for Swap {
+        type Error = anyhow::Error;
+
+        fn try_from(row: SwapRow) -> Result<Self> {
+            Ok(Self {
+                txid: Txid::from_str(&row.txid)?,
+                direction: Direction::from_str(&row.direction)?,
+                taker_asset_id: AssetId::from_str(&row.taker_asset_id)?,
+                taker_amount: Amount::from_sat(u64::try_from(row.taker_amount)?),
+                maker_asset_id: AssetId::from_str(&row.maker_asset_id)?,
+                maker_amount: Amount::from_sat(u64::try_from(row.maker_amount)?),
+                service_fee: Amount::from_sat(u64::try_from(row.service_fee)?),
+                rate: LiquidUsdt::from_satodollar(u64::try_from(row.rate)?),
+                taker_address: Address::from_str(&row.taker_address)?,
+                created_at: u64::try_from(row.created_at)?,
+                status: SwapStatus::from_str(&row.status)?,
+                invalidation_txid: row
+                    .invalidation_txid
+                    .map(|txid| Txid::from_str(&txid))
+                    .transpose()?,
+                batch_index: u32::try_from(row.batch_index)?,
+            })
+        }
+    }
+
+    /// Get all the swaps which were created in the time range
+    /// `[from, to)`, expressed in seconds since the UNIX epoch.
+    pub fn get_swaps_created_between(
+        conn: &SqliteConnection,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<Swap>> {
+        let swaps = swaps::table
+            .filter(swaps::created_at.ge(i64::try_from(from)?))
+            .filter(swaps::created_at.lt(i64::try_from(to)?))
+            .order(swaps::created_at.asc())
+            .get_results::<SwapRow>(conn)?;
+
+        let swaps = swaps
+            .into_iter()
+            .map(Swap::try_from)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(swaps)
+    }
+
+    /// Get all the swaps which are currently in `status`.
+    pub fn get_swaps_by_status(conn: &SqliteConnection, status: SwapStatus) -> Result<Vec<Swap>> {
+        let swaps = swaps::table
+            .filter(swaps::status.eq(status.to_string()))
+            .order(swaps::created_at.asc())
+            .get_results::<SwapRow>(conn)?;
+
+        let swaps = swaps
+            .into_iter()
+            .map(Swap::try_from)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(swaps)
+    }
+
+    /// Whether we have recorded any swap settled by transaction
+    /// `txid`.
+    pub fn has_swap(conn: &SqliteConnection, txid: Txid) -> Result<bool> {
+        let count = swaps::table
+            .filter(swaps::txid.eq(txid.to_string()))
+            .count()
+            .get_result::<i64>(conn)?;
+
+        Ok(count > 0)
+    }
+
+    pub fn update_swap_status(
+        conn: &SqliteConnection,
+        txid: Txid,
+        status: SwapStatus,
+    ) -> Result<()> {
+        diesel::update(swaps::table.filter(swaps::txid.eq(txid.to_string())))
+            .set(swaps::status.eq(status.to_string()))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Record that the swap `txid` was invalidated by our transaction
+    /// `invalidation_txid`.
+    pub fn invalidate_swap(
+        conn: &SqliteConnection,
+        txid: Txid,
+        invalidation_txid: Txid,
+    ) -> Result<()> {
+        diesel::update(swaps::table.filter(swaps::txid.eq(txid.to_string())))
+            .set((
+                swaps::status.eq(SwapStatus::Invalidated.to_string()),
+                swaps::invalidation_txid.eq(Some(invalidation_txid.to_string())),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    #[derive(Associations, Clone, Debug, Queryable, PartialEq)]
+    #[table_name = "utxo_reservations"]
+    struct UtxoReservationRow {
+        txid: String,
+        vout: i32,
+        swap_txid: String,
+        expires_at: i64,
+    }
+
+    pub fn get_utxo_reservations(conn: &SqliteConnection) -> Result<Vec<UtxoReservation>> {
+        let reservations = utxo_reservations::table.get_results::<UtxoReservationRow>(conn)?;
+
+        let reservations = reservations
             .into_iter()
-            .map(|liquidation| Ok(deserialize(&hex::decode(liquidation.tx_hex)?)?))
+            .map(|row| {
+                Ok(UtxoReservation {
+                    outpoint: OutPoint {
+                        txid: Txid::from_str(&row.txid)?,
+                        vout: u32::try_from(row.vout)?,
+                    },
+                    swap_txid: Txid::from_str(&row.swap_txid)?,
+                    expires_at: u64::try_from(row.expires_at)?,
+                })
+            })
             .collect::<Result<Vec<_>>>()?;
 
-        Ok(txs)
+        Ok(reservations)
+    }
+
+    pub fn delete_utxo_reservation(conn: &SqliteConnection, outpoint: OutPoint) -> Result<()> {
+        diesel::delete(
+            utxo_reservations::table
+                .filter(utxo_reservations::txid.eq(outpoint.txid.to_string()))
+                .filter(utxo_reservations::vout.eq(i32::try_from(outpoint.vout)?)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    }
+
+    #[derive(Associations, Clone, Debug, Queryable, PartialEq)]
+    #[table_name = "fee_bumps"]
+    struct FeeBumpRow {
+        child_txid: String,
+        parent_txid: String,
+        fee: i64,
+        created_at: i64,
+    }
+
+    /// Get all the fee bumps of the transaction `parent_txid`, oldest
+    /// first.
+    pub fn get_fee_bumps(conn: &SqliteConnection, parent_txid: Txid) -> Result<Vec<FeeBump>> {
+        let bumps = fee_bumps::table
+            .filter(fee_bumps::parent_txid.eq(parent_txid.to_string()))
+            .order(fee_bumps::created_at.asc())
+            .get_results::<FeeBumpRow>(conn)?;
+
+        bumps
+            .into_iter()
+            .map(|row| {
+                Ok(FeeBump {
+                    child_txid: Txid::from_str(&row.child_txid)?,
+                    parent_txid: Txid::from_str(&row.parent_txid)?,
+                    fee: Amount::from_sat(u64::try_from(row.fee)?),
+                    created_at: u64::try_from(row.created_at)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_oracle_address(conn: &SqliteConnection) -> Result<Option<Address>> {
+        let address = oracle_keys::table
+            .select(oracle_keys::address)
+            .first::<String>(conn)
+            .optional()?;
+
+        address
+            .map(|address| Address::from_str(&address).map_err(anyhow::Error::from))
+            .transpose()
+    }
+
+    #[derive(Associations, Clone, Debug, Queryable, PartialEq)]
+    #[table_name = "price_attestations"]
+    struct PriceAttestationRow {
+        sequence: i64,
+        pair: String,
+        ask: i64,
+        bid: i64,
+        timestamp: i64,
+        signature: String,
+    }
+
+    impl TryFrom<PriceAttestationRow> for Attestation {
+        type Error = anyhow::Error;
+
+        fn try_from(row: PriceAttestationRow) -> Result<Self> {
+            Ok(Self {
+                sequence: u64::try_from(row.sequence)?,
+                pair: PairId::new(row.pair),
+                ask: LiquidUsdt::from_satodollar(u64::try_from(row.ask)?),
+                bid: LiquidUsdt::from_satodollar(u64::try_from(row.bid)?),
+                timestamp: u64::try_from(row.timestamp)?,
+                signature: Signature::from_str(&row.signature)?,
+            })
+        }
+    }
+
+    pub fn get_last_attestation_sequence(conn: &SqliteConnection) -> Result<Option<u64>> {
+        let sequence = price_attestations::table
+            .select(diesel::dsl::max(price_attestations::sequence))
+            .first::<Option<i64>>(conn)?;
+
+        sequence
+            .map(|sequence| u64::try_from(sequence).map_err(anyhow::Error::from))
+            .transpose()
+    }
+
+    /// The most recent attestation of the rate of `pair`.
+    pub fn get_latest_attestation(
+        conn: &SqliteConnection,
+        pair: &PairId,
+    ) -> Result<Option<Attestation>> {
+        price_attestations::table
+            .filter(price_attestations::pair.eq(pair.to_string()))
+            .order(price_attestations::sequence.desc())
+            .first::<PriceAttestationRow>(conn)
+            .optional()?
+            .map(Attestation::try_from)
+            .transpose()
+    }
+
+    pub fn get_attestation(conn: &SqliteConnection, sequence: u64) -> Result<Option<Attestation>> {
+        price_attestations::table
+            .filter(price_attestations::sequence.eq(i64::try_from(sequence)?))
+            .first::<PriceAttestationRow>(conn)
+            .optional()?
+            .map(Attestation::try_from)
+            .transpose()
+    }
+
+    /// Delete the attestations published before `timestamp`, returning
+    /// how many were deleted.
+    ///
+    /// The last attestation is always kept so that sequence numbers are
+    /// never reused.
+    pub fn delete_attestations_before(conn: &SqliteConnection, timestamp: u64) -> Result<usize> {
+        let last_sequence = match get_last_attestation_sequence(conn)? {
+            Some(sequence) => i64::try_from(sequence)?,
+            None => return Ok(0),
+        };
+
+        let deleted = diesel::delete(
+            price_attestations::table
+                .filter(price_attestations::timestamp.lt(i64::try_from(timestamp)?))
+                .filter(price_attestations::sequence.lt(last_sequence)),
+        )
+        .execute(conn)?;
+
+        Ok(deleted)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use elements::{
+        bitcoin::{Network, PrivateKey, PublicKey},
+        secp256k1_zkp::{SecretKey, SECP256K1},
+        AddressParams, Script, TxIn,
+    };
     use std::path::PathBuf;
 
     fn temp_db() -> PathBuf {
@@ -140,6 +951,10 @@ mod tests {
         temp_file.into_temp_path().to_path_buf()
     }
 
+    fn txid(n: u8) -> Txid {
+        Txid::from_str(&format!("{:064x}", n)).unwrap()
+    }
+
     #[test]
     fn can_create_a_new_temp_db() {
         let path = temp_db();
@@ -181,4 +996,317 @@ mod tests {
         assert!(&db.is_ok());
         assert!(&path.exists());
     }
+
+    #[tokio::test]
+    async fn can_query_swaps_by_creation_time() {
+        let db = Sqlite::new_ephemeral_db().unwrap();
+
+        let btc_asset_id =
+            AssetId::from_str("6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d")
+                .unwrap();
+        let usdt_asset_id = AssetId::from_str(crate::USDT_ASSET_ID).unwrap();
+        let taker_address = {
+            let sk = SecretKey::from_slice(&[1u8; 32]).unwrap();
+            let pk = PublicKey::from_private_key(
+                SECP256K1,
+                &PrivateKey {
+                    compressed: true,
+                    network: Network::Regtest,
+                    key: sk,
+                },
+            );
+
+            Address::p2wpkh(&pk, None, &AddressParams::ELEMENTS)
+        };
+
+        let swap = |txid: Txid, created_at: u64| Swap {
+            txid,
+            direction: Direction::Buy,
+            taker_asset_id: usdt_asset_id,
+            taker_amount: Amount::from_sat(2_000_000_000_000),
+            maker_asset_id: btc_asset_id,
+            maker_amount: Amount::ONE_BTC,
+            service_fee: Amount::from_sat(2_000_000_000),
+            rate: LiquidUsdt::from_satodollar(2_000_000_000_000),
+            taker_address: taker_address.clone(),
+            created_at,
+            status: SwapStatus::Created,
+            invalidation_txid: None,
+            batch_index: 0,
+        };
+        let yesterday = swap(txid(1), 1_000);
+        let today = swap(txid(2), 90_000);
+
+        db.do_in_transaction(|conn| {
+            for swap in [yesterday.clone(), today.clone()].iter() {
+                SwapForm::new(
+                    swap.txid,
+                    swap.direction,
+                    (swap.taker_asset_id, swap.taker_amount),
+                    (swap.maker_asset_id, swap.maker_amount),
+                    swap.service_fee,
+                    swap.rate,
+                    &swap.taker_address,
+                    swap.created_at,
+                    swap.batch_index,
+                )?
+                .insert(conn)?;
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let swaps = db
+            .do_in_transaction(|conn| queries::get_swaps_created_between(conn, 0, 86_400))
+            .await
+            .unwrap();
+
+        assert_eq!(swaps, vec![yesterday])
+    }
+
+    #[tokio::test]
+    async fn can_delete_utxo_reservation() {
+        let db = Sqlite::new_ephemeral_db().unwrap();
+
+        let swap_txid = txid(3);
+        let reservation = |vout: u32| UtxoReservation {
+            outpoint: OutPoint {
+                txid: txid(4),
+                vout,
+            },
+            swap_txid,
+            expires_at: 1_000,
+        };
+        let first = reservation(0);
+        let second = reservation(1);
+
+        db.do_in_transaction(|conn| {
+            for reservation in [first, second].iter() {
+                UtxoReservationForm::new(
+                    reservation.outpoint,
+                    reservation.swap_txid,
+                    reservation.expires_at,
+                )?
+                .insert(conn)?;
+            }
+
+            queries::delete_utxo_reservation(conn, first.outpoint)
+        })
+        .await
+        .unwrap();
+
+        let reservations = db
+            .do_in_transaction(|conn| queries::get_utxo_reservations(conn))
+            .await
+            .unwrap();
+
+        assert_eq!(reservations, vec![second])
+    }
+
+    #[tokio::test]
+    async fn settled_liquidations_are_moved_to_history() {
+        let db = Sqlite::new_ephemeral_db().unwrap();
+
+        let liquidation_tx = |loan_txid: Txid| Transaction {
+            version: 2,
+            lock_time: 100,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: loan_txid,
+                    vout: 0,
+                },
+                is_pegin: false,
+                has_issuance: false,
+                script_sig: Script::new(),
+                sequence: 0xFFFF_FFFE,
+                asset_issuance: Default::default(),
+                witness: Default::default(),
+            }],
+            output: Vec::new(),
+        };
+
+        let repaid = txid(5);
+        let confirmed = txid(6);
+        let failed = txid(7);
+
+        db.do_in_transaction(|conn| {
+            for txid in [repaid, confirmed, failed].iter() {
+                LiquidationForm::new(
+                    *txid,
+                    &liquidation_tx(*txid),
+                    100,
+                    Amount::ONE_BTC,
+                    Amount::from_sat(2_000_000_000_000),
+                )?
+                .insert(conn)?;
+            }
+
+            queries::settle_liquidation(conn, repaid, LiquidationStatus::Repaid, 1_000)?;
+            queries::settle_liquidation(conn, confirmed, LiquidationStatus::Confirmed, 2_000)?;
+            queries::update_liquidation_status(conn, failed, LiquidationStatus::Failed)
+        })
+        .await
+        .unwrap();
+
+        let history = db
+            .do_in_transaction(|conn| queries::get_liquidation_history(conn))
+            .await
+            .unwrap();
+        let mut settled = history
+            .iter()
+            .map(|liquidation| (liquidation.loan_txid, liquidation.status))
+            .collect::<Vec<_>>();
+        settled.sort_by_key(|(loan_txid, _)| *loan_txid);
+        assert_eq!(
+            settled,
+            vec![
+                (repaid, LiquidationStatus::Repaid),
+                (confirmed, LiquidationStatus::Confirmed)
+            ]
+        );
+
+        let active = db
+            .do_in_transaction(|conn| queries::get_active_liquidations(conn))
+            .await
+            .unwrap();
+
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].loan_txid, failed);
+        assert_eq!(active[0].status, LiquidationStatus::Failed);
+        assert_eq!(
+            active[0].collateral_outpoint().unwrap(),
+            OutPoint {
+                txid: failed,
+                vout: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn can_query_fee_bumps_by_parent() {
+        let db = Sqlite::new_ephemeral_db().unwrap();
+
+        let bump = |child: u8, parent: u8, created_at: u64| FeeBump {
+            child_txid: txid(child),
+            parent_txid: txid(parent),
+            fee: Amount::from_sat(2_000),
+            created_at,
+        };
+        let first = bump(10, 8, 1_000);
+        let second = bump(11, 8, 2_000);
+        let other = bump(12, 9, 1_500);
+
+        db.do_in_transaction(|conn| {
+            for bump in [second, other, first].iter() {
+                FeeBumpForm::new(bump.child_txid, bump.parent_txid, bump.fee, bump.created_at)?
+                    .insert(conn)?;
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let bumps = db
+            .do_in_transaction(|conn| queries::get_fee_bumps(conn, txid(8)))
+            .await
+            .unwrap();
+
+        assert_eq!(bumps, vec![first, second])
+    }
+
+    #[tokio::test]
+    async fn can_query_price_attestations() {
+        let db = Sqlite::new_ephemeral_db().unwrap();
+
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let attestation = |sequence: u64, pair: &str| Attestation {
+            sequence,
+            pair: PairId::new(pair),
+            ask: LiquidUsdt::from_satodollar(3_000_100_000_000),
+            bid: LiquidUsdt::from_satodollar(3_000_000_000_000),
+            timestamp: 1_626_000_000 + sequence,
+            signature: SECP256K1.sign(
+                &elements::secp256k1_zkp::Message::from_slice(&[sequence as u8 + 1; 32]).unwrap(),
+                &secret_key,
+            ),
+        };
+        let first = attestation(0, "lbtc-lusdt");
+        let second = attestation(1, "lbtc-lusdt");
+        let other = attestation(2, "lbtc-leurx");
+
+        db.do_in_transaction(|conn| {
+            for attestation in [first.clone(), second.clone(), other].iter() {
+                PriceAttestationForm::new(attestation)?.insert(conn)?;
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let (last_sequence, latest, by_sequence) = db
+            .do_in_transaction(|conn| {
+                Ok((
+                    queries::get_last_attestation_sequence(conn)?,
+                    queries::get_latest_attestation(conn, &PairId::new("lbtc-lusdt"))?,
+                    queries::get_attestation(conn, 0)?,
+                ))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(last_sequence, Some(2));
+        assert_eq!(latest, Some(second));
+        assert_eq!(by_sequence, Some(first));
+    }
+
+    #[tokio::test]
+    async fn old_attestations_are_deleted_except_the_last_one() {
+        let db = Sqlite::new_ephemeral_db().unwrap();
+
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let attestation = |sequence: u64, timestamp: u64| Attestation {
+            sequence,
+            pair: PairId::new("lbtc-lusdt"),
+            ask: LiquidUsdt::from_satodollar(3_000_100_000_000),
+            bid: LiquidUsdt::from_satodollar(3_000_000_000_000),
+            timestamp,
+            signature: SECP256K1.sign(
+                &elements::secp256k1_zkp::Message::from_slice(&[sequence as u8 + 1; 32]).unwrap(),
+                &secret_key,
+            ),
+        };
+        let old = attestation(0, 1_000);
+        let recent = attestation(1, 3_000);
+
+        let (deleted, remaining) = db
+            .do_in_transaction(|conn| {
+                for attestation in [old.clone(), recent.clone()].iter() {
+                    PriceAttestationForm::new(attestation)?.insert(conn)?;
+                }
+
+                let deleted = queries::delete_attestations_before(conn, 2_000)?;
+                let remaining = (
+                    queries::get_attestation(conn, 0)?,
+                    queries::get_attestation(conn, 1)?,
+                );
+
+                Ok((deleted, remaining))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(deleted, 1);
+        assert_eq!(remaining, (None, Some(recent)));
+
+        let deleted = db
+            .do_in_transaction(|conn| queries::delete_attestations_before(conn, 4_000))
+            .await
+            .unwrap();
+
+        assert_eq!(deleted, 0, "the last attestation must be kept");
+    }
 }
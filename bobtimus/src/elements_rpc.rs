@@ -5,15 +5,16 @@ use elements::{
     confidential::{Asset, Nonce, Value},
     encode::serialize_hex,
     secp256k1_zkp::{SecretKey, Signature},
-    Address, AssetId, OutPoint, Transaction, TxOut, TxOutWitness, Txid,
+    Address, AssetId, OutPoint, Transaction, TxOut, TxOutSecrets, TxOutWitness, Txid,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, convert::TryFrom, str::FromStr, time::Duration};
 
 #[jsonrpc_client::api(version = "1.0")]
 pub trait ElementsRpc {
     async fn getblockchaininfo(&self) -> BlockchainInfo;
     async fn getblockcount(&self) -> u32;
+    async fn waitfornewblock(&self, timeout: u64) -> BlockRef;
     async fn getnewaddress(&self, label: &str, address_type: Option<&str>) -> Address;
     #[allow(clippy::too_many_arguments)]
     async fn sendtoaddress(
@@ -46,6 +47,26 @@ pub trait ElementsRpc {
         asset_id: Option<AssetId>,
     ) -> f64;
     async fn fundrawtransaction(&self, tx_hex: String) -> FundRawTransactionResponse;
+    async fn createrawtransaction(
+        &self,
+        inputs: Vec<RawTransactionInput>,
+        outputs: Vec<HashMap<String, f64>>,
+        locktime: Option<u32>,
+        replaceable: Option<bool>,
+        output_assets: Option<HashMap<String, AssetId>>,
+    ) -> String;
+    async fn blindrawtransaction(&self, tx_hex: String) -> String;
+    #[allow(clippy::too_many_arguments)]
+    async fn rawblindrawtransaction(
+        &self,
+        tx_hex: String,
+        input_amount_blinders: Vec<String>,
+        input_amounts: Vec<f64>,
+        input_assets: Vec<AssetId>,
+        input_asset_blinders: Vec<String>,
+        totalblinder: Option<String>,
+        ignoreblindfail: Option<bool>,
+    ) -> String;
     async fn dumpblindingkey(&self, address: &Address) -> SecretKey;
     async fn listunspent(
         &self,
@@ -63,6 +84,11 @@ pub trait ElementsRpc {
     async fn dumpmasterblindingkey(&self) -> String;
     async fn unblindrawtransaction(&self, tx_hex: String) -> UnblindRawTransactionResponse;
     async fn lockunspent(&self, unlock: bool, utxos: Vec<OutPoint>) -> bool;
+    async fn listlockunspent(&self) -> Vec<OutPoint>;
+    async fn gettxout(&self, txid: Txid, n: u32, include_mempool: bool) -> Option<TxOutInfo>;
+    async fn gettransaction(&self, txid: Txid) -> WalletTransaction;
+    async fn getrawmempool(&self) -> Vec<Txid>;
+    async fn estimatesmartfee(&self, conf_target: u16) -> EstimateSmartFeeResponse;
     async fn reissueasset(&self, asset: AssetId, amount: f64) -> ReissueAssetResponse;
     async fn getaddressinfo(&self, address: &Address) -> GetAddressInfoResponse;
     async fn listreceivedbyaddress(
@@ -86,8 +112,10 @@ pub trait ElementsRpc {
         imbalance_ok: Option<bool>,
     ) -> WalletSignPsbtResponse;
     async fn finalizepsbt(&self, psbt: String, extract: Option<bool>) -> FinalizePsbtResponse;
+    async fn decodepsbt(&self, psbt: String) -> DecodedPset;
     async fn signmessage(&self, address: &Address, message: String) -> String;
     async fn dumpprivkey(&self, address: &Address) -> String;
+    async fn dumpblindingkey(&self, address: &Address) -> String;
 }
 
 #[jsonrpc_client::implement(ElementsRpc)]
@@ -140,6 +168,52 @@ pub struct WalletSignPsbtResponse {
     pub complete: bool,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockRef {
+    pub hash: String,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WalletTransaction {
+    /// Negative if the transaction conflicts with one in the
+    /// blockchain.
+    pub confirmations: i64,
+    /// Seconds since the UNIX epoch at which the wallet first saw the
+    /// transaction.
+    pub time: u64,
+    #[serde(default)]
+    pub details: Vec<WalletTransactionDetail>,
+}
+
+/// An output of a wallet transaction, or an amount it sends.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WalletTransactionDetail {
+    pub category: String,
+    pub vout: u32,
+    pub amount: f64,
+    pub asset: AssetId,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct RawTransactionInput {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TxOutInfo {
+    pub confirmations: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EstimateSmartFeeResponse {
+    /// Estimated fee rate in BTC/kvB.
+    pub feerate: Option<f64>,
+    pub errors: Option<Vec<String>>,
+    pub blocks: u32,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct FinalizePsbtResponse {
     pub hex: Option<String>,
@@ -147,6 +221,23 @@ pub struct FinalizePsbtResponse {
     pub complete: bool,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct DecodedPset {
+    pub tx: DecodedPsetTransaction,
+    pub inputs: Vec<DecodedPsetInput>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DecodedPsetTransaction {
+    pub txid: Txid,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DecodedPsetInput {
+    /// Hex-encoded witness stack, once the input has been finalized.
+    pub final_scriptwitness: Option<Vec<String>>,
+}
+
 impl Client {
     pub fn new(base_url: String) -> Result<Self> {
         Ok(Self {
@@ -302,6 +393,79 @@ impl Client {
         Ok(tx)
     }
 
+    /// Convert `tx` into a base64-encoded PSET.
+    ///
+    /// Any signatures in `tx` are discarded, so our inputs have to be
+    /// signed again with [`Client::sign_pset`].
+    pub async fn convert_to_pset(&self, tx: &Transaction) -> Result<String> {
+        let pset = self
+            .converttopsbt(serialize_hex(tx), Some(true), Some(true))
+            .await?;
+
+        Ok(pset)
+    }
+
+    /// Sign the inputs of `pset` which belong to our wallet.
+    pub async fn sign_pset(&self, pset: String) -> Result<String> {
+        let res = self.walletsignpsbt(pset, None, Some(true)).await?;
+
+        res.psbt.context("elementsd did not return the signed PSET")
+    }
+
+    /// The ID of the transaction `pset` produces once it is signed.
+    pub async fn pset_txid(&self, pset: String) -> Result<Txid> {
+        let decoded = self.decodepsbt(pset).await?;
+
+        Ok(decoded.tx.txid)
+    }
+
+    /// Finalize the inputs of `pset` which are fully signed, without
+    /// signing any of ours, and return the ID of its transaction
+    /// together with the witness of each input. Inputs which are not
+    /// signed yet have no witness.
+    pub async fn pset_witnesses(&self, pset: String) -> Result<(Txid, Vec<Option<Vec<Vec<u8>>>>)> {
+        let res = self.finalizepsbt(pset, Some(false)).await?;
+        let pset = res
+            .psbt
+            .context("elementsd did not return the finalized PSET")?;
+        let decoded = self.decodepsbt(pset).await?;
+
+        let witnesses = decoded
+            .inputs
+            .into_iter()
+            .map(|input| {
+                input
+                    .final_scriptwitness
+                    .map(|witness| {
+                        witness
+                            .iter()
+                            .map(|item| Vec::<u8>::from_hex(item))
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((decoded.tx.txid, witnesses))
+    }
+
+    /// Sign our inputs of `pset` and extract the resulting
+    /// transaction.
+    ///
+    /// Fails if any other input of `pset` is not signed yet.
+    pub async fn finalize_pset(&self, pset: String) -> Result<Transaction> {
+        let pset = self.sign_pset(pset).await?;
+        let res = self.finalizepsbt(pset, Some(true)).await?;
+
+        let hex = match res.hex {
+            Some(hex) if res.complete => hex,
+            _ => bail!("PSET is missing signatures"),
+        };
+        let tx = elements::encode::deserialize(&Vec::<u8>::from_hex(&hex)?)?;
+
+        Ok(tx)
+    }
+
     pub async fn fund_raw_transaction(&self, tx: &Transaction) -> Result<Transaction> {
         let tx_hex = serialize_hex(tx);
         let res = self.fundrawtransaction(tx_hex).await?;
@@ -310,6 +474,209 @@ impl Client {
         Ok(tx)
     }
 
+    /// Create an unsigned transaction spending `inputs`, which pays
+    /// each output's amount of its asset to its address, and `fee` in
+    /// `fee_asset`.
+    ///
+    /// The outputs are not blinded, see [`Client::blind_raw_transaction`].
+    pub async fn create_raw_transaction(
+        &self,
+        inputs: &[OutPoint],
+        outputs: &[(Address, AssetId, Amount)],
+        (fee_asset, fee): (AssetId, Amount),
+    ) -> Result<Transaction> {
+        let fee_key = "fee".to_string();
+
+        let raw_inputs = inputs
+            .iter()
+            .map(|outpoint| RawTransactionInput {
+                txid: outpoint.txid,
+                vout: outpoint.vout,
+            })
+            .collect();
+
+        let mut raw_outputs = Vec::new();
+        let mut output_assets = HashMap::new();
+        for (address, asset, amount) in outputs {
+            let mut output = HashMap::new();
+            output.insert(address.to_string(), amount.as_btc());
+
+            raw_outputs.push(output);
+            output_assets.insert(address.to_string(), *asset);
+        }
+
+        let mut fee_output = HashMap::new();
+        fee_output.insert(fee_key.clone(), fee.as_btc());
+        raw_outputs.push(fee_output);
+        output_assets.insert(fee_key, fee_asset);
+
+        let tx_hex = self
+            .createrawtransaction(raw_inputs, raw_outputs, None, None, Some(output_assets))
+            .await?;
+        let tx = elements::encode::deserialize(&Vec::<u8>::from_hex(&tx_hex)?)?;
+
+        Ok(tx)
+    }
+
+    /// Blind the outputs of `tx` which pay to confidential addresses.
+    ///
+    /// All the inputs must belong to our wallet.
+    pub async fn blind_raw_transaction(&self, tx: &Transaction) -> Result<Transaction> {
+        let tx_hex = serialize_hex(tx);
+        let tx_hex = self.blindrawtransaction(tx_hex).await?;
+        let tx = elements::encode::deserialize(&Vec::<u8>::from_hex(&tx_hex)?)?;
+
+        Ok(tx)
+    }
+
+    /// Blind the outputs of `tx` which pay to confidential addresses,
+    /// given the secrets of the outputs spent by each of its inputs,
+    /// in order.
+    ///
+    /// Unlike [`Client::blind_raw_transaction`], this works for
+    /// transactions with inputs which do not belong to our wallet.
+    pub async fn blind_raw_transaction_with_secrets(
+        &self,
+        tx: &Transaction,
+        input_secrets: &[TxOutSecrets],
+    ) -> Result<Transaction> {
+        let tx_hex = serialize_hex(tx);
+        let tx_hex = self
+            .rawblindrawtransaction(
+                tx_hex,
+                input_secrets
+                    .iter()
+                    .map(|secrets| secrets.value_bf.to_string())
+                    .collect(),
+                input_secrets
+                    .iter()
+                    .map(|secrets| Amount::from_sat(secrets.value).as_btc())
+                    .collect(),
+                input_secrets.iter().map(|secrets| secrets.asset).collect(),
+                input_secrets
+                    .iter()
+                    .map(|secrets| secrets.asset_bf.to_string())
+                    .collect(),
+                None,
+                Some(false),
+            )
+            .await?;
+        let tx = elements::encode::deserialize(&Vec::<u8>::from_hex(&tx_hex)?)?;
+
+        Ok(tx)
+    }
+
+    /// Get the wallet UTXOs among `outpoints`, including unconfirmed
+    /// and locked ones, which `listunspent` does not list.
+    ///
+    /// Spent outputs are not returned.
+    pub async fn get_wallet_utxos(&self, outpoints: &[OutPoint]) -> Result<Vec<UtxoInfo>> {
+        let mut utxos = Vec::new();
+        for outpoint in outpoints {
+            if !self.is_unspent(*outpoint, true).await? {
+                continue;
+            }
+
+            let tx = self.gettransaction(outpoint.txid).await?;
+            let received = tx
+                .details
+                .into_iter()
+                .find(|detail| detail.category == "receive" && detail.vout == outpoint.vout);
+
+            if let Some(detail) = received {
+                utxos.push(UtxoInfo {
+                    txid: outpoint.txid,
+                    vout: outpoint.vout,
+                    address: None,
+                    spendable: true,
+                    amount: detail.amount,
+                    asset: detail.asset,
+                });
+            }
+        }
+
+        Ok(utxos)
+    }
+
+    /// Get the largest confirmed and unlocked wallet UTXO of `asset`.
+    pub async fn get_largest_utxo(&self, asset: AssetId) -> Result<UtxoInfo> {
+        self.listunspent(
+            Some(1),
+            None,
+            None,
+            None,
+            Some(ListUnspentOptions {
+                asset: Some(asset),
+                ..Default::default()
+            }),
+        )
+        .await?
+        .into_iter()
+        .filter(|utxo| utxo.spendable)
+        .max_by(|a, b| {
+            a.amount
+                .partial_cmp(&b.amount)
+                .expect("amounts are not NaN")
+        })
+        .with_context(|| format!("no UTXO of asset {}", asset))
+    }
+
+    /// Send the funds of each of our `inputs` to a new address of
+    /// ours, paying `fee` out of the first input of `fee_asset`.
+    pub async fn spend_to_self(
+        &self,
+        inputs: &[UtxoInfo],
+        (fee_asset, fee): (AssetId, Amount),
+    ) -> Result<Txid> {
+        let fee_input = inputs
+            .iter()
+            .position(|utxo| utxo.asset == fee_asset)
+            .with_context(|| format!("no input of asset {} to pay the fee", fee_asset))?;
+
+        let mut outpoints = Vec::new();
+        let mut outputs = Vec::new();
+        for (i, utxo) in inputs.iter().enumerate() {
+            let amount = Amount::from_btc(utxo.amount)?;
+            let amount = if i == fee_input {
+                match amount
+                    .checked_sub(fee)
+                    .filter(|change| *change > Amount::ZERO)
+                {
+                    Some(change) => change,
+                    None => bail!(
+                        "input of {} cannot pay a fee of {}",
+                        amount.as_sat(),
+                        fee.as_sat()
+                    ),
+                }
+            } else {
+                amount
+            };
+            let address = self.get_new_segwit_confidential_address().await?;
+
+            outpoints.push(OutPoint {
+                txid: utxo.txid,
+                vout: utxo.vout,
+            });
+            outputs.push((address, utxo.asset, amount));
+        }
+
+        let tx = self
+            .create_raw_transaction(&outpoints, &outputs, (fee_asset, fee))
+            .await
+            .context("failed to create transaction")?;
+        let tx = self
+            .blind_raw_transaction(&tx)
+            .await
+            .context("failed to blind transaction")?;
+        let tx = self
+            .sign_raw_transaction(&tx)
+            .await
+            .context("failed to sign transaction")?;
+
+        self.send_raw_transaction(&tx).await
+    }
+
     pub async fn lock_utxos(&self, utxos: Vec<OutPoint>) -> Result<()> {
         let res = self.lockunspent(false, utxos).await?;
 
@@ -320,6 +687,64 @@ impl Client {
         }
     }
 
+    /// Make previously locked UTXOs available for coin selection
+    /// again.
+    ///
+    /// UTXOs which are not currently locked are ignored.
+    pub async fn unlock_utxos(&self, utxos: Vec<OutPoint>) -> Result<()> {
+        let locked = self.listlockunspent().await?;
+        let utxos = utxos
+            .into_iter()
+            .filter(|utxo| locked.contains(utxo))
+            .collect::<Vec<_>>();
+
+        if utxos.is_empty() {
+            return Ok(());
+        }
+
+        let res = self.lockunspent(true, utxos).await?;
+
+        if res {
+            Ok(())
+        } else {
+            bail!("Could not unlock outputs")
+        }
+    }
+
+    /// Lock UTXOs which are not locked yet, so that they are not
+    /// chosen by coin selection.
+    ///
+    /// UTXOs which are already locked or which have been spent are
+    /// ignored.
+    pub async fn relock_utxos(&self, utxos: Vec<OutPoint>) -> Result<()> {
+        let locked = self.listlockunspent().await?;
+
+        let mut to_lock = Vec::new();
+        for utxo in utxos {
+            if !locked.contains(&utxo) && self.is_unspent(utxo, true).await? {
+                to_lock.push(utxo);
+            }
+        }
+
+        if to_lock.is_empty() {
+            return Ok(());
+        }
+
+        self.lock_utxos(to_lock).await
+    }
+
+    /// Check if `outpoint` is an unspent transaction output.
+    ///
+    /// If `include_mempool` is set, outputs spent by transactions in
+    /// the mempool are considered spent.
+    pub async fn is_unspent(&self, outpoint: OutPoint, include_mempool: bool) -> Result<bool> {
+        let txout = self
+            .gettxout(outpoint.txid, outpoint.vout, include_mempool)
+            .await?;
+
+        Ok(txout.is_some())
+    }
+
     pub async fn list_received_by_address(
         &self,
         address: &Address,
@@ -345,11 +770,36 @@ impl Client {
         Ok(privkey.key)
     }
 
+    /// The key to unblind the outputs paying to `address`, which must
+    /// belong to our wallet.
+    pub async fn dump_blinding_key(&self, address: &Address) -> Result<SecretKey> {
+        let blinding_key = self.dumpblindingkey(address).await?;
+        let blinding_key = SecretKey::from_str(&blinding_key)?;
+
+        Ok(blinding_key)
+    }
+
+    pub async fn get_balance(&self, asset_id: AssetId) -> Result<Amount> {
+        let balance = self.getbalance(None, None, None, Some(asset_id)).await?;
+        let balance = Amount::from_btc(balance)?;
+
+        Ok(balance)
+    }
+
     pub async fn get_blockcount(&self) -> Result<u32> {
         let blockcount = self.getblockcount().await?;
 
         Ok(blockcount)
     }
+
+    /// Wait until a new block is added to the chain or `timeout`
+    /// expires, returning the height of the chain tip.
+    pub async fn wait_for_new_block(&self, timeout: Duration) -> Result<u32> {
+        let timeout_millis = u64::try_from(timeout.as_millis())?;
+        let tip = self.waitfornewblock(timeout_millis).await?;
+
+        Ok(tip.height)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -390,6 +840,7 @@ pub struct UtxoInfo {
     pub address: Option<Address>,
     pub spendable: bool,
     pub amount: f64,
+    pub asset: AssetId,
 }
 
 #[cfg(all(test))]
@@ -518,4 +969,54 @@ mod test {
 
         assert_eq!(blockcount, 1)
     }
+
+    #[tokio::test]
+    async fn transaction_roundtrips_through_pset() {
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+
+        let address = client.get_new_segwit_confidential_address().await.unwrap();
+        client.generatetoaddress(1, &address).await.unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let utxo = client.get_largest_utxo(btc_asset_id).await.unwrap();
+        let fee = Amount::from_sat(10_000);
+        let amount = Amount::from_btc(utxo.amount).unwrap() - fee;
+        let tx = client
+            .create_raw_transaction(
+                &[OutPoint {
+                    txid: utxo.txid,
+                    vout: utxo.vout,
+                }],
+                &[(address, btc_asset_id, amount)],
+                (btc_asset_id, fee),
+            )
+            .await
+            .unwrap();
+        let tx = client.blind_raw_transaction(&tx).await.unwrap();
+
+        let pset = client.convert_to_pset(&tx).await.unwrap();
+        assert_eq!(client.pset_txid(pset.clone()).await.unwrap(), tx.txid());
+
+        let signed = client.sign_pset(pset.clone()).await.unwrap();
+        let (txid, witnesses) = client.pset_witnesses(signed).await.unwrap();
+        assert_eq!(txid, tx.txid());
+        assert!(witnesses.iter().all(Option::is_some));
+
+        let finalized = client.finalize_pset(pset).await.unwrap();
+        assert_eq!(finalized.txid(), tx.txid());
+        assert_eq!(
+            finalized.input[0].witness.script_witness,
+            witnesses[0].clone().unwrap()
+        );
+
+        client.send_raw_transaction(&finalized).await.unwrap();
+    }
 }
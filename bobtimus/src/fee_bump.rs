@@ -0,0 +1,320 @@
+use crate::{
+    database::{queries, FeeBumpForm, LiquidationStatus, Sqlite, SwapStatus},
+    elements_rpc::{Client, ElementsRpc},
+    fee_rate, unix_timestamp,
+};
+use anyhow::{Context, Result};
+use elements::{bitcoin::Amount, AssetId, Transaction, Txid};
+use estimate_transaction_size::estimate_virtual_size;
+use std::time::Duration;
+
+/// Speed up the confirmation of our transactions which have been in
+/// the mempool for longer than `threshold`.
+///
+/// We consider liquidation and swap transactions which have been
+/// broadcast. Each one is bumped by broadcasting a child transaction
+/// which spends one of the outputs we own in it and pays enough fees
+/// for both transactions to be mined at our current fee rate.
+///
+/// Children we broadcast earlier which are still in the mempool count
+/// towards the fees paid for the parent, so a transaction is only
+/// bumped again if our fee rate has gone up since.
+pub async fn bump_stuck_transactions(
+    elementsd: &Client,
+    db: &Sqlite,
+    fee_rate_service: &fee_rate::Service,
+    btc_asset_id: AssetId,
+    threshold: Duration,
+) -> Result<()> {
+    let txids = db
+        .do_in_transaction(|conn| {
+            let liquidations = queries::get_active_liquidations(conn)?
+                .into_iter()
+                .filter(|liquidation| liquidation.status == LiquidationStatus::Broadcast)
+                .map(|liquidation| liquidation.transaction.txid());
+            // All the takers of a batch swap share a transaction
+            let swaps = queries::get_swaps_by_status(conn, SwapStatus::Broadcast)?
+                .into_iter()
+                .filter(|swap| swap.batch_index == 0)
+                .map(|swap| swap.txid);
+
+            Ok(liquidations.chain(swaps).collect::<Vec<_>>())
+        })
+        .await?;
+
+    if txids.is_empty() {
+        return Ok(());
+    }
+
+    let fee_rate = fee_rate_service.fee_rate().await?;
+    let now = unix_timestamp();
+
+    for txid in txids {
+        let wallet_tx = elementsd
+            .gettransaction(txid)
+            .await
+            .with_context(|| format!("failed to get transaction {}", txid))?;
+
+        let is_stuck = wallet_tx.confirmations == 0 && now >= wallet_tx.time + threshold.as_secs();
+        if !is_stuck {
+            continue;
+        }
+
+        if let Err(e) = bump_fee(elementsd, db, btc_asset_id, txid, fee_rate).await {
+            tracing::error!("Failed to bump fee of transaction {}: {:#}", txid, e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn bump_fee(
+    elementsd: &Client,
+    db: &Sqlite,
+    btc_asset_id: AssetId,
+    parent_txid: Txid,
+    fee_rate: Amount,
+) -> Result<()> {
+    // Outputs which are already spent by a previous child in the
+    // mempool are not listed
+    let parent_outputs = elementsd
+        .listunspent(Some(0), Some(0), None, Some(true), None)
+        .await?
+        .into_iter()
+        .filter(|utxo| utxo.txid == parent_txid && utxo.spendable)
+        .collect::<Vec<_>>();
+
+    let parent_output = match parent_outputs
+        .iter()
+        .find(|utxo| utxo.asset == btc_asset_id)
+        .or_else(|| parent_outputs.first())
+    {
+        Some(utxo) => utxo.clone(),
+        None => {
+            tracing::debug!("No output of transaction {} left to bump from", parent_txid);
+            return Ok(());
+        }
+    };
+
+    let mut inputs = vec![parent_output];
+
+    // We need L-BTC to pay the fee of the child transaction
+    if inputs[0].asset != btc_asset_id {
+        let fee_utxo = elementsd
+            .get_largest_utxo(btc_asset_id)
+            .await
+            .context("no L-BTC to pay the fee of the child transaction")?;
+        inputs.push(fee_utxo);
+    }
+
+    let parent = elementsd.get_raw_transaction(parent_txid).await?;
+    let mut package_vsize = virtual_size(&parent);
+    let mut package_fee = fee_paid(&parent, btc_asset_id);
+
+    let mempool = elementsd.getrawmempool().await?;
+    let earlier_children = db
+        .do_in_transaction(|conn| queries::get_fee_bumps(conn, parent_txid))
+        .await?
+        .into_iter()
+        .filter(|bump| mempool.contains(&bump.child_txid));
+    for bump in earlier_children {
+        let child = elementsd.get_raw_transaction(bump.child_txid).await?;
+        package_vsize += virtual_size(&child);
+        package_fee += bump.fee;
+    }
+
+    let child_vsize = estimate_virtual_size(inputs.len() as u64, inputs.len() as u64);
+    let fee = match child_fee(package_vsize, package_fee, child_vsize, fee_rate) {
+        Some(fee) => fee,
+        None => {
+            tracing::debug!(
+                "Transaction {} and its children already pay {} sat/vB",
+                parent_txid,
+                fee_rate.as_sat()
+            );
+            return Ok(());
+        }
+    };
+
+    let child_txid = elementsd
+        .spend_to_self(&inputs, (btc_asset_id, fee))
+        .await
+        .context("failed to broadcast child transaction")?;
+
+    db.do_in_transaction(|conn| {
+        FeeBumpForm::new(child_txid, parent_txid, fee, unix_timestamp())?.insert(conn)
+    })
+    .await
+    .with_context(|| format!("failed to record fee bump {}", child_txid))?;
+
+    tracing::info!(
+        "Bumped fee of transaction {} with child {} paying {} sat",
+        parent_txid,
+        child_txid,
+        fee.as_sat()
+    );
+
+    Ok(())
+}
+
+/// Periodically bump the fee of our stuck transactions.
+pub async fn bump_stuck_transactions_continuously(
+    elementsd: Client,
+    db: Sqlite,
+    fee_rate_service: fee_rate::Service,
+    btc_asset_id: AssetId,
+    threshold: Duration,
+    period: Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) =
+            bump_stuck_transactions(&elementsd, &db, &fee_rate_service, btc_asset_id, threshold)
+                .await
+        {
+            tracing::error!("Failed to bump fees of stuck transactions: {:#}", e);
+        }
+    }
+}
+
+/// The fee a new child transaction of `child_vsize` virtual bytes has
+/// to pay for it to be mined at `fee_rate` together with the
+/// `package`, i.e. the parent and the children it already has.
+///
+/// Returns `None` if the package pays at least `fee_rate` already.
+fn child_fee(
+    package_vsize: u64,
+    package_fee: Amount,
+    child_vsize: u64,
+    fee_rate: Amount,
+) -> Option<Amount> {
+    let package_target = fee_rate.as_sat() * package_vsize;
+    if package_fee.as_sat() >= package_target {
+        return None;
+    }
+
+    let target = fee_rate.as_sat() * (package_vsize + child_vsize);
+
+    Some(Amount::from_sat(target - package_fee.as_sat()))
+}
+
+fn virtual_size(tx: &Transaction) -> u64 {
+    (tx.get_weight() as u64 + 3) / 4
+}
+
+/// The fee paid by `tx` in `fee_asset`, i.e. the sum of its explicit
+/// fee outputs.
+fn fee_paid(tx: &Transaction, fee_asset: AssetId) -> Amount {
+    let fee = tx
+        .output
+        .iter()
+        .filter(|txout| txout.is_fee() && txout.asset.explicit() == Some(fee_asset))
+        .filter_map(|txout| txout.value.explicit())
+        .sum();
+
+    Amount::from_sat(fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements_harness::Elementsd;
+    use testcontainers::clients::Cli;
+
+    #[test]
+    fn child_pays_for_the_whole_package() {
+        let fee = child_fee(1_000, Amount::from_sat(1_000), 500, Amount::from_sat(3)).unwrap();
+
+        assert_eq!(fee, Amount::from_sat(3_500));
+    }
+
+    #[test]
+    fn no_bump_if_parent_pays_enough() {
+        let fee = child_fee(1_000, Amount::from_sat(3_000), 500, Amount::from_sat(3));
+
+        assert_eq!(fee, None);
+    }
+
+    #[test]
+    fn earlier_children_count_towards_package_fee() {
+        let parent_vsize = 1_000;
+        let parent_fee = Amount::from_sat(1_000);
+        let child_vsize = 500;
+        let fee_rate = Amount::from_sat(3);
+
+        let first = child_fee(parent_vsize, parent_fee, child_vsize, fee_rate).unwrap();
+        let second = child_fee(
+            parent_vsize + child_vsize,
+            parent_fee + first,
+            child_vsize,
+            fee_rate,
+        );
+
+        assert_eq!(second, None);
+    }
+
+    #[tokio::test]
+    async fn transaction_is_only_bumped_again_if_fee_rate_goes_up() {
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let db = Sqlite::new_ephemeral_db().unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let address = client.get_new_segwit_confidential_address().await.unwrap();
+        let parent_txid = client
+            .send_asset_to_address(&address, Amount::ONE_BTC, Some(btc_asset_id))
+            .await
+            .unwrap();
+
+        bump_fee(
+            &client,
+            &db,
+            btc_asset_id,
+            parent_txid,
+            Amount::from_sat(100),
+        )
+        .await
+        .unwrap();
+        bump_fee(
+            &client,
+            &db,
+            btc_asset_id,
+            parent_txid,
+            Amount::from_sat(100),
+        )
+        .await
+        .unwrap();
+
+        let bumps = db
+            .do_in_transaction(|conn| queries::get_fee_bumps(conn, parent_txid))
+            .await
+            .unwrap();
+        assert_eq!(bumps.len(), 1);
+
+        bump_fee(
+            &client,
+            &db,
+            btc_asset_id,
+            parent_txid,
+            Amount::from_sat(200),
+        )
+        .await
+        .unwrap();
+
+        let bumps = db
+            .do_in_transaction(|conn| queries::get_fee_bumps(conn, parent_txid))
+            .await
+            .unwrap();
+        assert_eq!(bumps.len(), 2);
+    }
+}
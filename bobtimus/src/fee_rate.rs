@@ -0,0 +1,100 @@
+use crate::elements_rpc::{Client, ElementsRpc};
+use anyhow::{bail, Context, Result};
+use elements::bitcoin::Amount;
+use serde::Serialize;
+
+/// Fee rate, in satoshi per virtual byte, which we use for the
+/// transactions we build.
+///
+/// The rate is estimated by elementsd and kept between `floor` and
+/// `ceiling`. If elementsd cannot produce an estimate, e.g. because
+/// it has not seen enough blocks yet, we fall back to `floor`.
+#[derive(Clone)]
+pub struct Service {
+    elementsd: Client,
+    conf_target: u16,
+    floor: Amount,
+    ceiling: Amount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FeeRate {
+    /// Fee rate in satoshi per virtual byte.
+    #[serde(with = "::elements::bitcoin::util::amount::serde::as_sat")]
+    pub fee_rate: Amount,
+}
+
+impl Service {
+    pub fn new(
+        elementsd: Client,
+        conf_target: u16,
+        floor: Amount,
+        ceiling: Amount,
+    ) -> Result<Self> {
+        if conf_target == 0 {
+            bail!("confirmation target must be at least 1 block")
+        }
+
+        if floor > ceiling {
+            bail!(
+                "fee rate floor {} sat/vB is above ceiling {} sat/vB",
+                floor.as_sat(),
+                ceiling.as_sat()
+            )
+        }
+
+        Ok(Self {
+            elementsd,
+            conf_target,
+            floor,
+            ceiling,
+        })
+    }
+
+    pub async fn fee_rate(&self) -> Result<Amount> {
+        let estimate = self
+            .elementsd
+            .estimatesmartfee(self.conf_target)
+            .await
+            .context("failed to estimate fee rate")?;
+
+        let fee_rate = match estimate.feerate {
+            Some(btc_per_kvb) => sat_per_vbyte(btc_per_kvb)?,
+            None => {
+                tracing::debug!(
+                    "no fee estimate available for target {}, using floor: {:?}",
+                    self.conf_target,
+                    estimate.errors
+                );
+                self.floor
+            }
+        };
+
+        Ok(fee_rate.max(self.floor).min(self.ceiling))
+    }
+}
+
+/// Convert a fee rate in BTC per kilo virtual byte, as returned by
+/// elementsd, into satoshi per virtual byte, rounding up.
+fn sat_per_vbyte(btc_per_kvb: f64) -> Result<Amount> {
+    let sat_per_kvb = Amount::from_btc(btc_per_kvb)?.as_sat();
+
+    Ok(Amount::from_sat((sat_per_kvb + 999) / 1000))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_btc_per_kvb_to_sat_per_vbyte() {
+        assert_eq!(sat_per_vbyte(0.0001).unwrap(), Amount::from_sat(10));
+        assert_eq!(sat_per_vbyte(0.00001).unwrap(), Amount::from_sat(1));
+    }
+
+    #[test]
+    fn rounds_fractional_sat_per_vbyte_up() {
+        assert_eq!(sat_per_vbyte(0.000001).unwrap(), Amount::from_sat(1));
+        assert_eq!(sat_per_vbyte(0.000015).unwrap(), Amount::from_sat(2));
+    }
+}
@@ -10,7 +10,19 @@ pub struct Service(Receiver<Rate>);
 
 impl Service {
     pub fn new() -> Self {
-        let data = fixed_rate();
+        Self::with_rate(fixed_rate())
+    }
+
+    /// A rate service which always publishes the given nominal prices.
+    pub fn from_nominal(ask: f64, bid: f64) -> anyhow::Result<Self> {
+        Ok(Self::with_rate(Rate {
+            ask: LiquidUsdt::try_from(ask)?,
+            bid: LiquidUsdt::try_from(bid)?,
+        }))
+    }
+
+    /// A rate service which always publishes `data`.
+    pub fn with_rate(data: Rate) -> Self {
         let (tx, rx) = watch::channel(data);
 
         tokio::spawn(async move {
@@ -37,7 +49,7 @@ impl Default for Service {
 
 impl LatestRate for Service {
     fn latest_rate(&mut self) -> Rate {
-        fixed_rate()
+        *self.0.borrow()
     }
 }
 
@@ -1,13 +1,22 @@
-use crate::{problem, Bobtimus, CreateSwapPayload, LatestRate, RateSubscription};
+use crate::{
+    batch::{BatchingDisabled, SwapBatcher, UnknownBatch},
+    fee_rate::FeeRate,
+    oracle::{Attestation, Oracle, UnknownAttestation},
+    pair::PairId,
+    problem,
+    quote::{CreateQuotePayload, Direction, SimulateSwapPayload},
+    Bobtimus, CreateSwapPayload, LatestRate, RateSubscription, UnknownPair,
+};
 use anyhow::Context;
+use baru::loan::LoanResponse;
 use elements::{
     encode::serialize_hex,
     secp256k1_zkp::rand::{thread_rng, CryptoRng, RngCore},
-    Transaction,
+    Transaction, TxOutSecrets, Txid,
 };
 use futures::{StreamExt, TryStreamExt};
 use rust_embed::RustEmbed;
-use std::{error::Error, fmt, sync::Arc};
+use std::{collections::HashMap, convert::Infallible, error::Error, fmt, sync::Arc};
 use tokio::sync::Mutex;
 use warp::{
     filters::BoxedFilter,
@@ -17,13 +26,53 @@ use warp::{
     Filter, Rejection, Reply,
 };
 
+/// Media type with which clients ask for transactions to be
+/// exchanged as base64-encoded PSETs instead of hex-encoded raw
+/// transactions.
+const PSET_MEDIA_TYPE: &str = "application/pset";
+
+/// Header carrying the JSON-encoded secrets of the blinded output
+/// which pays us the service fee of a swap, if it has one.
+const SERVICE_FEE_SECRETS_HEADER: &str = "service-fee-secrets";
+
+/// How the transactions in our responses are encoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransactionFormat {
+    Hex,
+    Pset,
+}
+
+impl TransactionFormat {
+    fn from_accept(accept: Option<&str>) -> Self {
+        match accept {
+            Some(accept)
+                if accept
+                    .split(',')
+                    .any(|media_type| media_type.trim().starts_with(PSET_MEDIA_TYPE)) =>
+            {
+                TransactionFormat::Pset
+            }
+            _ => TransactionFormat::Hex,
+        }
+    }
+}
+
+/// Negotiate the transaction format through the `Accept` header,
+/// defaulting to raw transaction hex.
+fn transaction_format() -> impl Filter<Extract = (TransactionFormat,), Error = Infallible> + Clone {
+    warp::header::optional::<String>("accept")
+        .map(|accept: Option<String>| TransactionFormat::from_accept(accept.as_deref()))
+}
+
 #[derive(RustEmbed)]
 #[folder = "../waves/dist/"]
 struct Waves;
 
 pub fn routes<R, RS>(
     bobtimus: Arc<Mutex<Bobtimus<R, RS>>>,
-    latest_rate_subscription: RateSubscription,
+    rate_subscriptions: HashMap<PairId, RateSubscription>,
+    oracle: Oracle,
+    batcher: Option<SwapBatcher>,
 ) -> BoxedFilter<(impl Reply,)>
 where
     R: RngCore + CryptoRng + Clone + Send + Sync + 'static,
@@ -40,48 +89,165 @@ where
     sse_headers.insert("Cache-Control", HeaderValue::from_static("no-transform"));
 
     let latest_rate = warp::get()
-        .and(warp::path!("api" / "rate" / "lbtc-lusdt"))
-        .map(move || latest_rate(latest_rate_subscription.clone()))
+        .and(warp::path!("api" / "rate" / PairId))
+        .and_then(move |pair_id: PairId| {
+            let subscription = rate_subscriptions.get(&pair_id).cloned();
+            async move {
+                let subscription = subscription
+                    .ok_or(UnknownPair(pair_id))
+                    .map_err(anyhow::Error::from)
+                    .map_err(problem::from_anyhow)
+                    .map_err(warp::reject::custom)?;
+
+                Result::<_, Rejection>::Ok(latest_rate(subscription))
+            }
+        })
         .with(warp::reply::with::headers(sse_headers));
 
-    let create_buy_swap = warp::post()
-        .and(warp::path!("api" / "swap" / "lbtc-lusdt" / "buy"))
+    let fee_rate = warp::get().and(warp::path!("api" / "fee-rate")).and_then({
+        let bobtimus = bobtimus.clone();
+        move || {
+            let bobtimus = bobtimus.clone();
+            async move {
+                let bobtimus = bobtimus.lock().await;
+                fee_rate(&bobtimus).await
+            }
+        }
+    });
+
+    let trade_limits = warp::get()
+        .and(warp::path!("api" / "limits" / PairId))
+        .and_then({
+            let bobtimus = bobtimus.clone();
+            move |pair_id| {
+                let bobtimus = bobtimus.clone();
+                async move {
+                    let bobtimus = bobtimus.lock().await;
+                    trade_limits(&bobtimus, pair_id)
+                }
+            }
+        });
+
+    let create_quote = warp::post()
+        .and(warp::path!("api" / "quote" / PairId))
         .and(warp::body::json())
         .and_then({
             let bobtimus = bobtimus.clone();
-            move |payload| {
+            move |pair_id, payload| {
+                let bobtimus = bobtimus.clone();
+                async move {
+                    let mut bobtimus = bobtimus.lock().await;
+                    create_quote(&mut bobtimus, pair_id, payload).await
+                }
+            }
+        });
+
+    let create_swap = warp::post()
+        .and(warp::path!("api" / "swap" / PairId / Direction))
+        .and(transaction_format())
+        .and(warp::body::json())
+        .and_then({
+            let bobtimus = bobtimus.clone();
+            move |pair_id, direction, format, payload| {
                 let bobtimus = bobtimus.clone();
                 async move {
                     let mut bobtimus = bobtimus.lock().await;
-                    create_buy_swap(&mut bobtimus, payload).await
+                    create_swap(&mut bobtimus, pair_id, direction, format, payload).await
+                }
+            }
+        });
+
+    let simulate_swap = warp::post()
+        .and(warp::path!(
+            "api" / "swap" / PairId / Direction / "simulate"
+        ))
+        .and(warp::body::json())
+        .and_then({
+            let bobtimus = bobtimus.clone();
+            move |pair_id, direction, payload| {
+                let bobtimus = bobtimus.clone();
+                async move {
+                    let mut bobtimus = bobtimus.lock().await;
+                    simulate_swap(&mut bobtimus, pair_id, direction, payload).await
+                }
+            }
+        });
+
+    let create_batched_swap = warp::post()
+        .and(warp::path!("api" / "swap" / PairId / Direction / "batch"))
+        .and(transaction_format())
+        .and(warp::body::json())
+        .and_then({
+            let bobtimus = bobtimus.clone();
+            let batcher = batcher.clone();
+            move |pair_id, direction, format, payload| {
+                let bobtimus = bobtimus.clone();
+                let batcher = batcher.clone();
+                async move {
+                    create_batched_swap(&bobtimus, batcher, pair_id, direction, format, payload)
+                        .await
+                        .map_err(problem::from_anyhow)
+                        .map_err(warp::reject::custom)
+                }
+            }
+        });
+
+    let batch_status = warp::get()
+        .and(warp::path!("api" / "swap" / "batch" / Txid))
+        .and_then({
+            let batcher = batcher.clone();
+            move |txid| {
+                let batcher = batcher.clone();
+                async move {
+                    batch_status(batcher, txid)
+                        .await
+                        .map_err(problem::from_anyhow)
+                        .map_err(warp::reject::custom)
                 }
             }
         });
 
-    let create_sell_swap = warp::post()
-        .and(warp::path!("api" / "swap" / "lbtc-lusdt" / "sell"))
+    let sign_batched_swap = warp::post()
+        .and(warp::path!("api" / "swap" / "batch"))
         .and(warp::body::json())
         .and_then({
             let bobtimus = bobtimus.clone();
             move |payload| {
+                let bobtimus = bobtimus.clone();
+                let batcher = batcher.clone();
+                async move {
+                    sign_batched_swap(&bobtimus, batcher, payload)
+                        .await
+                        .map_err(problem::from_anyhow)
+                        .map_err(warp::reject::custom)
+                }
+            }
+        });
+
+    let loan_offer = warp::get()
+        .and(warp::path!("api" / "loan" / "lbtc-lusdt" / "offer"))
+        .and_then({
+            let bobtimus = bobtimus.clone();
+            move || {
                 let bobtimus = bobtimus.clone();
                 async move {
                     let mut bobtimus = bobtimus.lock().await;
-                    create_sell_swap(&mut bobtimus, payload).await
+                    loan_offer(&mut bobtimus)
                 }
             }
         });
 
     let create_loan = warp::post()
         .and(warp::path!("api" / "loan" / "lbtc-lusdt"))
+        .and(transaction_format())
         .and(warp::body::json())
         .and_then({
             let bobtimus = bobtimus.clone();
-            move |payload| {
+            move |format, payload| {
                 let bobtimus = bobtimus.clone();
                 async move {
                     let mut bobtimus = bobtimus.lock().await;
-                    create_loan(&mut bobtimus, payload).await
+                    create_loan(&mut bobtimus, format, payload).await
                 }
             }
         });
@@ -101,19 +267,104 @@ where
             }
         });
 
+    let oracle_key = warp::get().and(warp::path!("api" / "oracle" / "key")).map({
+        let oracle = oracle.clone();
+        move || warp::reply::json(&oracle.key())
+    });
+
+    let latest_attestation = warp::get()
+        .and(warp::path!("api" / "oracle" / "attestation" / PairId))
+        .and_then({
+            let oracle = oracle.clone();
+            move |pair_id: PairId| {
+                let oracle = oracle.clone();
+                async move {
+                    let attestation = oracle.latest_attestation(pair_id.clone()).await;
+                    attestation_reply(attestation, || UnknownPair(pair_id).into())
+                }
+            }
+        });
+
+    let attestation = warp::get()
+        .and(warp::path!("api" / "oracle" / "attestations" / u64))
+        .and_then(move |sequence| {
+            let oracle = oracle.clone();
+            async move {
+                let attestation = oracle.attestation(sequence).await;
+                attestation_reply(attestation, || UnknownAttestation(sequence).into())
+            }
+        });
+
     latest_rate
-        .or(create_sell_swap)
-        .or(create_buy_swap)
+        .or(fee_rate)
+        .or(trade_limits)
+        .or(create_quote)
+        .or(create_swap)
+        .or(simulate_swap)
+        .or(create_batched_swap)
+        .or(sign_batched_swap)
+        .or(batch_status)
+        .or(loan_offer)
         .or(create_loan)
         .or(finalize_loan)
+        .or(oracle_key)
+        .or(latest_attestation)
+        .or(attestation)
         .or(waves_resources)
         .or(index_html)
         .recover(problem::unpack_problem)
         .boxed()
 }
 
-async fn create_buy_swap<R, RS>(
+/// Routes which expose our internal state, e.g. the loan book.
+///
+/// They are not authenticated and must only be served on a port which
+/// is not reachable from outside.
+pub fn admin_routes<R, RS>(bobtimus: Arc<Mutex<Bobtimus<R, RS>>>) -> BoxedFilter<(impl Reply,)>
+where
+    R: RngCore + CryptoRng + Clone + Send + Sync + 'static,
+    RS: LatestRate + Clone + Send + Sync + 'static,
+{
+    let loan_book = warp::get()
+        .and(warp::path!("api" / "admin" / "loans"))
+        .and_then(move || {
+            let bobtimus = bobtimus.clone();
+            async move {
+                let mut bobtimus = bobtimus.lock().await;
+                loan_book(&mut bobtimus).await
+            }
+        });
+
+    loan_book.recover(problem::unpack_problem).boxed()
+}
+
+async fn fee_rate<R, RS>(bobtimus: &Bobtimus<R, RS>) -> Result<impl Reply, Rejection> {
+    bobtimus
+        .fee_rate_service
+        .fee_rate()
+        .await
+        .map(|fee_rate| warp::reply::json(&FeeRate { fee_rate }))
+        .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)
+}
+
+fn trade_limits<R, RS>(
+    bobtimus: &Bobtimus<R, RS>,
+    pair_id: PairId,
+) -> Result<impl Reply, Rejection> {
+    bobtimus
+        .pairs
+        .get(&pair_id)
+        .map(|pair| warp::reply::json(&pair.limits))
+        .ok_or(UnknownPair(pair_id))
+        .map_err(anyhow::Error::from)
+        .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)
+}
+
+async fn create_quote<R, RS>(
     bobtimus: &mut Bobtimus<R, RS>,
+    pair_id: PairId,
     payload: serde_json::Value,
 ) -> Result<impl Reply, Rejection>
 where
@@ -121,22 +372,23 @@ where
     RS: LatestRate,
 {
     let payload = payload.to_string();
-    let payload: CreateSwapPayload = serde_json::from_str(&payload)
+    let payload: CreateQuotePayload = serde_json::from_str(&payload)
         .map_err(anyhow::Error::from)
         .map_err(problem::from_anyhow)
         .map_err(warp::reject::custom)?;
 
     bobtimus
-        .handle_create_buy_swap(payload)
-        .await
-        .map(|transaction| serialize_hex(&transaction))
-        .map_err(anyhow::Error::from)
+        .handle_create_quote(&pair_id, payload)
+        .map(|quote| warp::reply::json(&quote))
         .map_err(problem::from_anyhow)
         .map_err(warp::reject::custom)
 }
 
-async fn create_sell_swap<R, RS>(
+async fn create_swap<R, RS>(
     bobtimus: &mut Bobtimus<R, RS>,
+    pair_id: PairId,
+    direction: Direction,
+    format: TransactionFormat,
     payload: serde_json::Value,
 ) -> Result<impl Reply, Rejection>
 where
@@ -149,17 +401,190 @@ where
         .map_err(problem::from_anyhow)
         .map_err(warp::reject::custom)?;
 
-    bobtimus
-        .handle_create_sell_swap(payload)
+    let (transaction, service_fee_secrets) = bobtimus
+        .handle_create_swap(&pair_id, direction, payload)
         .await
-        .map(|transaction| serialize_hex(&transaction))
+        .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)?;
+
+    let reply = match format {
+        TransactionFormat::Hex => Ok(serialize_hex(&transaction).into_response()),
+        TransactionFormat::Pset => swap_pset(bobtimus, &transaction)
+            .await
+            .map(|pset| warp::reply::with_header(pset, "content-type", PSET_MEDIA_TYPE))
+            .map(Reply::into_response),
+    };
+
+    reply
+        .and_then(|reply| with_service_fee_secrets(reply, service_fee_secrets))
+        .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)
+}
+
+/// Hand out the secrets of the output paying us the service fee, so
+/// that the taker can check it even though it is blinded.
+fn with_service_fee_secrets(
+    mut reply: Response,
+    service_fee_secrets: Option<TxOutSecrets>,
+) -> anyhow::Result<Response> {
+    if let Some(secrets) = service_fee_secrets {
+        let secrets = serde_json::to_string(&secrets)?;
+        reply
+            .headers_mut()
+            .insert(SERVICE_FEE_SECRETS_HEADER, HeaderValue::from_str(&secrets)?);
+    }
+
+    Ok(reply)
+}
+
+/// Convert our signed swap transaction into a PSET.
+///
+/// Converting discards our signatures, so we sign our inputs again.
+async fn swap_pset<R, RS>(
+    bobtimus: &Bobtimus<R, RS>,
+    transaction: &Transaction,
+) -> anyhow::Result<String> {
+    let pset = bobtimus.elementsd.convert_to_pset(transaction).await?;
+
+    bobtimus.elementsd.sign_pset(pset).await
+}
+
+async fn simulate_swap<R, RS>(
+    bobtimus: &mut Bobtimus<R, RS>,
+    pair_id: PairId,
+    direction: Direction,
+    payload: serde_json::Value,
+) -> Result<impl Reply, Rejection>
+where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    let payload = payload.to_string();
+    let payload: SimulateSwapPayload = serde_json::from_str(&payload)
         .map_err(anyhow::Error::from)
         .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)?;
+
+    bobtimus
+        .handle_simulate_swap(&pair_id, direction, payload)
+        .await
+        .map(|simulation| warp::reply::json(&simulation))
+        .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)
+}
+
+/// Include Alice's swap in the next batch and hand her the unsigned
+/// batch transaction.
+///
+/// Bobtimus is only locked while pricing the swap, since building the
+/// batch requires it too.
+async fn create_batched_swap<R, RS>(
+    bobtimus: &Mutex<Bobtimus<R, RS>>,
+    batcher: Option<SwapBatcher>,
+    pair_id: PairId,
+    direction: Direction,
+    format: TransactionFormat,
+    payload: serde_json::Value,
+) -> anyhow::Result<impl Reply>
+where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    let batcher = batcher.ok_or(BatchingDisabled)?;
+    let payload: CreateSwapPayload = serde_json::from_value(payload)?;
+
+    let swap = bobtimus
+        .lock()
+        .await
+        .prepare_swap(&pair_id, direction, payload)
+        .await?;
+    let (transaction, service_fee_secrets) = batcher.submit(swap).await?;
+
+    let reply = match format {
+        TransactionFormat::Hex => serialize_hex(&transaction).into_response(),
+        TransactionFormat::Pset => {
+            // Nobody has signed the batch transaction yet
+            let elementsd = bobtimus.lock().await.elementsd.clone();
+            let pset = elementsd.convert_to_pset(&transaction).await?;
+
+            warp::reply::with_header(pset, "content-type", PSET_MEDIA_TYPE).into_response()
+        }
+    };
+
+    with_service_fee_secrets(reply, service_fee_secrets)
+}
+
+/// The batch transaction with a taker's signatures, either as raw
+/// transaction hex or as a PSET.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum SignBatchedSwapPayload {
+    Hex {
+        #[serde(with = "baru::loan::transaction_as_string")]
+        tx_hex: Transaction,
+    },
+    Pset {
+        pset: String,
+    },
+}
+
+async fn sign_batched_swap<R, RS>(
+    bobtimus: &Mutex<Bobtimus<R, RS>>,
+    batcher: Option<SwapBatcher>,
+    payload: serde_json::Value,
+) -> anyhow::Result<impl Reply>
+where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    let batcher = batcher.ok_or(BatchingDisabled)?;
+    let payload: SignBatchedSwapPayload = serde_json::from_value(payload)?;
+    let transaction = match payload {
+        SignBatchedSwapPayload::Hex { tx_hex } => tx_hex,
+        SignBatchedSwapPayload::Pset { pset } => {
+            let elementsd = bobtimus.lock().await.elementsd.clone();
+            let (txid, witnesses) = elementsd.pset_witnesses(pset).await?;
+
+            let mut transaction = batcher.transaction(txid).await.ok_or(UnknownBatch(txid))?;
+            for (txin, witness) in transaction.input.iter_mut().zip(witnesses) {
+                if let Some(witness) = witness {
+                    txin.witness.script_witness = witness;
+                }
+            }
+
+            transaction
+        }
+    };
+
+    let status = batcher.add_signatures(bobtimus, transaction).await?;
+
+    Ok(warp::reply::json(&status))
+}
+
+/// How far a batch is from being broadcast, and the transaction to
+/// sign instead if a taker was dropped from it.
+async fn batch_status(batcher: Option<SwapBatcher>, txid: Txid) -> anyhow::Result<impl Reply> {
+    let batcher = batcher.ok_or(BatchingDisabled)?;
+    let status = batcher.status(txid).await?;
+
+    Ok(warp::reply::json(&status))
+}
+
+fn loan_offer<R, RS>(bobtimus: &mut Bobtimus<R, RS>) -> Result<impl Reply, Rejection>
+where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    bobtimus
+        .handle_loan_offer()
+        .map(|offer| warp::reply::json(&offer))
+        .map_err(problem::from_anyhow)
         .map_err(warp::reject::custom)
 }
 
 async fn create_loan<R, RS>(
     bobtimus: &mut Bobtimus<R, RS>,
+    format: TransactionFormat,
     payload: serde_json::Value,
 ) -> Result<impl Reply, Rejection>
 where
@@ -172,19 +597,53 @@ where
         .map_err(problem::from_anyhow)
         .map_err(warp::reject::custom)?;
 
-    bobtimus
+    let loan_response = bobtimus
         .handle_loan_request(payload)
         .await
-        .map(|loan_response| warp::reply::json(&loan_response))
-        .map_err(anyhow::Error::from)
         .map_err(problem::from_anyhow)
-        .map_err(warp::reject::custom)
+        .map_err(warp::reject::custom)?;
+
+    match format {
+        TransactionFormat::Hex => Ok(warp::reply::json(&loan_response)),
+        TransactionFormat::Pset => loan_response_with_pset(bobtimus, &loan_response)
+            .await
+            .map(|loan_response| warp::reply::json(&loan_response))
+            .map_err(problem::from_anyhow)
+            .map_err(warp::reject::custom),
+    }
 }
 
+/// Our loan response, with the loan transaction encoded as a PSET.
+///
+/// We have not signed the loan transaction at this stage, so there
+/// are no signatures to preserve.
+async fn loan_response_with_pset<R, RS>(
+    bobtimus: &Bobtimus<R, RS>,
+    loan_response: &LoanResponse,
+) -> anyhow::Result<serde_json::Value> {
+    let pset = bobtimus
+        .elementsd
+        .convert_to_pset(&loan_response.transaction)
+        .await?;
+
+    let mut loan_response = serde_json::to_value(loan_response)?;
+    loan_response["transaction"] = serde_json::Value::String(pset);
+
+    Ok(loan_response)
+}
+
+/// The loan transaction signed by the borrower, either as raw
+/// transaction hex or as a PSET.
 #[derive(serde::Deserialize)]
-struct FinalizeLoanPayload {
-    #[serde(with = "baru::loan::transaction_as_string")]
-    tx_hex: Transaction,
+#[serde(untagged)]
+enum FinalizeLoanPayload {
+    Hex {
+        #[serde(with = "baru::loan::transaction_as_string")]
+        tx_hex: Transaction,
+    },
+    Pset {
+        pset: String,
+    },
 }
 
 async fn finalize_loan<R, RS>(
@@ -196,12 +655,52 @@ where
     RS: LatestRate,
 {
     let payload: FinalizeLoanPayload = serde_json::from_value(payload)?;
+    let transaction = match payload {
+        FinalizeLoanPayload::Hex { tx_hex } => tx_hex,
+        FinalizeLoanPayload::Pset { pset } => {
+            // Our wallet must only sign loan transactions we have
+            // offered, the txid of which does not depend on signatures
+            let loan_txid = bobtimus.elementsd.pset_txid(pset.clone()).await?;
+            bobtimus.ensure_pending_loan(loan_txid)?;
+
+            bobtimus
+                .elementsd
+                .finalize_pset(pset)
+                .await
+                .context("failed to finalize loan PSET")?
+        }
+    };
+
     bobtimus
-        .finalize_loan(payload.tx_hex)
+        .finalize_loan(transaction)
         .await
         .map(|loan_response| warp::reply::json(&loan_response))
 }
 
+async fn loan_book<R, RS>(bobtimus: &mut Bobtimus<R, RS>) -> Result<impl Reply, Rejection>
+where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    bobtimus
+        .handle_loan_book()
+        .await
+        .map(|loan_book| warp::reply::json(&loan_book))
+        .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)
+}
+
+fn attestation_reply(
+    attestation: anyhow::Result<Option<Attestation>>,
+    not_found: impl FnOnce() -> anyhow::Error,
+) -> Result<impl Reply, Rejection> {
+    attestation
+        .and_then(|attestation| attestation.ok_or_else(not_found))
+        .map(|attestation| warp::reply::json(&attestation))
+        .map_err(problem::from_anyhow)
+        .map_err(warp::reject::custom)
+}
+
 fn latest_rate(subscription: RateSubscription) -> impl Reply {
     let stream = subscription
         .into_stream()
@@ -264,3 +763,29 @@ fn serve_impl(path: &str) -> Result<impl Reply, Rejection> {
     );
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pset_is_negotiated_through_accept_header() {
+        assert_eq!(
+            TransactionFormat::from_accept(Some("application/pset")),
+            TransactionFormat::Pset
+        );
+        assert_eq!(
+            TransactionFormat::from_accept(Some("application/json, application/pset;q=0.9")),
+            TransactionFormat::Pset
+        );
+    }
+
+    #[test]
+    fn raw_transaction_hex_is_the_default() {
+        assert_eq!(TransactionFormat::from_accept(None), TransactionFormat::Hex);
+        assert_eq!(
+            TransactionFormat::from_accept(Some("*/*")),
+            TransactionFormat::Hex
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    elements_rpc::Client, pair::TradingPairConfig, LatestRate, LiquidUsdt, Rate, RateSubscription,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use elements::{bitcoin::Amount, AssetId};
+use futures::StreamExt;
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal,
+};
+use std::time::Duration;
+use tokio::sync::watch::{self, Receiver};
+
+/// The funds of a trading pair we currently hold in our elementsd
+/// wallet.
+///
+/// These are on-chain amounts, in which every asset has the maximum
+/// precision of 8 decimal places whatever the precision of the
+/// trading pair, just like the rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inventory {
+    pub base: Amount,
+    pub quote: LiquidUsdt,
+}
+
+impl Inventory {
+    pub async fn fetch(
+        elementsd: &Client,
+        base_asset_id: AssetId,
+        quote_asset_id: AssetId,
+    ) -> Result<Self> {
+        let base = elementsd
+            .get_balance(base_asset_id)
+            .await
+            .with_context(|| format!("failed to get balance of {}", base_asset_id))?;
+        let quote = elementsd
+            .get_balance(quote_asset_id)
+            .await
+            .with_context(|| format!("failed to get balance of {}", quote_asset_id))?;
+
+        Ok(Self {
+            base,
+            quote: LiquidUsdt::from_satodollar(quote.as_sat()),
+        })
+    }
+}
+
+/// Parameters used to skew the rate depending on our inventory.
+///
+/// If we hold more of the base asset than the `target_base_ratio`
+/// (measured by value), both the ask and the bid are lowered to
+/// attract buyers and discourage sellers. If we hold less, they are
+/// raised. The rate is moved by at most `max_skew` in either
+/// direction, which is reached when one side of our inventory is
+/// completely depleted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InventorySkew {
+    target_base_ratio: Decimal,
+    max_skew: Decimal,
+}
+
+impl InventorySkew {
+    pub fn new(target_base_ratio: Decimal, max_skew: Decimal) -> Result<Self> {
+        if target_base_ratio <= Decimal::ZERO || target_base_ratio >= Decimal::ONE {
+            bail!(
+                "target ratio of the base asset must be between 0 and 1 exclusive, got {}",
+                target_base_ratio
+            )
+        }
+
+        if max_skew < Decimal::ZERO || max_skew >= Decimal::ONE {
+            bail!(
+                "maximum skew must be between 0 inclusive and 1 exclusive, got {}",
+                max_skew
+            )
+        }
+
+        Ok(Self {
+            target_base_ratio,
+            max_skew,
+        })
+    }
+
+    /// The skew of the trading pair configured by `config`, which
+    /// aims for `default_target_base_ratio` unless it sets its own
+    /// target.
+    pub fn for_pair(
+        config: &TradingPairConfig,
+        default_target_base_ratio: Decimal,
+        max_skew: Decimal,
+    ) -> Result<Self> {
+        let target_base_ratio = match config.target_base_ratio {
+            Some(ratio) => Decimal::from_f64(ratio)
+                .with_context(|| format!("invalid target base ratio {}", ratio))?,
+            None => default_target_base_ratio,
+        };
+
+        Self::new(target_base_ratio, max_skew)
+            .with_context(|| format!("invalid skew for trading pair {}", config.id))
+    }
+
+    pub fn apply(&self, rate: Rate, inventory: Inventory) -> Result<Rate> {
+        let mid = (Decimal::from(rate.ask.as_satodollar())
+            + Decimal::from(rate.bid.as_satodollar()))
+            / Decimal::from(2);
+
+        let base_value =
+            Decimal::from(inventory.base.as_sat()) * mid / Decimal::from(Amount::ONE_BTC.as_sat());
+        let quote_value = Decimal::from(inventory.quote.as_satodollar());
+
+        let total_value = base_value + quote_value;
+        if total_value.is_zero() {
+            return Ok(rate);
+        }
+
+        let base_ratio = base_value / total_value;
+
+        // normalised to [-1, 1], with 0 meaning we are on target
+        let deviation = if base_ratio > self.target_base_ratio {
+            (base_ratio - self.target_base_ratio) / (Decimal::ONE - self.target_base_ratio)
+        } else {
+            (base_ratio - self.target_base_ratio) / self.target_base_ratio
+        };
+        let factor = Decimal::ONE - deviation * self.max_skew;
+
+        Ok(Rate {
+            ask: scale(rate.ask, factor)?,
+            bid: scale(rate.bid, factor)?,
+        })
+    }
+}
+
+fn scale(amount: LiquidUsdt, factor: Decimal) -> Result<LiquidUsdt> {
+    let satodollars = (Decimal::from(amount.as_satodollar()) * factor)
+        .round()
+        .to_u64()
+        .ok_or_else(|| anyhow!("decimal cannot be represented as u64"))?;
+
+    Ok(LiquidUsdt::from_satodollar(satodollars))
+}
+
+/// A rate service which skews the rate of an underlying rate
+/// subscription based on our current inventory.
+#[derive(Clone)]
+pub struct RateService {
+    receiver: Receiver<Rate>,
+}
+
+impl LatestRate for RateService {
+    fn latest_rate(&mut self) -> Rate {
+        *self.receiver.borrow()
+    }
+}
+
+impl RateService {
+    pub fn new(
+        rate_subscription: RateSubscription,
+        elementsd: Client,
+        base_asset_id: AssetId,
+        quote_asset_id: AssetId,
+        skew: InventorySkew,
+        refresh_interval: Duration,
+    ) -> Self {
+        let (tx, rx) = watch::channel(Rate::ZERO);
+
+        tokio::spawn(async move {
+            let rates = rate_subscription.into_stream();
+            tokio::pin!(rates);
+
+            let mut interval = tokio::time::interval(refresh_interval);
+            let mut latest_rate = None;
+            let mut inventory = None;
+
+            loop {
+                tokio::select! {
+                    rate = rates.next() => match rate {
+                        Some(Ok(rate)) => latest_rate = Some(rate),
+                        Some(Err(e)) => {
+                            tracing::error!("could not get rate update: {:#}", e);
+                            continue;
+                        }
+                        None => break,
+                    },
+                    _ = interval.tick() => {
+                        match Inventory::fetch(&elementsd, base_asset_id, quote_asset_id).await {
+                            Ok(latest_inventory) => inventory = Some(latest_inventory),
+                            Err(e) => {
+                                tracing::warn!("could not update inventory: {:#}", e);
+                                continue;
+                            }
+                        }
+                    }
+                }
+
+                let rate = match (latest_rate, inventory) {
+                    (Some(rate), Some(inventory)) => match skew.apply(rate, inventory) {
+                        Ok(skewed_rate) => skewed_rate,
+                        Err(e) => {
+                            tracing::error!("could not skew rate: {:#}", e);
+                            rate
+                        }
+                    },
+                    (Some(rate), None) => rate,
+                    (None, _) => continue,
+                };
+
+                let _ = tx.send(rate);
+            }
+        });
+
+        Self { receiver: rx }
+    }
+
+    pub fn subscribe(&self) -> RateSubscription {
+        RateSubscription::from(self.receiver.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{convert::TryFrom, str::FromStr};
+
+    fn rate() -> Rate {
+        Rate {
+            ask: LiquidUsdt::try_from(20_100.0).unwrap(),
+            bid: LiquidUsdt::try_from(19_900.0).unwrap(),
+        }
+    }
+
+    fn skew() -> InventorySkew {
+        InventorySkew::new(
+            Decimal::from_str("0.5").unwrap(),
+            Decimal::from_str("0.01").unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn balanced_inventory_does_not_skew_rate() {
+        let inventory = Inventory {
+            base: Amount::ONE_BTC,
+            quote: LiquidUsdt::try_from(20_000.0).unwrap(),
+        };
+
+        let skewed = skew().apply(rate(), inventory).unwrap();
+
+        assert_eq!(skewed, rate())
+    }
+
+    #[test]
+    fn only_btc_in_inventory_lowers_rate_by_max_skew() {
+        let inventory = Inventory {
+            base: Amount::ONE_BTC,
+            quote: LiquidUsdt::from_satodollar(0),
+        };
+
+        let skewed = skew().apply(rate(), inventory).unwrap();
+
+        assert_eq!(
+            skewed,
+            Rate {
+                ask: LiquidUsdt::try_from(19_899.0).unwrap(),
+                bid: LiquidUsdt::try_from(19_701.0).unwrap(),
+            }
+        )
+    }
+
+    #[test]
+    fn only_usdt_in_inventory_raises_rate_by_max_skew() {
+        let inventory = Inventory {
+            base: Amount::ZERO,
+            quote: LiquidUsdt::try_from(20_000.0).unwrap(),
+        };
+
+        let skewed = skew().apply(rate(), inventory).unwrap();
+
+        assert_eq!(
+            skewed,
+            Rate {
+                ask: LiquidUsdt::try_from(20_301.0).unwrap(),
+                bid: LiquidUsdt::try_from(20_099.0).unwrap(),
+            }
+        )
+    }
+
+    #[test]
+    fn empty_inventory_does_not_skew_rate() {
+        let inventory = Inventory {
+            base: Amount::ZERO,
+            quote: LiquidUsdt::from_satodollar(0),
+        };
+
+        let skewed = skew().apply(rate(), inventory).unwrap();
+
+        assert_eq!(skewed, rate())
+    }
+
+    #[test]
+    fn trading_pair_can_set_its_own_target_ratio() {
+        let config = serde_json::from_str::<TradingPairConfig>(
+            r#"{
+                "id": "lbtc-lusdt",
+                "quote_asset_id": "ce091c998b83c78bb71a632313ba3760f1763d9cfcffae02258ffa9865a37bd2",
+                "rate_source": { "fixed": { "ask": 20100.0, "bid": 19900.0 } },
+                "limits": { "buy": { "min": 0, "max": 1 }, "sell": { "min": 0, "max": 1 } },
+                "target_base_ratio": 0.25
+            }"#,
+        )
+        .unwrap();
+        let max_skew = Decimal::from_str("0.01").unwrap();
+
+        let skew =
+            InventorySkew::for_pair(&config, Decimal::from_str("0.5").unwrap(), max_skew).unwrap();
+
+        assert_eq!(
+            skew,
+            InventorySkew::new(Decimal::from_str("0.25").unwrap(), max_skew).unwrap()
+        )
+    }
+
+    #[test]
+    fn target_ratio_must_be_exclusive_between_zero_and_one() {
+        let max_skew = Decimal::from_str("0.01").unwrap();
+
+        assert!(InventorySkew::new(Decimal::ZERO, max_skew).is_err());
+        assert!(InventorySkew::new(Decimal::ONE, max_skew).is_err());
+    }
+}
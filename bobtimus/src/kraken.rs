@@ -10,13 +10,6 @@ use tokio_tungstenite::tungstenite::Message;
 use watch::Receiver;
 
 const KRAKEN_WS_URL: &str = "wss://ws.kraken.com";
-const SUBSCRIBE_XBT_USD_TICKER_PAYLOAD: &str = r#"
-{ "event": "subscribe",
-  "pair": [ "XBT/USD" ],
-  "subscription": {
-    "name": "ticker"
-  }
-}"#;
 
 #[derive(Clone)]
 pub struct RateService {
@@ -30,7 +23,8 @@ impl LatestRate for RateService {
 }
 
 impl RateService {
-    pub async fn new() -> Result<Self> {
+    /// Subscribe to the ticker of a Kraken `pair`, e.g. `XBT/USD`.
+    pub async fn new(pair: &str) -> Result<Self> {
         let (tx, rx) = watch::channel(Rate::ZERO);
 
         let (ws, _response) =
@@ -66,7 +60,7 @@ impl RateService {
             }
         });
 
-        write.send(SUBSCRIBE_XBT_USD_TICKER_PAYLOAD.into()).await?;
+        write.send(subscribe_ticker_payload(pair).into()).await?;
 
         Ok(Self { receiver: rx })
     }
@@ -76,6 +70,17 @@ impl RateService {
     }
 }
 
+fn subscribe_ticker_payload(pair: &str) -> String {
+    serde_json::json!({
+        "event": "subscribe",
+        "pair": [ pair ],
+        "subscription": {
+            "name": "ticker"
+        }
+    })
+    .to_string()
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(transparent)]
 struct TickerUpdate(Vec<TickerField>);
@@ -3,44 +3,72 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
-use std::{collections::HashMap, convert::TryInto};
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use crate::{
     database::{queries, Sqlite},
     elements_rpc::{Client, ElementsRpc},
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use baru::{
     input::Input,
     loan::{Lender0, Lender1, LoanRequest, LoanResponse},
     swap,
 };
-use database::LiquidationForm;
+use database::{
+    LenderStateForm, Liquidation, LiquidationForm, LiquidationStatus, SwapForm, SwapStatus,
+    UtxoReservationForm,
+};
+use diesel::SqliteConnection;
 use elements::{
     bitcoin::{
         secp256k1::{All, Secp256k1},
         Amount,
     },
+    confidential::{Asset, Value},
     secp256k1_zkp::{
         rand::{CryptoRng, RngCore},
         SecretKey, SECP256K1,
     },
-    Address, AssetId, OutPoint, Transaction, Txid,
+    Address, AssetId, OutPoint, Transaction, TxOut, TxOutSecrets, Txid,
 };
+use estimate_transaction_size::estimate_virtual_size;
 use futures::{stream, stream::FuturesUnordered, Stream, TryStreamExt};
+use loan_book::LoanBook;
+use pair::{PairId, TradeAmounts, TradingPair};
+use pricing_models::LoanTerms;
+use quote::{
+    CreateQuotePayload, Direction, Quote, QuoteExpired, QuoteId, QuoteMismatch,
+    SimulateSwapPayload, SwapMode, SwapSimulation, TooManyQuotes, UnknownQuote, MAX_OPEN_QUOTES,
+};
 use serde::{Deserialize, Serialize};
-use tokio::sync::watch::Receiver;
+use tokio::sync::{watch::Receiver, Mutex};
 
 mod amounts;
 
+pub mod batch;
 pub mod cli;
 pub mod database;
 pub mod elements_rpc;
+pub mod fee_bump;
+pub mod fee_rate;
 pub mod fixed_rate;
 pub mod http;
+pub mod inventory;
 pub mod kraken;
+pub mod limits;
+pub mod loan_book;
 pub mod models;
+pub mod oracle;
+pub mod pair;
+pub mod pricing_models;
 pub mod problem;
+pub mod quote;
 pub mod schema;
 
 pub use amounts::*;
@@ -49,26 +77,252 @@ pub const USDT_ASSET_ID: &str = "ce091c998b83c78bb71a632313ba3760f1763d9cfcffae0
 
 pub struct Bobtimus<R, RS> {
     pub rng: R,
-    pub rate_service: RS,
     pub secp: Secp256k1<All>,
     pub elementsd: Client,
+    /// The native asset, used to pay fees and as loan collateral.
     pub btc_asset_id: AssetId,
+    /// The asset we lend out. Loans are priced using the trading pair
+    /// of L-BTC and this asset.
     pub usdt_asset_id: AssetId,
+    pub pairs: HashMap<PairId, TradingPair<RS>>,
     pub db: Sqlite,
-    pub lender_states: HashMap<Txid, Lender1>,
+    pub lender_states: HashMap<Txid, PendingLoan>,
+    pub loan_handshake_ttl: Duration,
+    pub quotes: HashMap<QuoteId, Quote>,
+    pub quote_ttl: Duration,
+    pub swap_expiry: Duration,
+    pub fee_rate_service: fee_rate::Service,
+    /// The loan durations borrowers can choose from.
+    pub loan_terms: Vec<LoanTerms>,
+    /// Loan-to-value ratio above which open loans are flagged in the
+    /// loan book.
+    pub loan_ltv_threshold: f64,
+    /// Where service fees are paid to. If not set, each swap pays to
+    /// a new address of our wallet. A confidential address must belong
+    /// to our wallet, since takers need the secrets of the blinded
+    /// service fee outputs.
+    pub service_fee_address: Option<Address>,
+}
+
+/// A loan handshake which was started by a borrower, but which has
+/// not been finalized yet.
+pub struct PendingLoan {
+    pub lender: Lender1,
+    /// Seconds since the UNIX epoch at which we responded to the
+    /// borrower's loan request.
+    pub created_at: u64,
+    pub collateral_amount: Amount,
+    pub principal_amount: Amount,
+}
+
+impl PendingLoan {
+    fn is_expired(&self, now: u64, ttl: Duration) -> bool {
+        handshake_expired(self.created_at, now, ttl)
+    }
+}
+
+/// Whether a loan handshake started at `created_at` has outlived
+/// `ttl` at `now`, all in seconds since the UNIX epoch.
+fn handshake_expired(created_at: u64, now: u64, ttl: Duration) -> bool {
+    now.saturating_sub(created_at) > ttl.as_secs()
+}
+
+/// The terms under which we currently lend L-USDt against L-BTC
+/// collateral.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LoanOffer {
+    /// The price of L-BTC at which the collateral and the principal
+    /// are valued against each other.
+    #[serde(serialize_with = "LiquidUsdt::serialize_to_nominal")]
+    pub rate: LiquidUsdt,
+    pub terms: Vec<LoanTerms>,
+}
+
+/// Number of blocks by which the locktime of a loan may deviate from
+/// the requested term, since the borrower's view of the chain tip can
+/// differ from ours.
+const LOAN_TIMELOCK_TOLERANCE: u32 = 10;
+
+#[derive(Deserialize)]
+pub struct CreateLoanPayload {
+    #[serde(flatten)]
+    pub loan_request: LoanRequest,
+    /// The loan duration chosen by the borrower, in blocks. Must be
+    /// one of the terms we offer.
+    pub term: u32,
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("loan term of {0} blocks is not offered")]
+pub struct UnknownLoanTerm(pub u32);
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("locktime {timelock} of loan request does not match term of {term} blocks")]
+pub struct LoanTermMismatch {
+    pub timelock: u64,
+    pub term: u32,
 }
 
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("loan handshake for transaction {0} has expired")]
+pub struct LoanHandshakeExpired(pub Txid);
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("unknown trading pair {0}")]
+pub struct UnknownPair(pub PairId);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateSwapPayload {
     pub alice_inputs: Vec<AliceInput>,
     pub address: Address,
+    /// The amount Alice sends us or, in `ExactOutput` mode, the
+    /// amount she receives from us.
     pub amount: u64,
+    #[serde(default)]
+    pub mode: SwapMode,
+    /// A quote previously handed out by us, whose price we will
+    /// honour if it hasn't expired yet.
+    #[serde(default)]
+    pub quote_id: Option<QuoteId>,
 }
 
+/// An input Alice contributes to a swap, together with what we need
+/// to know about the output it spends.
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
-pub struct AliceInput {
-    pub outpoint: OutPoint,
-    pub blinding_key: SecretKey,
+#[serde(untagged)]
+pub enum AliceInput {
+    /// The asset, value and blinding factors of the spent output.
+    /// Nothing else about Alice's wallet is revealed to us.
+    Secrets {
+        outpoint: OutPoint,
+        secrets: TxOutSecrets,
+    },
+    /// The key to unblind the spent output with.
+    ///
+    /// Prefer `Secrets`: wallets which use a single blinding key
+    /// hand us the power to unblind their entire history.
+    BlindingKey {
+        outpoint: OutPoint,
+        blinding_key: SecretKey,
+    },
+}
+
+impl AliceInput {
+    pub fn outpoint(&self) -> OutPoint {
+        match self {
+            AliceInput::Secrets { outpoint, .. } | AliceInput::BlindingKey { outpoint, .. } => {
+                *outpoint
+            }
+        }
+    }
+
+    fn blinding_key(&self) -> Option<SecretKey> {
+        match self {
+            AliceInput::Secrets { .. } => None,
+            AliceInput::BlindingKey { blinding_key, .. } => Some(*blinding_key),
+        }
+    }
+
+    /// Learn the secrets of `txout`, the output spent by this input.
+    fn unblind(&self, txout: &TxOut) -> Result<TxOutSecrets> {
+        match self {
+            AliceInput::Secrets { outpoint, secrets } => {
+                verify_secrets(txout, secrets)
+                    .with_context(|| format!("invalid secrets for input {}", outpoint))?;
+
+                Ok(*secrets)
+            }
+            AliceInput::BlindingKey {
+                outpoint,
+                blinding_key,
+            } => txout
+                .unblind(SECP256K1, *blinding_key)
+                .with_context(|| format!("failed to unblind input {}", outpoint)),
+        }
+    }
+}
+
+/// Check that `secrets` open the asset and value commitments of
+/// `txout`.
+fn verify_secrets(txout: &TxOut, secrets: &TxOutSecrets) -> Result<()> {
+    let asset = match txout.asset {
+        Asset::Explicit(_) => Asset::Explicit(secrets.asset),
+        _ => Asset::new_confidential(SECP256K1, secrets.asset, secrets.asset_bf),
+    };
+    if txout.asset != asset {
+        bail!("asset commitment does not match");
+    }
+
+    let value = match (txout.value, asset.commitment()) {
+        (Value::Explicit(_), _) | (_, None) => Value::Explicit(secrets.value),
+        (_, Some(generator)) => {
+            Value::new_confidential(SECP256K1, secrets.value, generator, secrets.value_bf)
+        }
+    };
+    if txout.value != value {
+        bail!("value commitment does not match");
+    }
+
+    Ok(())
+}
+
+/// One of Alice's inputs, with the output it spends and the secrets
+/// of that output.
+struct UnblindedInput {
+    txin: OutPoint,
+    txout: TxOut,
+    secrets: TxOutSecrets,
+    /// The key Alice revealed to unblind the output with, if any.
+    blinding_key: Option<SecretKey>,
+}
+
+/// A swap request which we have priced and whose inputs we have
+/// unblinded, ready to be settled on its own or in a batch.
+pub struct PreparedSwap {
+    direction: Direction,
+    inputs: Vec<UnblindedInput>,
+    address: Address,
+    /// The asset and amount Alice sends us.
+    taker: (AssetId, Amount),
+    /// The asset and amount we send Alice.
+    maker: (AssetId, Amount),
+    /// The asset and amount we pay to our service fee address.
+    service_fee: (AssetId, Amount),
+    rate: LiquidUsdt,
+    /// The quote whose price is honoured, which is used up once the
+    /// swap transaction has been built.
+    quote_id: Option<QuoteId>,
+}
+
+/// Record the `swaps` settled by transaction `txid` and reserve the
+/// inputs we contributed to it until `expires_at`.
+fn record_swaps(
+    conn: &SqliteConnection,
+    txid: Txid,
+    swaps: &[PreparedSwap],
+    created_at: u64,
+    our_inputs: &[OutPoint],
+    expires_at: u64,
+) -> Result<()> {
+    for (batch_index, swap) in swaps.iter().enumerate() {
+        SwapForm::new(
+            txid,
+            swap.direction,
+            swap.taker,
+            swap.maker,
+            swap.service_fee.1,
+            swap.rate,
+            &swap.address,
+            created_at,
+            u32::try_from(batch_index)?,
+        )?
+        .insert(conn)?;
+    }
+    for outpoint in our_inputs.iter() {
+        UtxoReservationForm::new(*outpoint, txid, expires_at)?.insert(conn)?;
+    }
+
+    Ok(())
 }
 
 impl<R, RS> Bobtimus<R, RS>
@@ -76,59 +330,220 @@ where
     R: RngCore + CryptoRng,
     RS: LatestRate,
 {
-    /// Handle Alice's request to create a swap transaction in which
-    /// she buys L-BTC from us and in return we get L-USDt from her.
-    pub async fn handle_create_buy_swap(
+    /// Handle Alice's request for a quote on the trading pair
+    /// `pair_id`, which we promise to honour until it expires.
+    pub fn handle_create_quote(
+        &mut self,
+        pair_id: &PairId,
+        payload: CreateQuotePayload,
+    ) -> Result<Quote> {
+        let now = unix_timestamp();
+        self.quotes.retain(|_, quote| !quote.is_expired(now));
+        if self.quotes.len() >= MAX_OPEN_QUOTES {
+            return Err(TooManyQuotes.into());
+        }
+
+        let pair = self
+            .pairs
+            .get_mut(pair_id)
+            .ok_or_else(|| UnknownPair(pair_id.clone()))?;
+        let latest_rate = pair.latest_rate();
+        let amounts =
+            pair.trade_amounts(latest_rate, payload.direction, payload.mode, payload.amount)?;
+        pair.limits.check(payload.direction, amounts.input)?;
+
+        let quote = Quote {
+            id: QuoteId::random(&mut self.rng),
+            pair: pair_id.clone(),
+            direction: payload.direction,
+            input_amount: amounts.input,
+            output_amount: amounts.output,
+            service_fee: amounts.service_fee,
+            rate: latest_rate.price(payload.direction),
+            expires_at: now + self.quote_ttl.as_secs(),
+        };
+        self.quotes.insert(quote.id.clone(), quote.clone());
+
+        Ok(quote)
+    }
+
+    /// Preview a swap on the trading pair `pair_id` at the latest
+    /// rate.
+    ///
+    /// Unlike creating a swap, this neither selects nor locks any of
+    /// our UTXOs and nothing is signed.
+    pub async fn handle_simulate_swap(
+        &mut self,
+        pair_id: &PairId,
+        direction: Direction,
+        payload: SimulateSwapPayload,
+    ) -> Result<SwapSimulation> {
+        let pair = self
+            .pairs
+            .get_mut(pair_id)
+            .ok_or_else(|| UnknownPair(pair_id.clone()))?;
+        let latest_rate = pair.latest_rate();
+        let fee_rate = self.fee_rate_service.fee_rate().await?;
+
+        pair.simulate(
+            latest_rate,
+            direction,
+            payload.mode,
+            payload.amount,
+            fee_rate,
+        )
+    }
+
+    /// Handle Alice's request to create a swap transaction on the
+    /// trading pair `pair_id`.
+    ///
+    /// If the `direction` is `Buy`, she buys the base asset from us
+    /// and in return we get the quote asset from her. If it is
+    /// `Sell`, she sells the base asset and we give her the quote
+    /// asset.
+    ///
+    /// If the swap pays us a service fee, the secrets of its blinded
+    /// output are returned too, so that Alice can verify it.
+    pub async fn handle_create_swap(
         &mut self,
+        pair_id: &PairId,
+        direction: Direction,
         payload: CreateSwapPayload,
-    ) -> Result<Transaction> {
-        let usdt_amount = LiquidUsdt::from_satodollar(payload.amount);
-        let latest_rate = self.rate_service.latest_rate();
-        let btc_amount = latest_rate.sell_base(usdt_amount)?;
-
-        let transaction = self
-            .swap_transaction(
-                (self.usdt_asset_id, usdt_amount.into()),
-                (self.btc_asset_id, btc_amount.into()),
-                payload.alice_inputs,
-                payload.address,
-                self.btc_asset_id,
-            )
-            .await?;
+    ) -> Result<(Transaction, Option<TxOutSecrets>)> {
+        let swap = self.prepare_swap(pair_id, direction, payload).await?;
+        let quote_id = swap.quote_id.clone();
+        let swap_transaction = self.swap_transaction(swap).await?;
 
-        Ok(transaction)
+        if let Some(quote_id) = quote_id {
+            self.quotes.remove(&quote_id);
+        }
+
+        Ok(swap_transaction)
     }
 
-    /// Handle Alice's request to create a swap transaction in which
-    /// she sells L-BTC and we give her L-USDt.
-    pub async fn handle_create_sell_swap(
+    /// Price Alice's swap request and learn the secrets of her
+    /// inputs.
+    pub async fn prepare_swap(
         &mut self,
+        pair_id: &PairId,
+        direction: Direction,
         payload: CreateSwapPayload,
-    ) -> Result<Transaction> {
-        let btc_amount = Amount::from_sat(payload.amount);
-        let latest_rate = self.rate_service.latest_rate();
-        let usdt_amount = latest_rate.buy_quote(btc_amount.into())?;
-
-        let transaction = self
-            .swap_transaction(
-                (self.btc_asset_id, btc_amount),
-                (self.usdt_asset_id, usdt_amount.into()),
-                payload.alice_inputs,
-                payload.address,
-                self.btc_asset_id,
-            )
-            .await?;
+    ) -> Result<PreparedSwap> {
+        let pair = self
+            .pairs
+            .get(pair_id)
+            .ok_or_else(|| UnknownPair(pair_id.clone()))?;
+        let (alice_asset_id, bob_asset_id) = pair.assets(direction);
+        let quote_asset_id = pair.quote_asset_id;
+
+        let (amounts, rate) = self.swap_amounts(
+            pair_id,
+            direction,
+            payload.mode,
+            payload.amount,
+            payload.quote_id.clone(),
+        )?;
+        let (alice_amount, bob_amount, service_fee) = self
+            .pairs
+            .get(pair_id)
+            .ok_or_else(|| UnknownPair(pair_id.clone()))?
+            .onchain_amounts(direction, amounts)?;
+        let inputs = self.unblind_alice_inputs(&payload.alice_inputs).await?;
+
+        Ok(PreparedSwap {
+            direction,
+            inputs,
+            address: payload.address,
+            taker: (alice_asset_id, alice_amount),
+            maker: (bob_asset_id, bob_amount),
+            service_fee: (quote_asset_id, service_fee),
+            rate,
+            quote_id: payload.quote_id,
+        })
+    }
+
+    /// Compute how much Alice will send us, how much we will give
+    /// her and the service fee she pays, together with the price per
+    /// unit of the base asset applied.
+    ///
+    /// Depending on the `mode`, Alice fixes either the amount she
+    /// sends or the amount she receives. The other one is computed
+    /// in our favour.
+    ///
+    /// If Alice refers to a quote, the quoted amounts are used
+    /// instead of the latest rate. The quote is left in place, since
+    /// it may only be used up once the swap has been built.
+    fn swap_amounts(
+        &mut self,
+        pair_id: &PairId,
+        direction: Direction,
+        mode: SwapMode,
+        amount: u64,
+        quote_id: Option<QuoteId>,
+    ) -> Result<(TradeAmounts, LiquidUsdt)> {
+        let pair = self
+            .pairs
+            .get_mut(pair_id)
+            .ok_or_else(|| UnknownPair(pair_id.clone()))?;
+
+        let quote_id = match quote_id {
+            Some(quote_id) => quote_id,
+            None => {
+                let latest_rate = pair.latest_rate();
+                let amounts = pair.trade_amounts(latest_rate, direction, mode, amount)?;
+                pair.limits.check(direction, amounts.input)?;
+
+                return Ok((amounts, latest_rate.price(direction)));
+            }
+        };
+
+        let quote = self
+            .quotes
+            .get(&quote_id)
+            .ok_or_else(|| UnknownQuote(quote_id.clone()))?;
 
-        Ok(transaction)
+        if quote.is_expired(unix_timestamp()) {
+            return Err(QuoteExpired(quote_id).into());
+        }
+
+        let quoted_amount = match mode {
+            SwapMode::ExactInput => quote.input_amount,
+            SwapMode::ExactOutput => quote.output_amount,
+        };
+        if &quote.pair != pair_id || quote.direction != direction || quoted_amount != amount {
+            return Err(QuoteMismatch(quote_id).into());
+        }
+
+        let amounts = TradeAmounts {
+            input: quote.input_amount,
+            output: quote.output_amount,
+            service_fee: quote.service_fee,
+        };
+
+        Ok((amounts, quote.rate))
+    }
+
+    /// The latest rate of the trading pair of L-BTC and the asset we
+    /// lend out.
+    fn loan_rate(&mut self) -> Result<Rate> {
+        let (btc_asset_id, usdt_asset_id) = (self.btc_asset_id, self.usdt_asset_id);
+        let pair = self
+            .pairs
+            .values_mut()
+            .find(|pair| pair.base_asset_id == btc_asset_id && pair.quote_asset_id == usdt_asset_id)
+            .context("no trading pair to price loans with")?;
+
+        Ok(pair.latest_rate())
     }
 
     async fn find_inputs(
         elements_client: &Client,
         asset_id: AssetId,
         input_amount: Amount,
+        should_lock: bool,
     ) -> Result<Vec<Input>> {
         let bob_inputs = elements_client
-            .select_inputs_for(asset_id, input_amount, false)
+            .select_inputs_for(asset_id, input_amount, should_lock)
             .await
             .context("failed to select inputs for swap")?;
 
@@ -163,105 +578,210 @@ where
         Ok(bob_inputs)
     }
 
+    /// Fetch the outputs spent by Alice's inputs and learn their
+    /// secrets.
+    async fn unblind_alice_inputs(
+        &self,
+        alice_inputs: &[AliceInput],
+    ) -> Result<Vec<UnblindedInput>> {
+        alice_inputs
+            .iter()
+            .copied()
+            .map(|alice_input| {
+                let client = self.elementsd.clone();
+                async move {
+                    let outpoint = alice_input.outpoint();
+                    let transaction = client
+                        .get_raw_transaction(outpoint.txid)
+                        .await
+                        .with_context(|| {
+                            format!("failed to fetch transaction {}", outpoint.txid)
+                        })?;
+
+                    let txout = transaction
+                        .output
+                        .get(outpoint.vout as usize)
+                        .with_context(|| {
+                            format!(
+                                "vout index {} is not valid for transaction {}",
+                                outpoint.vout, outpoint.txid
+                            )
+                        })?
+                        .clone();
+                    let secrets = alice_input.unblind(&txout)?;
+
+                    Result::<_, anyhow::Error>::Ok(UnblindedInput {
+                        txin: outpoint,
+                        txout,
+                        secrets,
+                        blinding_key: alice_input.blinding_key(),
+                    })
+                }
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_collect::<Vec<_>>()
+            .await
+    }
+
     async fn swap_transaction(
         &mut self,
-        (alice_input_asset_id, alice_input_amount): (AssetId, Amount),
-        (bob_input_asset_id, bob_input_amount): (AssetId, Amount),
-        alice_inputs: Vec<AliceInput>,
-        alice_address: Address,
-        btc_asset_id: AssetId,
-    ) -> Result<Transaction> {
-        let bob_inputs = Self::find_inputs(&self.elementsd, bob_input_asset_id, bob_input_amount)
-            .await
-            .context("could not find transaction inputs for Bob")?;
+        swap: PreparedSwap,
+    ) -> Result<(Transaction, Option<TxOutSecrets>)> {
+        // baru only builds the outputs of the two parties, and needs
+        // Alice's blinding key to unblind her inputs itself
+        let alice_inputs = swap
+            .inputs
+            .iter()
+            .map(|input| {
+                input.blinding_key.map(|blinding_key| Input {
+                    txin: input.txin,
+                    original_txout: input.txout.clone(),
+                    blinding_key,
+                })
+            })
+            .collect::<Option<Vec<_>>>();
+        let alice_inputs = match alice_inputs {
+            Some(alice_inputs) if swap.service_fee.1 == Amount::ZERO => alice_inputs,
+            _ => return self.swap_transaction_without_baru(swap).await,
+        };
 
-        let bob_address = self
-            .elementsd
-            .get_new_segwit_confidential_address()
-            .await
-            .context("failed to get redeem address")?;
+        let (bob_input_asset_id, bob_input_amount) = swap.maker;
+        let (alice_input_asset_id, alice_input_amount) = swap.taker;
 
-        let alice_inputs = alice_inputs
+        // The inputs stay locked until the swap transaction is
+        // confirmed or the reservation expires
+        let bob_inputs =
+            Self::find_inputs(&self.elementsd, bob_input_asset_id, bob_input_amount, true)
+                .await
+                .context("could not find transaction inputs for Bob")?;
+        let reserved_utxos = bob_inputs
             .iter()
-            .copied()
-            .map(
-                |AliceInput {
-                     outpoint,
-                     blinding_key,
-                 }| {
-                    let client = self.elementsd.clone();
-                    async move {
-                        let transaction = client
-                            .get_raw_transaction(outpoint.txid)
-                            .await
-                            .with_context(|| {
-                                format!("failed to fetch transaction {}", outpoint.txid)
-                            })?;
-
-                        let txout = transaction
-                            .output
-                            .get(outpoint.vout as usize)
-                            .with_context(|| {
-                                format!(
-                                    "vout index {} is not valid for transaction {}",
-                                    outpoint.vout, outpoint.txid
-                                )
-                            })?
-                            .clone();
-
-                        Result::<_, anyhow::Error>::Ok(Input {
-                            txin: outpoint,
-                            original_txout: txout,
-                            blinding_key,
-                        })
+            .map(|input| input.txin)
+            .collect::<Vec<_>>();
+
+        let transaction = async {
+            let bob_address = self
+                .elementsd
+                .get_new_segwit_confidential_address()
+                .await
+                .context("failed to get redeem address")?;
+
+            let fee_rate = self.fee_rate_service.fee_rate().await?;
+
+            let alice = swap::Actor::new(
+                &self.secp,
+                alice_inputs,
+                swap.address.clone(),
+                bob_input_asset_id,
+                bob_input_amount,
+            )?;
+
+            let bob = swap::Actor::new(
+                &self.secp,
+                bob_inputs,
+                bob_address,
+                alice_input_asset_id,
+                alice_input_amount,
+            )?;
+
+            let transaction = swap::bob_create_transaction(
+                &mut self.rng,
+                &self.secp,
+                alice,
+                bob,
+                self.btc_asset_id,
+                fee_rate,
+                {
+                    let elementsd = self.elementsd.clone();
+                    move |transaction| async move {
+                        let tx = elementsd.sign_raw_transaction(&transaction).await?;
+
+                        Result::<_, anyhow::Error>::Ok(tx)
                     }
                 },
             )
-            .collect::<FuturesUnordered<_>>()
-            .try_collect::<Vec<_>>()
             .await?;
 
-        let alice = swap::Actor::new(
-            &self.secp,
-            alice_inputs,
-            alice_address,
-            bob_input_asset_id,
-            bob_input_amount,
-        )?;
+            let swap_txid = transaction.txid();
+            let created_at = unix_timestamp();
+            let expires_at = created_at + self.swap_expiry.as_secs();
+
+            self.db
+                .do_in_transaction(|conn| {
+                    record_swaps(
+                        conn,
+                        swap_txid,
+                        std::slice::from_ref(&swap),
+                        created_at,
+                        &reserved_utxos,
+                        expires_at,
+                    )
+                })
+                .await
+                .context("failed to record swap")?;
 
-        let bob = swap::Actor::new(
-            &self.secp,
-            bob_inputs,
-            bob_address,
-            alice_input_asset_id,
-            alice_input_amount,
-        )?;
+            Result::<_, anyhow::Error>::Ok(transaction)
+        }
+        .await;
 
-        let transaction = swap::bob_create_transaction(
-            &mut self.rng,
-            &self.secp,
-            alice,
-            bob,
-            btc_asset_id,
-            Amount::from_sat(1), // TODO: Make this dynamic once there is something going on on Liquid
-            {
-                let elementsd = self.elementsd.clone();
-                move |transaction| async move {
-                    let tx = elementsd.sign_raw_transaction(&transaction).await?;
+        if transaction.is_err() {
+            // Nobody can spend the inputs we selected, so they can be
+            // used for other swaps
+            if let Err(e) = self.elementsd.unlock_utxos(reserved_utxos).await {
+                tracing::error!("Failed to unlock inputs of failed swap: {:#}", e);
+            }
+        }
 
-                    Result::<_, anyhow::Error>::Ok(tx)
-                }
-            },
-        )
-        .await?;
+        // baru is only used for swaps without a service fee
+        transaction.map(|transaction| (transaction, None))
+    }
+
+    /// Our open loans, valued at the latest rate.
+    pub async fn handle_loan_book(&mut self) -> Result<LoanBook> {
+        let rate = self.loan_rate()?;
+
+        LoanBook::fetch(&self.elementsd, &self.db, rate, self.loan_ltv_threshold).await
+    }
+
+    /// The terms a borrower can expect if they request a loan now.
+    pub fn handle_loan_offer(&mut self) -> Result<LoanOffer> {
+        let rate = self.loan_rate()?;
 
-        Ok(transaction)
+        Ok(LoanOffer {
+            rate: rate.bid,
+            terms: self.loan_terms.clone(),
+        })
     }
 
     /// Handle Alice's loan request in which she puts up L-BTC as
     /// collateral and we give lend her L-USDt which she will have to
     /// repay in the future.
-    pub async fn handle_loan_request(&mut self, payload: LoanRequest) -> Result<LoanResponse> {
+    ///
+    /// The loan request must be for one of the terms we offer, and its
+    /// locktime must correspond to that term.
+    pub async fn handle_loan_request(
+        &mut self,
+        payload: CreateLoanPayload,
+    ) -> Result<LoanResponse> {
+        let terms = *self
+            .loan_terms
+            .iter()
+            .find(|terms| terms.term_blocks == payload.term)
+            .ok_or(UnknownLoanTerm(payload.term))?;
+
+        // Lending less than the value of the collateral is how we
+        // price the risk of the chosen term. This is expressed in
+        // satodollar per whole L-BTC
+        let rate = self.loan_rate()?.bid.as_satodollar() as f64;
+        let principal_per_collateral = (rate * terms.max_ltv) as u64;
+        let collateral_amount = payload.loan_request.collateral_amount;
+        let principal_amount = Amount::from_sat(
+            (u128::from(collateral_amount.as_sat()) * u128::from(principal_per_collateral)
+                / u128::from(Amount::ONE_BTC.as_sat())) as u64,
+        );
+
+        let blockcount = self.elementsd.get_blockcount().await?;
+
         let lender_address = self
             .elementsd
             .get_new_segwit_confidential_address()
@@ -274,49 +794,123 @@ where
             self.usdt_asset_id,
             lender_address,
         )
-        .unwrap();
+        .context("failed to create lender state")?;
 
+        let selected_inputs = Arc::new(Mutex::new(Vec::new()));
         let lender1 = lender0
             .interpret(
                 &mut self.rng,
                 &SECP256K1,
                 {
                     let elementsd_client = self.elementsd.clone();
+                    let selected_inputs = selected_inputs.clone();
                     |amount, asset| async move {
-                        Self::find_inputs(&elementsd_client, asset, amount).await
+                        // The inputs stay locked until the loan is
+                        // finalized or the handshake expires
+                        let inputs =
+                            Self::find_inputs(&elementsd_client, asset, amount, true).await?;
+                        selected_inputs
+                            .lock()
+                            .await
+                            .extend(inputs.iter().map(|input| input.txin));
+
+                        Result::<_, anyhow::Error>::Ok(inputs)
                     }
                 },
-                payload,
-                self.rate_service.latest_rate().bid.as_satodollar(),
+                payload.loan_request,
+                principal_per_collateral,
             )
-            .await
-            .unwrap();
+            .await;
+        let lender1 = match lender1 {
+            Ok(lender1) => lender1,
+            Err(e) => {
+                // Nobody can spend the inputs we selected, so they can be
+                // used for other swaps and loans
+                let outpoints = selected_inputs.lock().await.clone();
+                if let Err(e) = self.elementsd.unlock_utxos(outpoints).await {
+                    tracing::error!("Failed to unlock inputs of rejected loan request: {:#}", e);
+                }
+
+                return Err(e).context("failed to interpret loan request");
+            }
+        };
+
+        let expected_timelock = u64::from(blockcount) + u64::from(terms.term_blocks);
+        let tolerance = u64::from(LOAN_TIMELOCK_TOLERANCE);
+        if lender1.timelock + tolerance < expected_timelock
+            || lender1.timelock > expected_timelock + tolerance
+        {
+            let outpoints = lender1
+                .loan_response()
+                .transaction
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+                .collect();
+            self.elementsd.unlock_utxos(outpoints).await?;
+
+            return Err(LoanTermMismatch {
+                timelock: lender1.timelock,
+                term: terms.term_blocks,
+            }
+            .into());
+        }
 
         let loan_response = lender1.loan_response();
+        let loan_txid = loan_response.transaction.txid();
+        let pending_loan = PendingLoan {
+            lender: lender1,
+            created_at: unix_timestamp(),
+            collateral_amount,
+            principal_amount,
+        };
+
+        // The state is persisted so that the borrower can still
+        // finalize the loan if we are restarted in the meantime
+        self.db
+            .do_in_transaction(|conn| {
+                LenderStateForm::new(loan_txid, &pending_loan)?.insert(conn)?;
+
+                Ok(())
+            })
+            .await?;
 
-        self.lender_states
-            .insert(loan_response.transaction.txid(), lender1);
+        self.lender_states.insert(loan_txid, pending_loan);
 
         Ok(loan_response)
     }
 
+    /// Check that `loan_txid` is the transaction of a loan which we
+    /// have offered and whose handshake has not expired.
+    pub fn ensure_pending_loan(&self, loan_txid: Txid) -> Result<()> {
+        let pending_loan = self
+            .lender_states
+            .get(&loan_txid)
+            .context("unknown loan transaction")?;
+
+        if pending_loan.is_expired(unix_timestamp(), self.loan_handshake_ttl) {
+            return Err(LoanHandshakeExpired(loan_txid).into());
+        }
+
+        Ok(())
+    }
+
     /// Handle Alice's request to finalize a loan.
     ///
     /// If we still agree with the loan transaction sent by Alice, we
-    /// will sign and broadcast it.
+    /// will sign and broadcast it. We only accept loan transactions
+    /// which were proposed less than `loan_handshake_ttl` ago, since
+    /// we expect the borrower to quickly perform the protocol.
     ///
     /// Additionally, we save the signed liquidation transaction so
     /// that we can broadcast it when the locktime is reached.
     pub async fn finalize_loan(&mut self, transaction: Transaction) -> Result<Txid> {
-        // TODO: We should only take into account loan transactions which
-        // are relatively recent e.g. within 1 minute. We expect the
-        // borrower to quickly perform the protocol and let us broadcast
-        // the loan transaction
+        let loan_txid = transaction.txid();
+        self.ensure_pending_loan(loan_txid)?;
+        let pending_loan = &self.lender_states[&loan_txid];
 
-        let lender = self
-            .lender_states
-            .get(&transaction.txid())
-            .context("unknown loan transaction")?;
+        let lender = &pending_loan.lender;
+        let fee_rate = self.fee_rate_service.fee_rate().await?;
 
         let transaction = lender
             .finalise_loan(transaction, {
@@ -327,8 +921,7 @@ where
 
         let txid = self.elementsd.send_raw_transaction(&transaction).await?;
 
-        let liquidation_tx =
-            lender.liquidation_transaction(&mut self.rng, &self.secp, Amount::ONE_SAT)?;
+        let liquidation_tx = lender.liquidation_transaction(&mut self.rng, &self.secp, fee_rate)?;
         let locktime = lender
             .timelock
             .try_into()
@@ -336,14 +929,345 @@ where
 
         self.db
             .do_in_transaction(|conn| {
-                LiquidationForm::new(txid, &liquidation_tx, locktime).insert(conn)?;
+                LiquidationForm::new(
+                    txid,
+                    &liquidation_tx,
+                    locktime,
+                    pending_loan.collateral_amount,
+                    pending_loan.principal_amount,
+                )?
+                .insert(conn)?;
+                queries::delete_lender_state(conn, loan_txid)?;
 
                 Ok(())
             })
             .await?;
 
+        self.lender_states.remove(&loan_txid);
+
         Ok(txid)
     }
+
+    /// Forget about all the loan handshakes which have outlived
+    /// `loan_handshake_ttl`, unlocking the UTXOs we selected for
+    /// them.
+    ///
+    /// A handshake which cannot be expired is kept, so that we try
+    /// again next time, and does not prevent the others from being
+    /// expired.
+    pub async fn expire_loan_handshakes(&mut self) {
+        let now = unix_timestamp();
+        let ttl = self.loan_handshake_ttl;
+        let expired = self
+            .lender_states
+            .iter()
+            .filter(|(_, pending_loan)| pending_loan.is_expired(now, ttl))
+            .map(|(txid, _)| *txid)
+            .collect::<Vec<_>>();
+
+        for loan_txid in expired {
+            match self.expire_loan_handshake(loan_txid).await {
+                Ok(()) => tracing::info!("Expired loan handshake for transaction {}", loan_txid),
+                Err(e) => tracing::error!(
+                    "Failed to expire loan handshake for transaction {}: {:#}",
+                    loan_txid,
+                    e
+                ),
+            }
+        }
+    }
+
+    async fn expire_loan_handshake(&mut self, loan_txid: Txid) -> Result<()> {
+        let outpoints = match self.lender_states.get(&loan_txid) {
+            Some(pending_loan) => pending_loan
+                .lender
+                .loan_response()
+                .transaction
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+                .collect(),
+            None => return Ok(()),
+        };
+
+        // Unlocking is idempotent, so all of this can be retried if
+        // one of the steps fails
+        self.elementsd
+            .unlock_utxos(outpoints)
+            .await
+            .context("failed to unlock inputs")?;
+        self.db
+            .do_in_transaction(|conn| queries::delete_lender_state(conn, loan_txid))
+            .await?;
+        self.lender_states.remove(&loan_txid);
+
+        Ok(())
+    }
+}
+
+/// Drop expired loan handshakes every `period`.
+pub async fn expire_loan_handshakes<R, RS>(bobtimus: Arc<Mutex<Bobtimus<R, RS>>>, period: Duration)
+where
+    R: RngCore + CryptoRng,
+    RS: LatestRate,
+{
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        bobtimus.lock().await.expire_loan_handshakes().await;
+    }
+}
+
+/// Keep the UTXOs reserved for swaps in sync with the state of the
+/// corresponding swap transactions.
+///
+/// Reservations whose UTXO has been spent in a block are cleared.
+/// Swaps whose reservations expire before their UTXOs are spent are
+/// invalidated, see [`invalidate_swap`].
+pub async fn update_utxo_reservations(
+    elementsd: &Client,
+    db: &Sqlite,
+    fee_rate_service: &fee_rate::Service,
+    btc_asset_id: AssetId,
+) -> Result<()> {
+    let reservations = db
+        .do_in_transaction(|conn| queries::get_utxo_reservations(conn))
+        .await?;
+    let now = unix_timestamp();
+    let mut expired = HashMap::<Txid, Vec<OutPoint>>::new();
+
+    for reservation in reservations {
+        let outpoint = reservation.outpoint;
+        let swap_txid = reservation.swap_txid;
+
+        if !elementsd.is_unspent(outpoint, false).await? {
+            db.do_in_transaction(|conn| {
+                queries::delete_utxo_reservation(conn, outpoint)?;
+                queries::update_swap_status(conn, swap_txid, SwapStatus::Confirmed)?;
+
+                Ok(())
+            })
+            .await?;
+
+            continue;
+        }
+
+        if !elementsd.is_unspent(outpoint, true).await? {
+            db.do_in_transaction(|conn| {
+                queries::update_swap_status(conn, swap_txid, SwapStatus::Broadcast)
+            })
+            .await?;
+
+            continue;
+        }
+
+        if now > reservation.expires_at {
+            expired.entry(swap_txid).or_default().push(outpoint);
+        }
+    }
+
+    for (swap_txid, outpoints) in expired {
+        // Batches are only recorded once broadcast, so there is
+        // nothing to invalidate if we never signed them
+        if !db
+            .do_in_transaction(|conn| queries::has_swap(conn, swap_txid))
+            .await?
+        {
+            if let Err(e) = release_inputs(elementsd, db, outpoints).await {
+                tracing::error!("Failed to release inputs of batch {}: {:#}", swap_txid, e);
+            }
+
+            continue;
+        }
+
+        if let Err(e) = invalidate_swap(
+            elementsd,
+            db,
+            fee_rate_service,
+            btc_asset_id,
+            swap_txid,
+            outpoints,
+        )
+        .await
+        {
+            tracing::error!("Failed to invalidate expired swap {}: {:#}", swap_txid, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Make sure that the swap transaction `swap_txid`, which we have
+/// signed but which the taker has not broadcast in time, can never be
+/// broadcast, by spending one of our `outpoints` in it back to
+/// ourselves. Otherwise, the taker would get a free option to trade at
+/// an outdated price.
+///
+/// Our inputs stay locked until the invalidating transaction is
+/// broadcast, so that no other swap can select them in the meantime.
+/// Only then are the other inputs released.
+async fn invalidate_swap(
+    elementsd: &Client,
+    db: &Sqlite,
+    fee_rate_service: &fee_rate::Service,
+    btc_asset_id: AssetId,
+    swap_txid: Txid,
+    outpoints: Vec<OutPoint>,
+) -> Result<()> {
+    let utxos = elementsd.get_wallet_utxos(&outpoints).await?;
+    let utxo = utxos
+        .iter()
+        .find(|utxo| utxo.asset == btc_asset_id)
+        .or_else(|| utxos.first())
+        .context("inputs of swap are not in our wallet")?
+        .clone();
+
+    let mut inputs = vec![utxo];
+    let mut fee_outpoint = None;
+    if inputs[0].asset != btc_asset_id {
+        let fee_utxo = elementsd
+            .get_largest_utxo(btc_asset_id)
+            .await
+            .context("no L-BTC to pay the fee of the invalidation")?;
+        let outpoint = OutPoint {
+            txid: fee_utxo.txid,
+            vout: fee_utxo.vout,
+        };
+        elementsd
+            .lock_utxos(vec![outpoint])
+            .await
+            .context("failed to lock the fee input of the invalidation")?;
+
+        fee_outpoint = Some(outpoint);
+        inputs.push(fee_utxo);
+    }
+
+    let invalidation = async {
+        let fee_rate = fee_rate_service.fee_rate().await?;
+        let vsize = estimate_virtual_size(inputs.len() as u64, inputs.len() as u64);
+        let fee = Amount::from_sat(fee_rate.as_sat() * vsize);
+
+        elementsd.spend_to_self(&inputs, (btc_asset_id, fee)).await
+    }
+    .await;
+
+    let invalidation_txid = match invalidation {
+        Ok(txid) => txid,
+        Err(e) => {
+            // The inputs of the swap are still locked, so that we can
+            // retry, but the fee input can be used by others again
+            if let Some(outpoint) = fee_outpoint {
+                elementsd.unlock_utxos(vec![outpoint]).await?;
+            }
+
+            return Err(e);
+        }
+    };
+
+    // The inputs we did not spend can be used by other swaps again
+    let spent = OutPoint {
+        txid: inputs[0].txid,
+        vout: inputs[0].vout,
+    };
+    let unspent = outpoints
+        .iter()
+        .copied()
+        .filter(|outpoint| *outpoint != spent)
+        .collect();
+    elementsd
+        .unlock_utxos(unspent)
+        .await
+        .with_context(|| format!("failed to unlock inputs of swap {}", swap_txid))?;
+
+    db.do_in_transaction(|conn| {
+        for outpoint in outpoints.iter() {
+            queries::delete_utxo_reservation(conn, *outpoint)?;
+        }
+        queries::invalidate_swap(conn, swap_txid, invalidation_txid)?;
+
+        Ok(())
+    })
+    .await?;
+
+    tracing::info!(
+        "Invalidated expired swap {} with transaction {}",
+        swap_txid,
+        invalidation_txid
+    );
+
+    Ok(())
+}
+
+/// Unlock our `outpoints` and delete their reservations, so that
+/// other swaps can use them again.
+async fn release_inputs(elementsd: &Client, db: &Sqlite, outpoints: Vec<OutPoint>) -> Result<()> {
+    elementsd.unlock_utxos(outpoints.clone()).await?;
+    db.do_in_transaction(|conn| {
+        for outpoint in outpoints.iter() {
+            queries::delete_utxo_reservation(conn, *outpoint)?;
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+/// Number of times the UTXO reservations are checked during
+/// `swap_expiry`, which bounds how late an expired swap is
+/// invalidated.
+const RESERVATION_CHECKS_PER_EXPIRY: u32 = 4;
+
+/// Keep the UTXOs reserved for swaps up to date, checking them
+/// several times per `swap_expiry`.
+pub async fn track_utxo_reservations(
+    elementsd: Client,
+    db: Sqlite,
+    fee_rate_service: fee_rate::Service,
+    btc_asset_id: AssetId,
+    swap_expiry: Duration,
+) {
+    let mut interval = tokio::time::interval(reservation_check_period(swap_expiry));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) =
+            update_utxo_reservations(&elementsd, &db, &fee_rate_service, btc_asset_id).await
+        {
+            tracing::error!("Failed to update UTXO reservations: {:#}", e);
+        }
+    }
+}
+
+fn reservation_check_period(swap_expiry: Duration) -> Duration {
+    (swap_expiry / RESERVATION_CHECKS_PER_EXPIRY).max(Duration::from_secs(1))
+}
+
+/// Lock all the UTXOs which are reserved for a swap.
+///
+/// elementsd does not persist locked UTXOs, so this has to be called
+/// on startup in case elementsd was restarted in the meantime.
+pub async fn relock_reserved_utxos(elementsd: &Client, db: &Sqlite) -> Result<()> {
+    let outpoints = db
+        .do_in_transaction(|conn| queries::get_utxo_reservations(conn))
+        .await?
+        .into_iter()
+        .map(|reservation| reservation.outpoint)
+        .collect();
+
+    elementsd
+        .relock_utxos(outpoints)
+        .await
+        .context("failed to lock reserved UTXOs")
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time to be after the UNIX epoch")
+        .as_secs()
 }
 
 pub trait LatestRate {
@@ -362,6 +1286,12 @@ impl From<Receiver<Rate>> for RateSubscription {
 }
 
 impl RateSubscription {
+    /// The most recent rate, which is `Rate::ZERO` until the first
+    /// update is received.
+    pub fn latest_rate(&self) -> Rate {
+        *self.receiver.borrow()
+    }
+
     pub fn into_stream(self) -> impl Stream<Item = Result<Rate>> {
         stream::try_unfold(self.receiver, |mut receiver| async move {
             receiver
@@ -376,43 +1306,449 @@ impl RateSubscription {
     }
 }
 
+/// Advance the liquidation of every loan which is not settled yet.
+///
+/// A loan whose collateral is spent by anything but our liquidation
+/// transaction has been repaid. Otherwise, we broadcast the
+/// liquidation transaction once its locktime is reached, and
+/// rebroadcast it until it is confirmed in case it is dropped from
+/// the mempool. Settled loans are moved to the liquidation history.
+///
+/// Fails if any liquidation could not be advanced, so that the caller
+/// can try again.
 pub async fn liquidate_loans(elementsd: &Client, db: Sqlite) -> Result<()> {
     let blockcount = elementsd.get_blockcount().await?;
-    let liquidation_txs = db
-        .do_in_transaction(|conn| {
-            let txs = queries::get_publishable_liquidations_txs(conn, blockcount)?;
-            Ok(txs)
-        })
+    let liquidations = db
+        .do_in_transaction(|conn| queries::get_active_liquidations(conn))
         .await?;
 
-    for tx in liquidation_txs.iter() {
-        match elementsd.send_raw_transaction(&tx).await {
-            Ok(txid) => log::info!("Broadcast liquidation transaction {}", txid),
-            Err(e) => log::error!("Failed to broadcast liquidation transaction: {}", e),
-        };
+    let mut failed = 0;
+    for liquidation in liquidations.iter() {
+        if let Err(e) = liquidate_loan(elementsd, &db, liquidation, blockcount).await {
+            tracing::error!(
+                "Failed to update liquidation of loan {}: {:#}",
+                liquidation.loan_txid,
+                e
+            );
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("failed to advance {} liquidations", failed);
+    }
+
+    Ok(())
+}
+
+async fn liquidate_loan(
+    elementsd: &Client,
+    db: &Sqlite,
+    liquidation: &Liquidation,
+    blockcount: u32,
+) -> Result<()> {
+    let loan_txid = liquidation.loan_txid;
+    let liquidation_txid = liquidation.transaction.txid();
+
+    let observation = LiquidationObservation {
+        blockcount,
+        collateral_unspent: elementsd
+            .is_unspent(liquidation.collateral_outpoint()?, true)
+            .await?,
+        // The liquidation transaction pays us, so our wallet knows
+        // about it as soon as it has been broadcast
+        liquidation_confirmations: elementsd
+            .gettransaction(liquidation_txid)
+            .await
+            .ok()
+            .map(|transaction| transaction.confirmations),
+    };
+
+    let status = match next_liquidation_step(liquidation, observation) {
+        LiquidationStep::Update(status) => status,
+        LiquidationStep::Broadcast => {
+            match elementsd
+                .send_raw_transaction(&liquidation.transaction)
+                .await
+            {
+                Ok(txid) => {
+                    tracing::info!("Broadcast liquidation transaction {}", txid);
+                    LiquidationStatus::Broadcast
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to broadcast liquidation transaction of loan {}: {:#}",
+                        loan_txid,
+                        e
+                    );
+                    LiquidationStatus::Failed
+                }
+            }
+        }
+    };
+
+    if status.is_final() {
+        db.do_in_transaction(|conn| {
+            queries::settle_liquidation(conn, loan_txid, status, unix_timestamp())
+        })
+        .await?;
+    } else if status != liquidation.status {
+        db.do_in_transaction(|conn| queries::update_liquidation_status(conn, loan_txid, status))
+            .await?;
+    }
+
+    if status != liquidation.status {
+        tracing::info!("Liquidation of loan {} is now {}", loan_txid, status);
+    }
+
+    if status == LiquidationStatus::Failed {
+        bail!(
+            "liquidation transaction {} was not broadcast",
+            liquidation_txid
+        );
     }
 
     Ok(())
 }
 
+/// What the chain tells us about a loan we may have to liquidate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LiquidationObservation {
+    blockcount: u32,
+    /// Whether the collateral output is unspent, taking the mempool
+    /// into account.
+    collateral_unspent: bool,
+    /// The confirmations of the liquidation transaction, if our
+    /// wallet knows about it. Negative if it conflicts with a
+    /// confirmed transaction.
+    liquidation_confirmations: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LiquidationStep {
+    Update(LiquidationStatus),
+    /// Broadcast the liquidation transaction, or broadcast it again
+    /// in case it was dropped from the mempool.
+    Broadcast,
+}
+
+fn next_liquidation_step(
+    liquidation: &Liquidation,
+    observation: LiquidationObservation,
+) -> LiquidationStep {
+    match observation.liquidation_confirmations {
+        Some(confirmations) if confirmations > 0 => {
+            LiquidationStep::Update(LiquidationStatus::Confirmed)
+        }
+        // The borrower's repayment was mined instead
+        Some(confirmations) if confirmations < 0 => {
+            LiquidationStep::Update(LiquidationStatus::Repaid)
+        }
+        Some(_) => LiquidationStep::Broadcast,
+        None if !observation.collateral_unspent => {
+            LiquidationStep::Update(LiquidationStatus::Repaid)
+        }
+        None if liquidation.locktime > observation.blockcount => {
+            LiquidationStep::Update(liquidation.status)
+        }
+        None => LiquidationStep::Broadcast,
+    }
+}
+
+/// Liquidate loans as soon as their locktime is reached.
+///
+/// We wake up on every new block, but at least every `poll_interval`
+/// in case we fail to hear about one. A block height is only marked as
+/// processed once every liquidation due at it has been broadcast, so
+/// failed attempts are retried on the next wake-up.
+pub async fn liquidate_loans_continuously(elementsd: Client, db: Sqlite, poll_interval: Duration) {
+    let mut last_liquidated_height = None;
+
+    loop {
+        let height = match elementsd.wait_for_new_block(poll_interval).await {
+            Ok(height) => height,
+            Err(e) => {
+                tracing::warn!("Failed to wait for new block: {:#}", e);
+                tokio::time::sleep(poll_interval).await;
+
+                match elementsd.get_blockcount().await {
+                    Ok(height) => height,
+                    Err(e) => {
+                        tracing::error!("Failed to get block count: {:#}", e);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if last_liquidated_height == Some(height) {
+            continue;
+        }
+
+        match liquidate_loans(&elementsd, db.clone()).await {
+            Ok(()) => last_liquidated_height = Some(height),
+            Err(e) => tracing::error!("Failed to liquidate loans at height {}: {:#}", height, e),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         elements_rpc::{Client, ElementsRpc, ListUnspentOptions},
         fixed_rate,
+        limits::{Limits, TradeLimits},
+        pair::{RateSource, ServiceFee, TradingPairConfig, LBTC_LUSDT},
     };
     use anyhow::{Context, Result};
-    use baru::swap::sign_with_key;
+    use baru::{loan::Borrower0, swap::sign_with_key};
     use elements::{
         bitcoin::{secp256k1::Secp256k1, Amount, Network, PrivateKey, PublicKey},
-        secp256k1_zkp::{rand::thread_rng, SecretKey, SECP256K1},
+        confidential::{AssetBlindingFactor, Nonce, ValueBlindingFactor},
+        secp256k1_zkp::{
+            rand::{rngs::ThreadRng, thread_rng},
+            SecretKey, SECP256K1,
+        },
         sighash::SigHashCache,
-        Address, AddressParams, OutPoint, Transaction, TxOut,
+        Address, AddressParams, OutPoint, Script, Transaction, TxOut, TxOutWitness,
     };
     use elements_harness::Elementsd;
+    use std::str::FromStr;
     use testcontainers::clients::Cli;
 
+    fn liquidation(status: LiquidationStatus) -> Liquidation {
+        Liquidation {
+            loan_txid: Txid::default(),
+            transaction: Transaction {
+                version: 2,
+                lock_time: 100,
+                input: Vec::new(),
+                output: Vec::new(),
+            },
+            locktime: 100,
+            status,
+            collateral_amount: Amount::ONE_BTC,
+            principal_amount: Amount::from_sat(2_000_000_000_000),
+        }
+    }
+
+    fn observation(
+        blockcount: u32,
+        collateral_unspent: bool,
+        liquidation_confirmations: Option<i64>,
+    ) -> LiquidationObservation {
+        LiquidationObservation {
+            blockcount,
+            collateral_unspent,
+            liquidation_confirmations,
+        }
+    }
+
+    #[test]
+    fn liquidation_waits_for_locktime() {
+        let step = next_liquidation_step(
+            &liquidation(LiquidationStatus::Pending),
+            observation(99, true, None),
+        );
+
+        assert_eq!(step, LiquidationStep::Update(LiquidationStatus::Pending));
+    }
+
+    #[test]
+    fn liquidation_is_broadcast_once_locktime_is_reached() {
+        for status in [LiquidationStatus::Pending, LiquidationStatus::Failed].iter() {
+            let step = next_liquidation_step(&liquidation(*status), observation(100, true, None));
+
+            assert_eq!(step, LiquidationStep::Broadcast);
+        }
+    }
+
+    #[test]
+    fn collateral_spent_by_someone_else_means_loan_was_repaid() {
+        let step = next_liquidation_step(
+            &liquidation(LiquidationStatus::Pending),
+            observation(100, false, None),
+        );
+
+        assert_eq!(step, LiquidationStep::Update(LiquidationStatus::Repaid));
+    }
+
+    #[test]
+    fn collateral_spent_by_unrecorded_broadcast_is_not_mistaken_for_repayment() {
+        // We broadcast the liquidation transaction but failed to
+        // record it
+        let step = next_liquidation_step(
+            &liquidation(LiquidationStatus::Pending),
+            observation(101, false, Some(1)),
+        );
+
+        assert_eq!(step, LiquidationStep::Update(LiquidationStatus::Confirmed));
+    }
+
+    #[test]
+    fn unconfirmed_liquidation_is_rebroadcast() {
+        // Dropped from the mempool, so the collateral is unspent again
+        let evicted = next_liquidation_step(
+            &liquidation(LiquidationStatus::Broadcast),
+            observation(101, true, Some(0)),
+        );
+        let in_mempool = next_liquidation_step(
+            &liquidation(LiquidationStatus::Broadcast),
+            observation(101, false, Some(0)),
+        );
+
+        assert_eq!(evicted, LiquidationStep::Broadcast);
+        assert_eq!(in_mempool, LiquidationStep::Broadcast);
+    }
+
+    #[test]
+    fn conflicting_liquidation_means_loan_was_repaid() {
+        let step = next_liquidation_step(
+            &liquidation(LiquidationStatus::Broadcast),
+            observation(101, false, Some(-1)),
+        );
+
+        assert_eq!(step, LiquidationStep::Update(LiquidationStatus::Repaid));
+    }
+
+    #[test]
+    fn utxo_reservations_are_checked_several_times_per_expiry() {
+        assert_eq!(
+            reservation_check_period(Duration::from_secs(60)),
+            Duration::from_secs(15)
+        );
+        assert_eq!(
+            reservation_check_period(Duration::from_secs(2)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn input_secrets_which_open_the_commitments_are_accepted() {
+        let (txout, secrets) = confidential_txout();
+
+        assert!(verify_secrets(&txout, &secrets).is_ok());
+    }
+
+    #[test]
+    fn input_secrets_with_wrong_asset_are_rejected() {
+        let (txout, secrets) = confidential_txout();
+        let secrets = TxOutSecrets {
+            asset: AssetId::from_slice(&[2u8; 32]).unwrap(),
+            ..secrets
+        };
+
+        assert!(verify_secrets(&txout, &secrets).is_err());
+    }
+
+    #[test]
+    fn input_secrets_with_wrong_value_are_rejected() {
+        let (txout, secrets) = confidential_txout();
+        let secrets = TxOutSecrets {
+            value: secrets.value + 1,
+            ..secrets
+        };
+
+        assert!(verify_secrets(&txout, &secrets).is_err());
+    }
+
+    #[test]
+    fn input_secrets_with_wrong_blinding_factors_are_rejected() {
+        let (txout, secrets) = confidential_txout();
+        let wrong_asset_bf = TxOutSecrets {
+            asset_bf: AssetBlindingFactor::new(&mut thread_rng()),
+            ..secrets
+        };
+        let wrong_value_bf = TxOutSecrets {
+            value_bf: ValueBlindingFactor::new(&mut thread_rng()),
+            ..secrets
+        };
+
+        assert!(verify_secrets(&txout, &wrong_asset_bf).is_err());
+        assert!(verify_secrets(&txout, &wrong_value_bf).is_err());
+    }
+
+    #[test]
+    fn loan_handshake_expires_after_ttl() {
+        let ttl = Duration::from_secs(60);
+
+        assert!(!handshake_expired(1_000, 1_000, ttl));
+        assert!(!handshake_expired(1_000, 1_060, ttl));
+        assert!(handshake_expired(1_000, 1_061, ttl));
+        // our clock went backwards
+        assert!(!handshake_expired(1_000, 900, ttl));
+    }
+
+    #[tokio::test]
+    async fn loan_transaction_we_have_not_offered_is_not_pending() {
+        let bob = bobtimus_without_elementsd();
+        let loan_txid = Txid::from_str(&"ab".repeat(32)).unwrap();
+
+        let result = bob.ensure_pending_loan(loan_txid);
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn quote_is_not_used_up_by_rejected_swap() {
+        let mut bob = bobtimus_without_elementsd();
+        let pair_id = PairId::new(LBTC_LUSDT);
+
+        let quote = bob
+            .handle_create_quote(
+                &pair_id,
+                CreateQuotePayload {
+                    direction: Direction::Sell,
+                    amount: Amount::ONE_BTC.as_sat(),
+                    mode: SwapMode::ExactInput,
+                },
+            )
+            .unwrap();
+
+        let mismatch = bob
+            .swap_amounts(
+                &pair_id,
+                Direction::Sell,
+                SwapMode::ExactInput,
+                quote.input_amount + 1,
+                Some(quote.id.clone()),
+            )
+            .unwrap_err();
+        let (amounts, rate) = bob
+            .swap_amounts(
+                &pair_id,
+                Direction::Sell,
+                SwapMode::ExactInput,
+                quote.input_amount,
+                Some(quote.id.clone()),
+            )
+            .unwrap();
+
+        assert!(mismatch.is::<QuoteMismatch>());
+        assert_eq!(amounts.output, quote.output_amount);
+        assert_eq!(rate, quote.rate);
+        assert!(bob.quotes.contains_key(&quote.id));
+    }
+
+    #[tokio::test]
+    async fn number_of_open_quotes_is_capped() {
+        let mut bob = bobtimus_without_elementsd();
+        let pair_id = PairId::new(LBTC_LUSDT);
+        let payload = || CreateQuotePayload {
+            direction: Direction::Buy,
+            amount: 1_000_000_000,
+            mode: SwapMode::ExactInput,
+        };
+
+        for _ in 0..MAX_OPEN_QUOTES {
+            bob.handle_create_quote(&pair_id, payload()).unwrap();
+        }
+        let error = bob.handle_create_quote(&pair_id, payload()).unwrap_err();
+
+        assert!(error.is::<TooManyQuotes>());
+    }
+
     #[tokio::test]
     async fn test_handle_btc_sell_swap_request() {
         let db = Sqlite::new_ephemeral_db().expect("A ephemeral db");
@@ -431,7 +1767,6 @@ mod tests {
         let have_asset_id_alice = client.get_bitcoin_asset_id().await.unwrap();
         let have_asset_id_bob = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
 
-        let rate_service = fixed_rate::Service::new();
         let redeem_amount_bob = Amount::ONE_BTC;
 
         let (
@@ -478,26 +1813,29 @@ mod tests {
             .unwrap();
         client.generatetoaddress(1, &mining_address).await.unwrap();
 
-        let mut bob = Bobtimus {
-            rng: &mut thread_rng(),
-            rate_service,
-            secp: Secp256k1::new(),
-            elementsd: client.clone(),
-            btc_asset_id: have_asset_id_alice,
-            usdt_asset_id: have_asset_id_bob,
+        let mut bob = bobtimus(
+            &client,
             db,
-            lender_states: HashMap::new(),
-        };
+            have_asset_id_alice,
+            have_asset_id_bob,
+            lbtc_lusdt_pair(have_asset_id_alice, have_asset_id_bob),
+        );
 
-        let transaction = bob
-            .handle_create_sell_swap(CreateSwapPayload {
-                alice_inputs: vec![AliceInput {
-                    outpoint: input_alice.0,
-                    blinding_key: fund_blinding_sk_alice,
-                }],
-                address: final_address_alice,
-                amount: redeem_amount_bob.as_sat(),
-            })
+        let (transaction, _) = bob
+            .handle_create_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Sell,
+                CreateSwapPayload {
+                    alice_inputs: vec![AliceInput::BlindingKey {
+                        outpoint: input_alice.0,
+                        blinding_key: fund_blinding_sk_alice,
+                    }],
+                    address: final_address_alice,
+                    amount: redeem_amount_bob.as_sat(),
+                    mode: SwapMode::ExactInput,
+                    quote_id: None,
+                },
+            )
             .await
             .unwrap();
 
@@ -546,7 +1884,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_handle_btc_buy_swap_request() {
+    async fn sell_swap_with_input_secrets_can_be_broadcast() {
         let db = Sqlite::new_ephemeral_db().expect("A ephemeral db");
 
         let tc_client = Cli::default();
@@ -560,110 +1898,1098 @@ mod tests {
         };
         let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
 
-        let have_asset_id_alice = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
-        let have_asset_id_bob = client.get_bitcoin_asset_id().await.unwrap();
-
-        let rate_service = fixed_rate::Service::new();
-        let redeem_amount_bob = LiquidUsdt::from_str_in_dollar("20000.0").unwrap();
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
 
-        let (
-            fund_address_alice,
-            fund_sk_alice,
-            _fund_pk_alice,
-            fund_blinding_sk_alice,
-            _fund_blinding_pk_alice,
-        ) = make_confidential_address();
+        let (fund_address, fund_sk, _fund_pk, fund_blinding_sk, _fund_blinding_pk) =
+            make_confidential_address();
+        let fund_txid = client
+            .send_asset_to_address(
+                &fund_address,
+                Amount::from_btc(2.0).unwrap(),
+                Some(btc_asset_id),
+            )
+            .await
+            .unwrap();
 
-        let fund_alice_txid = client
+        // move issued asset to wallet address
+        let address = client.get_new_segwit_confidential_address().await.unwrap();
+        let _txid = client
             .send_asset_to_address(
-                &fund_address_alice,
-                Amount::from_btc(10_000.0).unwrap() + redeem_amount_bob.into(),
-                Some(have_asset_id_alice),
+                &address,
+                Amount::from_btc(10.0).unwrap(),
+                Some(usdt_asset_id),
             )
             .await
             .unwrap();
         client.generatetoaddress(1, &mining_address).await.unwrap();
 
-        let input_alice = extract_input(
-            &client.get_raw_transaction(fund_alice_txid).await.unwrap(),
-            fund_address_alice,
+        let (outpoint, txout) = extract_input(
+            &client.get_raw_transaction(fund_txid).await.unwrap(),
+            fund_address,
         )
         .unwrap();
+        let secrets = txout.unblind(SECP256K1, fund_blinding_sk).unwrap();
 
-        let (
-            final_address_alice,
-            _final_sk_alice,
-            _final_pk_alice,
-            _final_blinding_sk_alice,
-            _final_blinding_pk_alice,
-        ) = make_confidential_address();
+        let (final_address, _final_sk, _final_pk, final_blinding_sk, _final_blinding_pk) =
+            make_confidential_address();
 
-        let mut bob = Bobtimus {
-            rng: &mut thread_rng(),
-            rate_service,
-            secp: Secp256k1::new(),
-            elementsd: client.clone(),
-            btc_asset_id: have_asset_id_bob,
-            usdt_asset_id: have_asset_id_alice,
+        let mut bob = bobtimus(
+            &client,
             db,
-            lender_states: HashMap::new(),
+            btc_asset_id,
+            usdt_asset_id,
+            lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+        );
+
+        let (mut transaction, _) = bob
+            .handle_create_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Sell,
+                CreateSwapPayload {
+                    alice_inputs: vec![AliceInput::Secrets { outpoint, secrets }],
+                    address: final_address.clone(),
+                    amount: Amount::ONE_BTC.as_sat(),
+                    mode: SwapMode::ExactInput,
+                    quote_id: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let input_index = transaction
+            .input
+            .iter()
+            .position(|txin| txin.previous_output == outpoint)
+            .unwrap();
+        let witness = {
+            let mut cache = SigHashCache::new(&transaction);
+            sign_with_key(&SECP256K1, &mut cache, input_index, &fund_sk, txout.value)
         };
+        transaction.input[input_index].witness.script_witness = witness;
 
-        let transaction = bob
-            .handle_create_buy_swap(CreateSwapPayload {
-                alice_inputs: vec![AliceInput {
-                    outpoint: input_alice.0,
-                    blinding_key: fund_blinding_sk_alice,
-                }],
-                address: final_address_alice,
-                amount: redeem_amount_bob.as_satodollar(),
-            })
+        client.send_raw_transaction(&transaction).await.unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let swaps = bob
+            .db
+            .do_in_transaction(|conn| queries::get_swaps_by_status(conn, SwapStatus::Created))
             .await
             .unwrap();
+        let received = transaction
+            .output
+            .iter()
+            .filter(|txout| txout.script_pubkey == final_address.script_pubkey())
+            .filter_map(|txout| txout.unblind(SECP256K1, final_blinding_sk).ok())
+            .find(|secrets| secrets.asset == usdt_asset_id)
+            .unwrap();
+        assert_eq!(received.value, swaps[0].maker_amount.as_sat());
+    }
 
-        let transaction = swap::alice_finalize_transaction(transaction, {
-            let value = input_alice.1.value;
-            move |mut tx| async move {
-                let input_index = tx
-                    .input
-                    .iter()
-                    .position(|txin| fund_alice_txid == txin.previous_output.txid)
-                    .context("transaction does not contain input")?;
-                let mut cache = SigHashCache::new(&tx);
+    #[tokio::test]
+    async fn buy_swap_with_input_secrets_can_be_broadcast() {
+        let db = Sqlite::new_ephemeral_db().expect("A ephemeral db");
 
-                tx.input[input_index].witness.script_witness =
-                    sign_with_key(&SECP256K1, &mut cache, input_index, &fund_sk_alice, value);
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
 
-                Ok(tx)
-            }
-        })
-        .await
-        .unwrap();
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
 
-        let _txid = client.send_raw_transaction(&transaction).await.unwrap();
-        let _txid = client.generatetoaddress(1, &mining_address).await.unwrap();
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
 
-        let utxos = client
-            .listunspent(
-                None,
-                None,
-                None,
-                None,
-                Some(ListUnspentOptions {
-                    asset: Some(have_asset_id_alice),
-                    ..Default::default()
-                }),
+        // Alice spends L-USDt, but pays her share of the transaction
+        // fee with an L-BTC input
+        let (fund_address, fund_sk, _fund_pk, fund_blinding_sk, _fund_blinding_pk) =
+            make_confidential_address();
+        let usdt_amount = LiquidUsdt::from_str_in_dollar("20000.0").unwrap();
+        let fund_usdt_txid = client
+            .send_asset_to_address(&fund_address, usdt_amount.into(), Some(usdt_asset_id))
+            .await
+            .unwrap();
+        let fund_btc_txid = client
+            .send_asset_to_address(
+                &fund_address,
+                Amount::from_btc(0.1).unwrap(),
+                Some(btc_asset_id),
             )
             .await
             .unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
 
-        assert!(utxos.iter().any(
-            |utxo| (utxo.amount - Amount::from(redeem_amount_bob).as_btc()).abs() < f64::EPSILON
-                && utxo.spendable
-        ));
+        let mut alice_inputs = Vec::new();
+        for txid in [fund_usdt_txid, fund_btc_txid].iter() {
+            let (outpoint, txout) = extract_input(
+                &client.get_raw_transaction(*txid).await.unwrap(),
+                fund_address.clone(),
+            )
+            .unwrap();
+            let secrets = txout.unblind(SECP256K1, fund_blinding_sk).unwrap();
+
+            alice_inputs.push((outpoint, txout, secrets));
+        }
+
+        let (final_address, _final_sk, _final_pk, final_blinding_sk, _final_blinding_pk) =
+            make_confidential_address();
+
+        let mut bob = bobtimus(
+            &client,
+            db,
+            btc_asset_id,
+            usdt_asset_id,
+            lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+        );
+
+        let (mut transaction, _) = bob
+            .handle_create_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Buy,
+                CreateSwapPayload {
+                    alice_inputs: alice_inputs
+                        .iter()
+                        .map(|(outpoint, _, secrets)| AliceInput::Secrets {
+                            outpoint: *outpoint,
+                            secrets: *secrets,
+                        })
+                        .collect(),
+                    address: final_address.clone(),
+                    amount: usdt_amount.as_satodollar(),
+                    mode: SwapMode::ExactInput,
+                    quote_id: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        for (outpoint, txout, _) in alice_inputs.iter() {
+            let input_index = transaction
+                .input
+                .iter()
+                .position(|txin| txin.previous_output == *outpoint)
+                .unwrap();
+            let witness = {
+                let mut cache = SigHashCache::new(&transaction);
+                sign_with_key(&SECP256K1, &mut cache, input_index, &fund_sk, txout.value)
+            };
+            transaction.input[input_index].witness.script_witness = witness;
+        }
+
+        client.send_raw_transaction(&transaction).await.unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let swaps = bob
+            .db
+            .do_in_transaction(|conn| queries::get_swaps_by_status(conn, SwapStatus::Created))
+            .await
+            .unwrap();
+        let received = transaction
+            .output
+            .iter()
+            .filter(|txout| txout.script_pubkey == final_address.script_pubkey())
+            .filter_map(|txout| txout.unblind(SECP256K1, final_blinding_sk).ok())
+            .filter(|secrets| secrets.asset == btc_asset_id)
+            .map(|secrets| secrets.value)
+            .collect::<Vec<_>>();
+        // What Alice bought, and the change of her L-BTC input
+        assert_eq!(received.len(), 2);
+        assert!(received.contains(&swaps[0].maker_amount.as_sat()));
+    }
+
+    #[tokio::test]
+    async fn swap_with_service_fee_pays_it_at_max_precision() {
+        let db = Sqlite::new_ephemeral_db().expect("A ephemeral db");
+
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+
+        let (fund_address, fund_sk, _fund_pk, fund_blinding_sk, _fund_blinding_pk) =
+            make_confidential_address();
+        let fund_txid = client
+            .send_asset_to_address(
+                &fund_address,
+                Amount::from_btc(2.0).unwrap(),
+                Some(btc_asset_id),
+            )
+            .await
+            .unwrap();
+
+        // move issued asset to wallet address
+        let address = client.get_new_segwit_confidential_address().await.unwrap();
+        let _txid = client
+            .send_asset_to_address(
+                &address,
+                Amount::from_btc(30_000.0).unwrap(),
+                Some(usdt_asset_id),
+            )
+            .await
+            .unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let (outpoint, txout) = extract_input(
+            &client.get_raw_transaction(fund_txid).await.unwrap(),
+            fund_address,
+        )
+        .unwrap();
+
+        let (final_address, _final_sk, _final_pk, final_blinding_sk, _final_blinding_pk) =
+            make_confidential_address();
+        let service_fee_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        // L-USDt amounts are in cents, 1.50 L-USDt of service fee
+        let mut config = lbtc_lusdt_config(btc_asset_id, usdt_asset_id);
+        config.quote_precision = 2;
+        config.service_fee = Some(ServiceFee::Flat(150));
+
+        let mut bob = bobtimus(
+            &client,
+            db,
+            btc_asset_id,
+            usdt_asset_id,
+            trading_pairs(config, btc_asset_id),
+        );
+        bob.service_fee_address = Some(service_fee_address.clone());
+
+        let (mut transaction, service_fee_secrets) = bob
+            .handle_create_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Sell,
+                CreateSwapPayload {
+                    alice_inputs: vec![AliceInput::BlindingKey {
+                        outpoint,
+                        blinding_key: fund_blinding_sk,
+                    }],
+                    address: final_address.clone(),
+                    amount: Amount::ONE_BTC.as_sat(),
+                    mode: SwapMode::ExactInput,
+                    quote_id: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let input_index = transaction
+            .input
+            .iter()
+            .position(|txin| txin.previous_output == outpoint)
+            .unwrap();
+        let witness = {
+            let mut cache = SigHashCache::new(&transaction);
+            sign_with_key(&SECP256K1, &mut cache, input_index, &fund_sk, txout.value)
+        };
+        transaction.input[input_index].witness.script_witness = witness;
+
+        client.send_raw_transaction(&transaction).await.unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let service_fee = Amount::from_sat(150_000_000);
+        let fee_outputs = transaction
+            .output
+            .iter()
+            .filter(|txout| txout.script_pubkey == service_fee_address.script_pubkey())
+            .collect::<Vec<_>>();
+        assert_eq!(fee_outputs.len(), 1);
+        assert!(!matches!(fee_outputs[0].value, Value::Explicit(_)));
+
+        // Alice can only check the service fee with the secrets of its
+        // blinded output
+        let service_fee_secrets = service_fee_secrets.unwrap();
+        verify_secrets(fee_outputs[0], &service_fee_secrets).unwrap();
+        assert_eq!(service_fee_secrets.asset, usdt_asset_id);
+        assert_eq!(service_fee_secrets.value, service_fee.as_sat());
+
+        let swaps = bob
+            .db
+            .do_in_transaction(|conn| queries::get_swaps_by_status(conn, SwapStatus::Created))
+            .await
+            .unwrap();
+        let received = transaction
+            .output
+            .iter()
+            .filter(|txout| txout.script_pubkey == final_address.script_pubkey())
+            .filter_map(|txout| txout.unblind(SECP256K1, final_blinding_sk).ok())
+            .find(|secrets| secrets.asset == usdt_asset_id)
+            .unwrap();
+        assert_eq!(swaps[0].service_fee, service_fee);
+        assert_eq!(received.value, swaps[0].maker_amount.as_sat());
+        // 19,000 L-USDt at the bid, minus the service fee
+        assert_eq!(
+            swaps[0].maker_amount,
+            Amount::from_btc(19_000.0).unwrap() - service_fee
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_btc_buy_swap_request() {
+        let db = Sqlite::new_ephemeral_db().expect("A ephemeral db");
+
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        let have_asset_id_alice = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+        let have_asset_id_bob = client.get_bitcoin_asset_id().await.unwrap();
+
+        let redeem_amount_bob = LiquidUsdt::from_str_in_dollar("20000.0").unwrap();
+
+        let (
+            fund_address_alice,
+            fund_sk_alice,
+            _fund_pk_alice,
+            fund_blinding_sk_alice,
+            _fund_blinding_pk_alice,
+        ) = make_confidential_address();
+
+        let fund_alice_txid = client
+            .send_asset_to_address(
+                &fund_address_alice,
+                Amount::from_btc(10_000.0).unwrap() + redeem_amount_bob.into(),
+                Some(have_asset_id_alice),
+            )
+            .await
+            .unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let input_alice = extract_input(
+            &client.get_raw_transaction(fund_alice_txid).await.unwrap(),
+            fund_address_alice,
+        )
+        .unwrap();
+
+        let (
+            final_address_alice,
+            _final_sk_alice,
+            _final_pk_alice,
+            _final_blinding_sk_alice,
+            _final_blinding_pk_alice,
+        ) = make_confidential_address();
+
+        let mut bob = bobtimus(
+            &client,
+            db,
+            have_asset_id_bob,
+            have_asset_id_alice,
+            lbtc_lusdt_pair(have_asset_id_bob, have_asset_id_alice),
+        );
+
+        let (transaction, _) = bob
+            .handle_create_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Buy,
+                CreateSwapPayload {
+                    alice_inputs: vec![AliceInput::BlindingKey {
+                        outpoint: input_alice.0,
+                        blinding_key: fund_blinding_sk_alice,
+                    }],
+                    address: final_address_alice,
+                    amount: redeem_amount_bob.as_satodollar(),
+                    mode: SwapMode::ExactInput,
+                    quote_id: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let transaction = swap::alice_finalize_transaction(transaction, {
+            let value = input_alice.1.value;
+            move |mut tx| async move {
+                let input_index = tx
+                    .input
+                    .iter()
+                    .position(|txin| fund_alice_txid == txin.previous_output.txid)
+                    .context("transaction does not contain input")?;
+                let mut cache = SigHashCache::new(&tx);
+
+                tx.input[input_index].witness.script_witness =
+                    sign_with_key(&SECP256K1, &mut cache, input_index, &fund_sk_alice, value);
+
+                Ok(tx)
+            }
+        })
+        .await
+        .unwrap();
+
+        let _txid = client.send_raw_transaction(&transaction).await.unwrap();
+        let _txid = client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let utxos = client
+            .listunspent(
+                None,
+                None,
+                None,
+                None,
+                Some(ListUnspentOptions {
+                    asset: Some(have_asset_id_alice),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+
+        assert!(utxos.iter().any(
+            |utxo| (utxo.amount - Amount::from(redeem_amount_bob).as_btc()).abs() < f64::EPSILON
+                && utxo.spendable
+        ));
+    }
+
+    #[tokio::test]
+    async fn swap_amounts_are_scaled_to_max_precision() {
+        let db = Sqlite::new_ephemeral_db().expect("A ephemeral db");
+
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+
+        let (fund_address_alice, _, _, fund_blinding_sk_alice, _) = make_confidential_address();
+        let fund_alice_txid = client
+            .send_asset_to_address(
+                &fund_address_alice,
+                Amount::from_btc(2.0).unwrap(),
+                Some(btc_asset_id),
+            )
+            .await
+            .unwrap();
+
+        // move issued asset to wallet address
+        let address = client.get_new_segwit_confidential_address().await.unwrap();
+        client
+            .send_asset_to_address(
+                &address,
+                Amount::from_btc(50_000.0).unwrap(),
+                Some(usdt_asset_id),
+            )
+            .await
+            .unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let input_alice = extract_input(
+            &client.get_raw_transaction(fund_alice_txid).await.unwrap(),
+            fund_address_alice,
+        )
+        .unwrap();
+
+        let (final_address_alice, _, _, final_blinding_sk_alice, _) = make_confidential_address();
+
+        // L-USDt is traded in cents
+        let pairs = trading_pairs(
+            TradingPairConfig {
+                quote_precision: 2,
+                ..lbtc_lusdt_config(btc_asset_id, usdt_asset_id)
+            },
+            btc_asset_id,
+        );
+        let mut bob = bobtimus(&client, db, btc_asset_id, usdt_asset_id, pairs);
+
+        let (transaction, _) = bob
+            .handle_create_swap(
+                &PairId::new(LBTC_LUSDT),
+                Direction::Sell,
+                CreateSwapPayload {
+                    alice_inputs: vec![AliceInput::BlindingKey {
+                        outpoint: input_alice.0,
+                        blinding_key: fund_blinding_sk_alice,
+                    }],
+                    address: final_address_alice.clone(),
+                    amount: Amount::ONE_BTC.as_sat(),
+                    mode: SwapMode::ExactInput,
+                    quote_id: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let received = transaction
+            .output
+            .iter()
+            .filter(|txout| txout.script_pubkey == final_address_alice.script_pubkey())
+            .filter_map(|txout| txout.unblind(SECP256K1, final_blinding_sk_alice).ok())
+            .find(|secrets| secrets.asset == usdt_asset_id)
+            .expect("Alice receives L-USDt");
+
+        // 19,000 L-USDt, rather than 19,000 cents
+        assert_eq!(received.value, 1_900_000_000_000);
+    }
+
+    #[tokio::test]
+    async fn lender_state_is_reloaded_after_restart() {
+        let db_file = tempfile::Builder::new()
+            .suffix(".sqlite")
+            .tempfile()
+            .unwrap()
+            .into_temp_path();
+        let db = Sqlite::new(&db_file).unwrap();
+
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let mut bob = bobtimus(
+            &client,
+            db,
+            btc_asset_id,
+            usdt_asset_id,
+            lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+        );
+        let term = bob.loan_terms[0].term_blocks;
+
+        let (borrower, _, _) =
+            borrower(&client, btc_asset_id, usdt_asset_id, Amount::ONE_BTC, term).await;
+        let loan_txid = bob
+            .handle_loan_request(CreateLoanPayload {
+                loan_request: borrower.loan_request(),
+                term,
+            })
+            .await
+            .unwrap()
+            .transaction
+            .txid();
+
+        let reloaded = Sqlite::new(&db_file)
+            .unwrap()
+            .do_in_transaction(|conn| queries::get_lender_states(conn))
+            .await
+            .unwrap();
+
+        let original = &bob.lender_states[&loan_txid];
+        let reloaded = reloaded
+            .get(&loan_txid)
+            .expect("lender state was persisted");
+        assert_eq!(reloaded.created_at, original.created_at);
+        assert_eq!(reloaded.collateral_amount, original.collateral_amount);
+        assert_eq!(reloaded.principal_amount, original.principal_amount);
+        assert_eq!(
+            serde_json::to_value(&reloaded.lender).unwrap(),
+            serde_json::to_value(&original.lender).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn loan_can_be_finalized_after_restart() {
+        let db_file = tempfile::Builder::new()
+            .suffix(".sqlite")
+            .tempfile()
+            .unwrap()
+            .into_temp_path();
+
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let (borrower, collateral_input, borrower_sk) = {
+            let mut bob = bobtimus(
+                &client,
+                Sqlite::new(&db_file).unwrap(),
+                btc_asset_id,
+                usdt_asset_id,
+                lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+            );
+            let term = bob.loan_terms[0].term_blocks;
+
+            let (borrower, collateral_input, borrower_sk) =
+                borrower(&client, btc_asset_id, usdt_asset_id, Amount::ONE_BTC, term).await;
+            let loan_response = bob
+                .handle_loan_request(CreateLoanPayload {
+                    loan_request: borrower.loan_request(),
+                    term,
+                })
+                .await
+                .unwrap();
+
+            (
+                borrower.interpret(SECP256K1, loan_response).unwrap(),
+                collateral_input,
+                borrower_sk,
+            )
+        };
+
+        // Only what was persisted survives the restart
+        let mut bob = bobtimus(
+            &client,
+            Sqlite::new(&db_file).unwrap(),
+            btc_asset_id,
+            usdt_asset_id,
+            lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+        );
+        bob.lender_states = bob
+            .db
+            .do_in_transaction(|conn| queries::get_lender_states(conn))
+            .await
+            .unwrap();
+
+        let loan_transaction = borrower
+            .sign(|mut transaction| async move {
+                let input_index = transaction
+                    .input
+                    .iter()
+                    .position(|txin| txin.previous_output == collateral_input.txin)
+                    .context("loan transaction does not spend the collateral")?;
+                let witness = {
+                    let mut cache = SigHashCache::new(&transaction);
+                    sign_with_key(
+                        &SECP256K1,
+                        &mut cache,
+                        input_index,
+                        &borrower_sk,
+                        collateral_input.original_txout.value,
+                    )
+                };
+                transaction.input[input_index].witness.script_witness = witness;
+
+                Ok(transaction)
+            })
+            .await
+            .unwrap();
+        let loan_txid = loan_transaction.txid();
+
+        let txid = bob.finalize_loan(loan_transaction).await.unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let (persisted, liquidations) = bob
+            .db
+            .do_in_transaction(|conn| {
+                Ok((
+                    queries::get_lender_states(conn)?,
+                    queries::get_active_liquidations(conn)?,
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(txid, loan_txid);
+        assert!(client.get_raw_transaction(txid).await.is_ok());
+        assert!(bob.lender_states.is_empty());
+        assert!(persisted.is_empty());
+        assert_eq!(liquidations.len(), 1);
+        assert_eq!(liquidations[0].loan_txid, loan_txid);
+    }
+
+    #[tokio::test]
+    async fn expired_loan_handshakes_are_rejected_and_forgotten() {
+        let db = Sqlite::new_ephemeral_db().expect("A ephemeral db");
+
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let mut bob = bobtimus(
+            &client,
+            db,
+            btc_asset_id,
+            usdt_asset_id,
+            lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+        );
+        bob.loan_handshake_ttl = Duration::from_secs(0);
+        let term = bob.loan_terms[0].term_blocks;
+
+        let (borrower, _, _) =
+            borrower(&client, btc_asset_id, usdt_asset_id, Amount::ONE_BTC, term).await;
+        let loan_transaction = bob
+            .handle_loan_request(CreateLoanPayload {
+                loan_request: borrower.loan_request(),
+                term,
+            })
+            .await
+            .unwrap()
+            .transaction;
+        let loan_txid = loan_transaction.txid();
+        let inputs = loan_transaction
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>();
+
+        // Only our inputs are locked in our wallet
+        let locked = client.listlockunspent().await.unwrap();
+        assert!(inputs.iter().any(|outpoint| locked.contains(outpoint)));
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let error = bob.finalize_loan(loan_transaction).await.unwrap_err();
+        assert!(error.is::<LoanHandshakeExpired>());
+
+        bob.expire_loan_handshakes().await;
+
+        let persisted = bob
+            .db
+            .do_in_transaction(|conn| queries::get_lender_states(conn))
+            .await
+            .unwrap();
+        let locked = client.listlockunspent().await.unwrap();
+        assert!(!bob.lender_states.contains_key(&loan_txid));
+        assert!(persisted.is_empty());
+        assert!(inputs.iter().all(|outpoint| !locked.contains(outpoint)));
+    }
+
+    #[tokio::test]
+    async fn expired_swap_is_invalidated_by_spending_our_input() {
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let db = Sqlite::new_ephemeral_db().unwrap();
+        let fee_rate_service =
+            fee_rate::Service::new(client.clone(), 1, Amount::ONE_SAT, Amount::ONE_SAT).unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+        let (swap_txid, outpoint) = expired_swap(&client, &db, usdt_asset_id).await;
+
+        update_utxo_reservations(&client, &db, &fee_rate_service, btc_asset_id)
+            .await
+            .unwrap();
+
+        let (invalidated, reservations) = db
+            .do_in_transaction(|conn| {
+                Ok((
+                    queries::get_swaps_by_status(conn, SwapStatus::Invalidated)?,
+                    queries::get_utxo_reservations(conn)?,
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(invalidated.len(), 1);
+        assert_eq!(invalidated[0].txid, swap_txid);
+        assert!(reservations.is_empty());
+
+        let invalidation_txid = invalidated[0].invalidation_txid.unwrap();
+        let invalidation = client.get_raw_transaction(invalidation_txid).await.unwrap();
+        let spent = invalidation
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect::<Vec<_>>();
+        assert!(spent.contains(&outpoint));
+        assert_eq!(spent.len(), 2, "L-BTC input pays the fee");
+    }
+
+    #[tokio::test]
+    async fn inputs_of_swap_stay_locked_if_invalidation_fails() {
+        let tc_client = Cli::default();
+        let (client, _container) = {
+            let blockchain = Elementsd::new(&tc_client, "0.18.1.9").unwrap();
+
+            (
+                Client::new(blockchain.node_url.clone().into()).unwrap(),
+                blockchain,
+            )
+        };
+        let db = Sqlite::new_ephemeral_db().unwrap();
+        // No input can pay a fee at this rate
+        let fee_rate = Amount::from_sat(1_000_000_000);
+        let fee_rate_service =
+            fee_rate::Service::new(client.clone(), 1, fee_rate, fee_rate).unwrap();
+
+        let btc_asset_id = client.get_bitcoin_asset_id().await.unwrap();
+        let usdt_asset_id = client.issueasset(100_000.0, 0.0, true).await.unwrap().asset;
+        let (swap_txid, outpoint) = expired_swap(&client, &db, usdt_asset_id).await;
+
+        update_utxo_reservations(&client, &db, &fee_rate_service, btc_asset_id)
+            .await
+            .unwrap();
+
+        let created = db
+            .do_in_transaction(|conn| queries::get_swaps_by_status(conn, SwapStatus::Created))
+            .await
+            .unwrap();
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].txid, swap_txid);
+
+        let locked = client.listlockunspent().await.unwrap();
+        assert_eq!(
+            locked,
+            vec![outpoint],
+            "only the input of the swap is locked"
+        );
+    }
+
+    /// Record a swap whose reservation of our UTXO of `asset` has
+    /// already expired, locking the UTXO as if we had signed it.
+    async fn expired_swap(client: &Client, db: &Sqlite, asset: AssetId) -> (Txid, OutPoint) {
+        let address = client.get_new_segwit_confidential_address().await.unwrap();
+        client.generatetoaddress(1, &address).await.unwrap();
+
+        let utxo = client.get_largest_utxo(asset).await.unwrap();
+        let outpoint = OutPoint {
+            txid: utxo.txid,
+            vout: utxo.vout,
+        };
+        client.lock_utxos(vec![outpoint]).await.unwrap();
+
+        // The swap transaction itself is never broadcast, so any
+        // identifier will do
+        let swap_txid = Txid::from_str(&"ab".repeat(32)).unwrap();
+        let created_at = unix_timestamp() - 120;
+        db.do_in_transaction(|conn| {
+            SwapForm::new(
+                swap_txid,
+                Direction::Buy,
+                (asset, Amount::from_btc(utxo.amount)?),
+                (asset, Amount::ONE_SAT),
+                Amount::ZERO,
+                LiquidUsdt::from_str_in_dollar("30000.0")?,
+                &address,
+                created_at,
+                0,
+            )?
+            .insert(conn)?;
+            UtxoReservationForm::new(outpoint, swap_txid, created_at + 60)?.insert(conn)
+        })
+        .await
+        .unwrap();
+
+        (swap_txid, outpoint)
+    }
+
+    /// A borrower who puts up `collateral` for a loan of the given
+    /// term, from an output funded by elementsd.
+    ///
+    /// The funding input is returned together with the key which can
+    /// sign for it.
+    async fn borrower(
+        client: &Client,
+        btc_asset_id: AssetId,
+        usdt_asset_id: AssetId,
+        collateral_amount: Amount,
+        term: u32,
+    ) -> (Borrower0, Input, SecretKey) {
+        let mining_address = client.get_new_segwit_confidential_address().await.unwrap();
+        let (address, secret_key, _, blinding_key, _) = make_confidential_address();
+
+        let funding_txid = client
+            .send_asset_to_address(
+                &address,
+                collateral_amount + Amount::ONE_BTC,
+                Some(btc_asset_id),
+            )
+            .await
+            .unwrap();
+        client.generatetoaddress(1, &mining_address).await.unwrap();
+
+        let (outpoint, txout) = extract_input(
+            &client.get_raw_transaction(funding_txid).await.unwrap(),
+            address.clone(),
+        )
+        .unwrap();
+        let input = Input {
+            txin: outpoint,
+            original_txout: txout,
+            blinding_key,
+        };
+
+        let timelock = u64::from(client.get_blockcount().await.unwrap()) + u64::from(term);
+
+        let borrower = Borrower0::new(
+            &mut thread_rng(),
+            {
+                let input = input.clone();
+                move |_, _| {
+                    let input = input.clone();
+                    async move { Result::<_, anyhow::Error>::Ok(vec![input]) }
+                }
+            },
+            address,
+            blinding_key,
+            collateral_amount,
+            Amount::ONE_SAT,
+            timelock,
+            btc_asset_id,
+            usdt_asset_id,
+        )
+        .await
+        .unwrap();
+
+        (borrower, input, secret_key)
+    }
+
+    /// A Bobtimus which can only be used for what does not need
+    /// elementsd, such as quotes.
+    fn bobtimus_without_elementsd() -> Bobtimus<ThreadRng, fixed_rate::Service> {
+        let btc_asset_id =
+            AssetId::from_str("6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d")
+                .unwrap();
+        let usdt_asset_id = AssetId::from_str(USDT_ASSET_ID).unwrap();
+
+        bobtimus(
+            &Client::new("http://127.0.0.1:7042".to_string()).unwrap(),
+            Sqlite::new_ephemeral_db().unwrap(),
+            btc_asset_id,
+            usdt_asset_id,
+            lbtc_lusdt_pair(btc_asset_id, usdt_asset_id),
+        )
+    }
+
+    pub(crate) fn bobtimus(
+        client: &Client,
+        db: Sqlite,
+        btc_asset_id: AssetId,
+        usdt_asset_id: AssetId,
+        pairs: HashMap<PairId, TradingPair<fixed_rate::Service>>,
+    ) -> Bobtimus<ThreadRng, fixed_rate::Service> {
+        Bobtimus {
+            rng: thread_rng(),
+            secp: Secp256k1::new(),
+            elementsd: client.clone(),
+            btc_asset_id,
+            usdt_asset_id,
+            pairs,
+            db,
+            lender_states: HashMap::new(),
+            loan_handshake_ttl: Duration::from_secs(60),
+            quotes: HashMap::new(),
+            quote_ttl: Duration::from_secs(30),
+            swap_expiry: Duration::from_secs(60),
+            loan_terms: vec![LoanTerms {
+                max_ltv: 0.7,
+                interest_rate: 0.1,
+                term_days: 30,
+                term_blocks: 43_200,
+            }],
+            loan_ltv_threshold: 0.9,
+            service_fee_address: None,
+            fee_rate_service: fee_rate::Service::new(
+                client.clone(),
+                1,
+                Amount::ONE_SAT,
+                Amount::ONE_SAT,
+            )
+            .unwrap(),
+        }
+    }
+
+    pub(crate) fn lbtc_lusdt_pair(
+        btc_asset_id: AssetId,
+        usdt_asset_id: AssetId,
+    ) -> HashMap<PairId, TradingPair<fixed_rate::Service>> {
+        trading_pairs(lbtc_lusdt_config(btc_asset_id, usdt_asset_id), btc_asset_id)
+    }
+
+    fn lbtc_lusdt_config(btc_asset_id: AssetId, usdt_asset_id: AssetId) -> TradingPairConfig {
+        TradingPairConfig {
+            id: PairId::new(LBTC_LUSDT),
+            base_asset_id: Some(btc_asset_id),
+            quote_asset_id: usdt_asset_id,
+            base_precision: 8,
+            quote_precision: 8,
+            rate_source: RateSource::Fixed {
+                ask: 20_000.0,
+                bid: 19_000.0,
+            },
+            limits: TradeLimits {
+                buy: Limits::new(0, u64::MAX).unwrap(),
+                sell: Limits::new(0, u64::MAX).unwrap(),
+            },
+            service_fee: None,
+            target_base_ratio: None,
+        }
+    }
+
+    fn trading_pairs(
+        config: TradingPairConfig,
+        btc_asset_id: AssetId,
+    ) -> HashMap<PairId, TradingPair<fixed_rate::Service>> {
+        let pair = TradingPair::new(config, btc_asset_id, fixed_rate::Service::new()).unwrap();
+
+        vec![(pair.id.clone(), pair)].into_iter().collect()
+    }
+
+    /// A confidential output together with the secrets which open its
+    /// commitments.
+    fn confidential_txout() -> (TxOut, TxOutSecrets) {
+        let secrets = TxOutSecrets::new(
+            AssetId::from_slice(&[1u8; 32]).unwrap(),
+            AssetBlindingFactor::new(&mut thread_rng()),
+            100_000,
+            ValueBlindingFactor::new(&mut thread_rng()),
+        );
+        let asset = Asset::new_confidential(SECP256K1, secrets.asset, secrets.asset_bf);
+        let value = Value::new_confidential(
+            SECP256K1,
+            secrets.value,
+            asset.commitment().unwrap(),
+            secrets.value_bf,
+        );
+        let txout = TxOut {
+            asset,
+            value,
+            nonce: Nonce::Null,
+            script_pubkey: Script::new(),
+            witness: TxOutWitness::default(),
+        };
+
+        (txout, secrets)
     }
 
-    fn extract_input(tx: &Transaction, address: Address) -> Result<(OutPoint, TxOut)> {
+    pub(crate) fn extract_input(tx: &Transaction, address: Address) -> Result<(OutPoint, TxOut)> {
         let vout = tx
             .output
             .iter()
@@ -678,7 +3004,7 @@ mod tests {
         Ok((outpoint, txout))
     }
 
-    fn make_keypair() -> (SecretKey, PublicKey) {
+    pub(crate) fn make_keypair() -> (SecretKey, PublicKey) {
         let sk = SecretKey::new(&mut thread_rng());
         let pk = PublicKey::from_private_key(
             &SECP256K1,
@@ -692,7 +3018,8 @@ mod tests {
         (sk, pk)
     }
 
-    fn make_confidential_address() -> (Address, SecretKey, PublicKey, SecretKey, PublicKey) {
+    pub(crate) fn make_confidential_address(
+    ) -> (Address, SecretKey, PublicKey, SecretKey, PublicKey) {
         let (sk, pk) = make_keypair();
         let (blinding_sk, blinding_pk) = make_keypair();
 
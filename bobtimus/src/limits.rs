@@ -0,0 +1,129 @@
+use crate::quote::Direction;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// The smallest and largest amount a taker can swap with us, in units
+/// of the precision of the asset they are selling, like every amount
+/// of a trading pair.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedLimits")]
+pub struct Limits {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Limits {
+    pub fn new(min: u64, max: u64) -> Result<Self> {
+        if min > max {
+            bail!("minimum trade amount {} is above maximum {}", min, max)
+        }
+
+        Ok(Self { min, max })
+    }
+}
+
+#[derive(Deserialize)]
+struct UncheckedLimits {
+    min: u64,
+    max: u64,
+}
+
+impl TryFrom<UncheckedLimits> for Limits {
+    type Error = anyhow::Error;
+
+    fn try_from(limits: UncheckedLimits) -> Result<Self> {
+        Self::new(limits.min, limits.max)
+    }
+}
+
+/// The trade limits for a trading pair.
+///
+/// The limits of the `buy` direction are expressed in the quote asset
+/// and the ones of the `sell` direction in the base asset, since that
+/// is what the taker sends us.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TradeLimits {
+    pub buy: Limits,
+    pub sell: Limits,
+}
+
+impl TradeLimits {
+    /// Check that the taker is sending us an `amount` we are willing
+    /// to trade in the given `direction`.
+    pub fn check(&self, direction: Direction, amount: u64) -> Result<()> {
+        let limits = match direction {
+            Direction::Buy => self.buy,
+            Direction::Sell => self.sell,
+        };
+
+        if amount < limits.min {
+            return Err(TradeTooSmall {
+                amount,
+                min: limits.min,
+            }
+            .into());
+        }
+
+        if amount > limits.max {
+            return Err(TradeTooLarge {
+                amount,
+                max: limits.max,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("trade amount {amount} is below the minimum of {min}")]
+pub struct TradeTooSmall {
+    pub amount: u64,
+    pub min: u64,
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("trade amount {amount} is above the maximum of {max}")]
+pub struct TradeTooLarge {
+    pub amount: u64,
+    pub max: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade_limits() -> TradeLimits {
+        TradeLimits {
+            buy: Limits::new(1_000, 10_000).unwrap(),
+            sell: Limits::new(10, 100).unwrap(),
+        }
+    }
+
+    #[test]
+    fn amounts_within_limits_are_accepted() {
+        let limits = trade_limits();
+
+        assert!(limits.check(Direction::Buy, 1_000).is_ok());
+        assert!(limits.check(Direction::Buy, 10_000).is_ok());
+        assert!(limits.check(Direction::Sell, 50).is_ok());
+    }
+
+    #[test]
+    fn amounts_outside_limits_are_rejected() {
+        let limits = trade_limits();
+
+        let too_small = limits.check(Direction::Sell, 9).unwrap_err();
+        let too_large = limits.check(Direction::Buy, 10_001).unwrap_err();
+
+        assert!(too_small.is::<TradeTooSmall>());
+        assert!(too_large.is::<TradeTooLarge>());
+    }
+
+    #[test]
+    fn min_cannot_be_above_max() {
+        assert!(Limits::new(2, 1).is_err());
+    }
+}
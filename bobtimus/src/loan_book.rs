@@ -0,0 +1,222 @@
+use crate::{
+    database::{queries, Liquidation, LiquidationStatus, Sqlite},
+    elements_rpc::Client,
+    kraken, Rate,
+};
+use anyhow::{Context, Result};
+use elements::{bitcoin::Amount, Txid};
+use futures::TryStreamExt;
+use reqwest::Url;
+use serde::Serialize;
+use std::{fmt, path::Path};
+
+/// All the loans we have made which are not settled yet, valued at
+/// the given rate.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoanBook {
+    pub rate: Rate,
+    pub blockcount: u32,
+    /// Loans whose loan-to-value ratio is above this threshold are
+    /// flagged.
+    pub ltv_threshold: f64,
+    pub loans: Vec<OpenLoan>,
+}
+
+/// Print the loan book stored in `db_file`, valued at the current
+/// rate of the Kraken ticker `kraken_pair`.
+pub async fn print(
+    elementsd_url: Url,
+    db_file: &Path,
+    kraken_pair: &str,
+    ltv_threshold: f64,
+    json: bool,
+) -> Result<()> {
+    let db = Sqlite::new(db_file)?;
+    let elementsd = Client::new(elementsd_url.into())?;
+
+    let loan_book =
+        LoanBook::fetch_at_kraken_rate(&elementsd, &db, kraken_pair, ltv_threshold).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&loan_book)?);
+    } else {
+        print!("{}", loan_book);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenLoan {
+    pub loan_txid: Txid,
+    #[serde(with = "::elements::bitcoin::util::amount::serde::as_sat")]
+    pub collateral_amount: Amount,
+    #[serde(with = "::elements::bitcoin::util::amount::serde::as_sat")]
+    pub principal_amount: Amount,
+    pub locktime: u32,
+    /// Number of blocks until we can liquidate the loan, zero if we
+    /// already can.
+    pub blocks_remaining: u32,
+    /// The value of the principal divided by the value of the
+    /// collateral. Unknown for loans recorded without their amounts.
+    pub ltv: Option<f64>,
+    pub above_threshold: bool,
+    pub status: LiquidationStatus,
+}
+
+impl LoanBook {
+    pub async fn fetch(
+        elementsd: &Client,
+        db: &Sqlite,
+        rate: Rate,
+        ltv_threshold: f64,
+    ) -> Result<Self> {
+        let blockcount = elementsd.get_blockcount().await?;
+        let liquidations = db
+            .do_in_transaction(|conn| queries::get_active_liquidations(conn))
+            .await?;
+
+        Ok(Self::new(liquidations, rate, blockcount, ltv_threshold))
+    }
+
+    /// Fetch the loan book valued at the next rate published on the
+    /// Kraken ticker `pair`, for when no rate service is running.
+    pub async fn fetch_at_kraken_rate(
+        elementsd: &Client,
+        db: &Sqlite,
+        pair: &str,
+        ltv_threshold: f64,
+    ) -> Result<Self> {
+        let rates = kraken::RateService::new(pair)
+            .await?
+            .subscribe()
+            .into_stream();
+        futures::pin_mut!(rates);
+        let rate = rates
+            .try_next()
+            .await?
+            .with_context(|| format!("no rate received for {}", pair))?;
+
+        Self::fetch(elementsd, db, rate, ltv_threshold).await
+    }
+
+    fn new(
+        liquidations: Vec<Liquidation>,
+        rate: Rate,
+        blockcount: u32,
+        ltv_threshold: f64,
+    ) -> Self {
+        let loans = liquidations
+            .into_iter()
+            .map(|liquidation| {
+                let ltv = loan_to_value(
+                    liquidation.collateral_amount,
+                    liquidation.principal_amount,
+                    rate,
+                );
+
+                OpenLoan {
+                    loan_txid: liquidation.loan_txid,
+                    collateral_amount: liquidation.collateral_amount,
+                    principal_amount: liquidation.principal_amount,
+                    locktime: liquidation.locktime,
+                    blocks_remaining: liquidation.locktime.saturating_sub(blockcount),
+                    ltv,
+                    above_threshold: ltv.map_or(false, |ltv| ltv > ltv_threshold),
+                    status: liquidation.status,
+                }
+            })
+            .collect();
+
+        Self {
+            rate,
+            blockcount,
+            ltv_threshold,
+            loans,
+        }
+    }
+}
+
+impl fmt::Display for LoanBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Block {}, ask {}, bid {}, LTV threshold {:.2}",
+            self.blockcount,
+            self.rate.ask.as_satodollar() as f64 / Amount::ONE_BTC.as_sat() as f64,
+            self.rate.bid.as_satodollar() as f64 / Amount::ONE_BTC.as_sat() as f64,
+            self.ltv_threshold
+        )?;
+
+        for loan in self.loans.iter() {
+            let ltv = match loan.ltv {
+                Some(ltv) => format!("{:.4}", ltv),
+                None => "unknown".to_string(),
+            };
+
+            writeln!(
+                f,
+                "{}{} collateral {} principal {} locktime {} ({} blocks left) LTV {} {}",
+                if loan.above_threshold { "! " } else { "  " },
+                loan.loan_txid,
+                loan.collateral_amount.as_sat(),
+                loan.principal_amount.as_sat(),
+                loan.locktime,
+                loan.blocks_remaining,
+                ltv,
+                loan.status
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The value of the principal of a loan divided by the value of its
+/// collateral, with the L-BTC collateral valued at the bid of `rate`
+/// like when we lent against it.
+///
+/// Returns `None` if the collateral is worth nothing at that rate.
+fn loan_to_value(collateral_amount: Amount, principal_amount: Amount, rate: Rate) -> Option<f64> {
+    let price = rate.bid.as_satodollar() as f64 / Amount::ONE_BTC.as_sat() as f64;
+
+    // Both values in satodollar
+    let collateral_value = collateral_amount.as_sat() as f64 * price;
+    let principal_value = principal_amount.as_sat() as f64;
+
+    if collateral_value <= 0.0 {
+        return None;
+    }
+
+    Some(principal_value / collateral_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ltv_rises_when_btc_falls() {
+        let collateral = Amount::ONE_BTC;
+        let principal = Amount::from_sat(30_000 * Amount::ONE_BTC.as_sat());
+
+        let ltv =
+            loan_to_value(collateral, principal, Rate::from_whole_usdt(50_001, 50_000)).unwrap();
+        assert!((ltv - 0.6).abs() < 1e-9);
+
+        let ltv =
+            loan_to_value(collateral, principal, Rate::from_whole_usdt(40_001, 40_000)).unwrap();
+        assert!((ltv - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ltv_is_unknown_without_collateral() {
+        let ltv = loan_to_value(
+            Amount::ZERO,
+            Amount::ZERO,
+            Rate::from_whole_usdt(50_001, 50_000),
+        );
+
+        assert_eq!(ltv, None);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    database::{queries, OracleKeyForm, PriceAttestationForm, Sqlite},
+    elements_rpc::{Client, ElementsRpc},
+    pair::PairId,
+    unix_timestamp, LiquidUsdt, Rate, RateSubscription,
+};
+use anyhow::{Context, Result};
+use bitcoin_hashes::{sha256, Hash};
+use elements::secp256k1_zkp::{Message, PublicKey, SecretKey, Signature, SECP256K1};
+use serde::{Serialize, Serializer};
+use std::{collections::HashMap, fmt::Display, time::Duration};
+
+/// Label of the address in the elementsd wallet whose key we sign
+/// price attestations with.
+const ORACLE_KEY_LABEL: &str = "oracle";
+
+/// A statement, signed with our oracle key, that the rate of `pair`
+/// was `ask`/`bid` at `timestamp`.
+///
+/// The signature is an ECDSA signature over the SHA256 hash of the
+/// message `<pair>:<ask>:<bid>:<timestamp>:<sequence>`, where the
+/// prices are expressed in satodollar per whole unit of the base
+/// asset.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Attestation {
+    /// Increases by one with every attestation we publish, across
+    /// all pairs.
+    pub sequence: u64,
+    pub pair: PairId,
+    pub ask: LiquidUsdt,
+    pub bid: LiquidUsdt,
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+    #[serde(serialize_with = "serialize_display")]
+    pub signature: Signature,
+}
+
+impl Attestation {
+    fn new(
+        secret_key: &SecretKey,
+        sequence: u64,
+        pair: PairId,
+        rate: Rate,
+        timestamp: u64,
+    ) -> Self {
+        let message = attestation_message(sequence, &pair, rate, timestamp);
+
+        Self {
+            sequence,
+            pair,
+            ask: rate.ask,
+            bid: rate.bid,
+            timestamp,
+            signature: SECP256K1.sign(&message, secret_key),
+        }
+    }
+
+    /// Check that the attestation was signed by the owner of
+    /// `public_key`.
+    pub fn verify(&self, public_key: &PublicKey) -> Result<()> {
+        let rate = Rate {
+            ask: self.ask,
+            bid: self.bid,
+        };
+        let message = attestation_message(self.sequence, &self.pair, rate, self.timestamp);
+
+        SECP256K1
+            .verify(&message, &self.signature, public_key)
+            .context("invalid attestation signature")
+    }
+}
+
+fn attestation_message(sequence: u64, pair: &PairId, rate: Rate, timestamp: u64) -> Message {
+    let message = format!(
+        "{}:{}:{}:{}:{}",
+        pair,
+        rate.ask.as_satodollar(),
+        rate.bid.as_satodollar(),
+        timestamp,
+        sequence
+    );
+    let digest = sha256::Hash::hash(message.as_bytes());
+
+    Message::from_slice(&digest.into_inner()).expect("a SHA256 hash is a valid message")
+}
+
+fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Display,
+    S: Serializer,
+{
+    serializer.collect_str(value)
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("unknown attestation {0}")]
+pub struct UnknownAttestation(pub u64);
+
+/// Signs and records the rates we use, so that anyone can later
+/// verify which price we based our decisions on.
+#[derive(Clone)]
+pub struct Oracle {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+    db: Sqlite,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct OracleKey {
+    #[serde(serialize_with = "serialize_display")]
+    pub public_key: PublicKey,
+}
+
+impl Oracle {
+    /// Load our oracle key from the elementsd wallet, creating it on
+    /// first use.
+    pub async fn new(elementsd: &Client, db: Sqlite) -> Result<Self> {
+        let address = match db
+            .do_in_transaction(|conn| queries::get_oracle_address(conn))
+            .await?
+        {
+            Some(address) => address,
+            None => {
+                let address = elementsd
+                    .getnewaddress(ORACLE_KEY_LABEL, Some("blech32"))
+                    .await
+                    .context("failed to create oracle key")?;
+                db.do_in_transaction(|conn| OracleKeyForm::new(&address).insert(conn))
+                    .await?;
+
+                address
+            }
+        };
+
+        let secret_key = elementsd
+            .dump_private_key(&address)
+            .await
+            .context("failed to load oracle key")?;
+        let public_key = PublicKey::from_secret_key(SECP256K1, &secret_key);
+
+        tracing::info!("Oracle public key: {}", public_key);
+
+        Ok(Self {
+            secret_key,
+            public_key,
+            db,
+        })
+    }
+
+    pub fn key(&self) -> OracleKey {
+        OracleKey {
+            public_key: self.public_key,
+        }
+    }
+
+    /// Sign and record the current `rate` of `pair`.
+    pub async fn attest(&self, pair: PairId, rate: Rate) -> Result<Attestation> {
+        let timestamp = unix_timestamp();
+
+        self.db
+            .do_in_transaction(|conn| {
+                let sequence = queries::get_last_attestation_sequence(conn)?
+                    .map_or(0, |sequence| sequence + 1);
+                let attestation =
+                    Attestation::new(&self.secret_key, sequence, pair, rate, timestamp);
+
+                PriceAttestationForm::new(&attestation)?.insert(conn)?;
+
+                Ok(attestation)
+            })
+            .await
+    }
+
+    /// The most recent attestation of the rate of `pair`.
+    pub async fn latest_attestation(&self, pair: PairId) -> Result<Option<Attestation>> {
+        self.db
+            .do_in_transaction(|conn| queries::get_latest_attestation(conn, &pair))
+            .await
+    }
+
+    pub async fn attestation(&self, sequence: u64) -> Result<Option<Attestation>> {
+        self.db
+            .do_in_transaction(|conn| queries::get_attestation(conn, sequence))
+            .await
+    }
+
+    /// Delete the attestations which are older than `retention`.
+    pub async fn prune(&self, retention: Duration) -> Result<()> {
+        let cutoff = unix_timestamp().saturating_sub(retention.as_secs());
+
+        let deleted = self
+            .db
+            .do_in_transaction(|conn| queries::delete_attestations_before(conn, cutoff))
+            .await?;
+        if deleted > 0 {
+            tracing::debug!("Deleted {} attestations older than {}", deleted, cutoff);
+        }
+
+        Ok(())
+    }
+}
+
+/// Periodically attest the latest rate of every trading pair and
+/// delete the attestations older than `retention`.
+///
+/// Pairs for which no rate has been received yet are skipped.
+pub async fn publish_attestations(
+    oracle: Oracle,
+    subscriptions: HashMap<PairId, RateSubscription>,
+    period: Duration,
+    retention: Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        for (pair, subscription) in subscriptions.iter() {
+            let rate = subscription.latest_rate();
+            if rate == Rate::ZERO {
+                continue;
+            }
+
+            if let Err(e) = oracle.attest(pair.clone(), rate).await {
+                tracing::error!("Failed to attest rate of {}: {:#}", pair, e);
+            }
+        }
+
+        if let Err(e) = oracle.prune(retention).await {
+            tracing::error!("Failed to delete old attestations: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attestation_can_be_verified_with_oracle_key() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(SECP256K1, &secret_key);
+
+        let attestation = Attestation::new(
+            &secret_key,
+            7,
+            PairId::new("lbtc-lusdt"),
+            Rate::from_whole_usdt(30_001, 30_000),
+            1_626_000_000,
+        );
+
+        assert!(attestation.verify(&public_key).is_ok());
+    }
+
+    #[test]
+    fn tampered_attestation_is_rejected() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(SECP256K1, &secret_key);
+
+        let mut attestation = Attestation::new(
+            &secret_key,
+            7,
+            PairId::new("lbtc-lusdt"),
+            Rate::from_whole_usdt(30_001, 30_000),
+            1_626_000_000,
+        );
+        attestation.bid = LiquidUsdt::from_satodollar(2_000_000_000_000);
+
+        assert!(attestation.verify(&public_key).is_err());
+    }
+}
@@ -0,0 +1,749 @@
+use crate::{
+    limits::TradeLimits,
+    quote::{Direction, SwapMode, SwapSimulation},
+    LatestRate, LiquidBtc, LiquidUsdt, Rate,
+};
+use anyhow::{anyhow, bail, Result};
+use elements::{bitcoin::Amount, AssetId};
+use estimate_transaction_size::estimate_virtual_size;
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::{Infallible, TryFrom},
+    fmt,
+    str::FromStr,
+};
+
+/// The identifier of the trading pair we have always been offering.
+pub const LBTC_LUSDT: &str = "lbtc-lusdt";
+
+/// Number of outputs of a swap transaction, not counting the fee nor
+/// the service fee: an output and a change output for each party.
+const SWAP_OUTPUTS: u64 = 4;
+
+/// Number of decimal places of L-BTC, which is also the maximum
+/// precision of any Liquid asset.
+const MAX_PRECISION: u8 = 8;
+
+/// Parts per million in a whole, used to apply percentage fees
+/// without floating point arithmetic.
+const PPM: u64 = 1_000_000;
+
+/// Identifies a trading pair in the API, e.g. `lbtc-lusdt`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PairId(String);
+
+impl PairId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+}
+
+impl fmt::Display for PairId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for PairId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s))
+    }
+}
+
+/// Where the rate of a trading pair comes from.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateSource {
+    /// Follow the ticker of the given Kraken pair, e.g. `XBT/USD`.
+    Kraken(String),
+    /// Always quote the same nominal prices per unit of the base asset.
+    Fixed { ask: f64, bid: f64 },
+}
+
+/// A fee we charge on every trade of a trading pair, on top of the
+/// spread.
+///
+/// It is always paid in the quote asset, to an output of the swap
+/// transaction of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceFee {
+    /// Percentage of the amount of the quote asset traded, e.g. 0.1
+    /// for 0.1%.
+    Percentage(f64),
+    /// Fixed amount of the quote asset, in units of its precision
+    /// like every amount of the trading pair.
+    Flat(u64),
+}
+
+impl ServiceFee {
+    /// The fee owed on a trade of `gross_amount` of the quote asset,
+    /// rounded up.
+    fn amount(&self, gross_amount: u64) -> u64 {
+        match self {
+            ServiceFee::Percentage(percentage) => {
+                let ppm = u128::from(percentage_to_ppm(*percentage));
+                let fee = (u128::from(gross_amount) * ppm + u128::from(PPM) - 1) / u128::from(PPM);
+
+                fee as u64
+            }
+            ServiceFee::Flat(fee) => *fee,
+        }
+    }
+
+    /// The smallest amount of the quote asset which leaves at least
+    /// `net_amount` once the fee is paid.
+    fn gross_amount(&self, net_amount: u64) -> Result<u64> {
+        match self {
+            ServiceFee::Percentage(percentage) => {
+                let ppm = percentage_to_ppm(*percentage);
+                let gross_amount = u128::from(net_amount) * u128::from(PPM) / u128::from(PPM - ppm);
+                let mut gross_amount = u64::try_from(gross_amount)
+                    .map_err(|_| anyhow!("amount including service fee overflows"))?;
+
+                // Rounding up the fee can leave us short of a few units
+                while gross_amount - self.amount(gross_amount) < net_amount {
+                    gross_amount += 1;
+                }
+
+                Ok(gross_amount)
+            }
+            ServiceFee::Flat(fee) => net_amount
+                .checked_add(*fee)
+                .ok_or_else(|| anyhow!("amount including service fee overflows")),
+        }
+    }
+}
+
+fn percentage_to_ppm(percentage: f64) -> u64 {
+    (percentage * (PPM / 100) as f64).round() as u64
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("trade amount does not cover the service fee of {0}")]
+pub struct ServiceFeeNotCovered(pub u64);
+
+/// The amounts of a trade, in units of the precision of their asset,
+/// e.g. in cents for a quote precision of 2.
+///
+/// On-chain, every asset has the maximum precision, see
+/// [`TradingPair::onchain_amounts`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeAmounts {
+    /// The amount the taker sends us.
+    pub input: u64,
+    /// The amount the taker receives from us.
+    pub output: u64,
+    /// The amount of the quote asset the taker pays us on top of the
+    /// spread.
+    pub service_fee: u64,
+}
+
+/// The configuration of a trading pair, as read from the trading
+/// pairs file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TradingPairConfig {
+    pub id: PairId,
+    /// Defaults to L-BTC.
+    #[serde(default)]
+    pub base_asset_id: Option<AssetId>,
+    pub quote_asset_id: AssetId,
+    /// Number of decimal places of the base asset.
+    #[serde(default = "max_precision")]
+    pub base_precision: u8,
+    /// Number of decimal places of the quote asset.
+    #[serde(default = "max_precision")]
+    pub quote_precision: u8,
+    pub rate_source: RateSource,
+    pub limits: TradeLimits,
+    #[serde(default)]
+    pub service_fee: Option<ServiceFee>,
+    /// Share of the value of our inventory of this pair which we aim
+    /// to hold in the base asset. Defaults to `--target-btc-ratio`.
+    #[serde(default)]
+    pub target_base_ratio: Option<f64>,
+}
+
+fn max_precision() -> u8 {
+    MAX_PRECISION
+}
+
+/// A market in which takers can buy and sell the base asset in
+/// exchange for the quote asset.
+pub struct TradingPair<RS> {
+    pub id: PairId,
+    pub base_asset_id: AssetId,
+    pub quote_asset_id: AssetId,
+    pub base_precision: u8,
+    pub quote_precision: u8,
+    pub limits: TradeLimits,
+    pub service_fee: Option<ServiceFee>,
+    pub rate_service: RS,
+}
+
+impl<RS> TradingPair<RS>
+where
+    RS: LatestRate,
+{
+    pub fn new(config: TradingPairConfig, btc_asset_id: AssetId, rate_service: RS) -> Result<Self> {
+        for precision in [config.base_precision, config.quote_precision].iter() {
+            if *precision > MAX_PRECISION {
+                bail!(
+                    "precision of trading pair {} cannot be more than {}, got {}",
+                    config.id,
+                    MAX_PRECISION,
+                    precision
+                )
+            }
+        }
+
+        if let Some(ServiceFee::Percentage(percentage)) = config.service_fee {
+            if !(0.0..100.0).contains(&percentage) || percentage_to_ppm(percentage) >= PPM {
+                bail!(
+                    "service fee of trading pair {} must be at least 0% and less than 100%, got {}%",
+                    config.id,
+                    percentage
+                )
+            }
+        }
+
+        Ok(Self {
+            id: config.id,
+            base_asset_id: config.base_asset_id.unwrap_or(btc_asset_id),
+            quote_asset_id: config.quote_asset_id,
+            base_precision: config.base_precision,
+            quote_precision: config.quote_precision,
+            limits: config.limits,
+            service_fee: config.service_fee,
+            rate_service,
+        })
+    }
+
+    pub fn latest_rate(&mut self) -> Rate {
+        self.rate_service.latest_rate()
+    }
+
+    /// The asset the taker sends us and the asset we send them when
+    /// trading in the given direction.
+    pub fn assets(&self, direction: Direction) -> (AssetId, AssetId) {
+        match direction {
+            Direction::Buy => (self.quote_asset_id, self.base_asset_id),
+            Direction::Sell => (self.base_asset_id, self.quote_asset_id),
+        }
+    }
+
+    /// Compute how much the taker receives for `input_amount` at the
+    /// given rate.
+    ///
+    /// Amounts are expressed in units of the precision of their
+    /// asset.
+    pub fn output_amount(
+        &self,
+        rate: Rate,
+        direction: Direction,
+        input_amount: u64,
+    ) -> Result<u64> {
+        let base_scale = scale(self.base_precision);
+        let quote_scale = scale(self.quote_precision);
+
+        let output_amount = match direction {
+            Direction::Buy => {
+                let quote_amount = LiquidUsdt::from_satodollar(
+                    input_amount
+                        .checked_mul(quote_scale)
+                        .ok_or_else(|| anyhow!("input amount overflow"))?,
+                );
+                let base_amount = rate.sell_base(quote_amount)?;
+
+                Amount::from(base_amount).as_sat() / base_scale
+            }
+            Direction::Sell => {
+                let base_amount = LiquidBtc::from(Amount::from_sat(
+                    input_amount
+                        .checked_mul(base_scale)
+                        .ok_or_else(|| anyhow!("input amount overflow"))?,
+                ));
+                let quote_amount = rate.buy_quote(base_amount)?;
+
+                quote_amount.as_satodollar() / quote_scale
+            }
+        };
+
+        Ok(output_amount)
+    }
+
+    /// Compute how much the taker has to send us to receive exactly
+    /// `output_amount` at the given rate.
+    ///
+    /// The result is rounded up, so that we never give away more than
+    /// the rate allows. Amounts are expressed in units of the
+    /// precision of their asset.
+    pub fn input_amount(
+        &self,
+        rate: Rate,
+        direction: Direction,
+        output_amount: u64,
+    ) -> Result<u64> {
+        let base_scale = scale(self.base_precision);
+        let quote_scale = scale(self.quote_precision);
+
+        let input_amount = match direction {
+            Direction::Buy => {
+                let base_amount = LiquidBtc::from(Amount::from_sat(
+                    output_amount
+                        .checked_mul(base_scale)
+                        .ok_or_else(|| anyhow!("output amount overflow"))?,
+                ));
+                let quote_amount = rate.sell_base_cost(base_amount)?;
+
+                div_ceil(quote_amount.as_satodollar(), quote_scale)
+            }
+            Direction::Sell => {
+                let quote_amount = LiquidUsdt::from_satodollar(
+                    output_amount
+                        .checked_mul(quote_scale)
+                        .ok_or_else(|| anyhow!("output amount overflow"))?,
+                );
+                let base_amount = rate.buy_quote_cost(quote_amount)?;
+
+                div_ceil(Amount::from(base_amount).as_sat(), base_scale)
+            }
+        };
+
+        Ok(input_amount)
+    }
+
+    /// The amounts the taker sends and receives in a trade in which
+    /// they fix `amount` according to `mode`.
+    ///
+    /// The service fee is taken out of the quote asset, whether the
+    /// taker sends or receives it.
+    pub fn trade_amounts(
+        &self,
+        rate: Rate,
+        direction: Direction,
+        mode: SwapMode,
+        amount: u64,
+    ) -> Result<TradeAmounts> {
+        let fee = |gross_amount| {
+            self.service_fee
+                .map_or(0, |service_fee| service_fee.amount(gross_amount))
+        };
+        let gross = |net_amount| {
+            self.service_fee.map_or(Ok(net_amount), |service_fee| {
+                service_fee.gross_amount(net_amount)
+            })
+        };
+
+        let amounts = match (direction, mode) {
+            (Direction::Buy, SwapMode::ExactInput) => {
+                let service_fee = fee(amount);
+                let net_amount = amount
+                    .checked_sub(service_fee)
+                    .ok_or(ServiceFeeNotCovered(service_fee))?;
+
+                TradeAmounts {
+                    input: amount,
+                    output: self.output_amount(rate, direction, net_amount)?,
+                    service_fee,
+                }
+            }
+            (Direction::Buy, SwapMode::ExactOutput) => {
+                let net_amount = self.input_amount(rate, direction, amount)?;
+                let input = gross(net_amount)?;
+
+                TradeAmounts {
+                    input,
+                    output: amount,
+                    service_fee: input - net_amount,
+                }
+            }
+            (Direction::Sell, SwapMode::ExactInput) => {
+                let gross_amount = self.output_amount(rate, direction, amount)?;
+                let service_fee = fee(gross_amount);
+
+                TradeAmounts {
+                    input: amount,
+                    output: gross_amount
+                        .checked_sub(service_fee)
+                        .ok_or(ServiceFeeNotCovered(service_fee))?,
+                    service_fee,
+                }
+            }
+            (Direction::Sell, SwapMode::ExactOutput) => {
+                let gross_amount = gross(amount)?;
+
+                TradeAmounts {
+                    input: self.input_amount(rate, direction, gross_amount)?,
+                    output: amount,
+                    service_fee: gross_amount - amount,
+                }
+            }
+        };
+
+        Ok(amounts)
+    }
+
+    /// The amounts of a trade in the given direction as they end up
+    /// in the swap transaction: what the taker sends us, what we
+    /// send them and the service fee.
+    pub fn onchain_amounts(
+        &self,
+        direction: Direction,
+        amounts: TradeAmounts,
+    ) -> Result<(Amount, Amount, Amount)> {
+        let base = |amount| onchain_amount(amount, self.base_precision);
+        let quote = |amount| onchain_amount(amount, self.quote_precision);
+
+        let (input, output) = match direction {
+            Direction::Buy => (quote(amounts.input)?, base(amounts.output)?),
+            Direction::Sell => (base(amounts.input)?, quote(amounts.output)?),
+        };
+
+        Ok((input, output, quote(amounts.service_fee)?))
+    }
+
+    /// Preview a trade at `rate` without committing to it.
+    ///
+    /// Amounts which are outside of our trade limits are reported
+    /// rather than rejected.
+    pub fn simulate(
+        &self,
+        rate: Rate,
+        direction: Direction,
+        mode: SwapMode,
+        amount: u64,
+        fee_rate: Amount,
+    ) -> Result<SwapSimulation> {
+        let amounts = self.trade_amounts(rate, direction, mode, amount)?;
+        let limit_violation = self
+            .limits
+            .check(direction, amounts.input)
+            .err()
+            .map(|e| e.to_string());
+        let service_fee_outputs = u64::from(amounts.service_fee > 0);
+        let estimated_fee =
+            fee_rate.as_sat() * estimate_virtual_size(2, SWAP_OUTPUTS + service_fee_outputs);
+
+        Ok(SwapSimulation {
+            input_amount: amounts.input,
+            output_amount: amounts.output,
+            service_fee: amounts.service_fee,
+            rate: rate.price(direction),
+            spread: LiquidUsdt::from_satodollar(
+                rate.ask
+                    .as_satodollar()
+                    .saturating_sub(rate.bid.as_satodollar()),
+            ),
+            estimated_fee: Amount::from_sat(estimated_fee),
+            limit_violation,
+        })
+    }
+}
+
+fn onchain_amount(amount: u64, precision: u8) -> Result<Amount> {
+    amount
+        .checked_mul(scale(precision))
+        .map(Amount::from_sat)
+        .ok_or_else(|| anyhow!("amount {} overflows with the maximum precision", amount))
+}
+
+fn div_ceil(a: u64, b: u64) -> u64 {
+    (a + b - 1) / b
+}
+
+/// The factor by which amounts of an asset with the given precision
+/// have to be multiplied to be expressed with the maximum precision.
+fn scale(precision: u8) -> u64 {
+    10u64.pow(u32::from(MAX_PRECISION - precision))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{limits::Limits, USDT_ASSET_ID};
+
+    struct StaticRate;
+
+    impl LatestRate for StaticRate {
+        fn latest_rate(&mut self) -> Rate {
+            rate()
+        }
+    }
+
+    fn trading_pair(quote_precision: u8) -> TradingPair<StaticRate> {
+        let btc_asset_id =
+            AssetId::from_str("6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d")
+                .unwrap();
+        let config = TradingPairConfig {
+            id: PairId::new(LBTC_LUSDT),
+            base_asset_id: None,
+            quote_asset_id: AssetId::from_str(USDT_ASSET_ID).unwrap(),
+            base_precision: 8,
+            quote_precision,
+            rate_source: RateSource::Fixed {
+                ask: 20_000.0,
+                bid: 19_000.0,
+            },
+            limits: TradeLimits {
+                buy: Limits::new(0, u64::MAX).unwrap(),
+                sell: Limits::new(0, u64::MAX).unwrap(),
+            },
+            service_fee: None,
+            target_base_ratio: None,
+        };
+
+        TradingPair::new(config, btc_asset_id, StaticRate).unwrap()
+    }
+
+    fn trading_pair_with_fee(service_fee: ServiceFee) -> TradingPair<StaticRate> {
+        let mut pair = trading_pair(8);
+        pair.service_fee = Some(service_fee);
+
+        pair
+    }
+
+    fn rate() -> Rate {
+        Rate {
+            ask: LiquidUsdt::try_from(20_000.0).unwrap(),
+            bid: LiquidUsdt::try_from(19_000.0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn output_amount_with_max_precision() {
+        let pair = trading_pair(8);
+
+        let sell = pair
+            .output_amount(rate(), Direction::Sell, Amount::ONE_BTC.as_sat())
+            .unwrap();
+        let buy = pair
+            .output_amount(rate(), Direction::Buy, 1_000_000_000_000)
+            .unwrap();
+
+        assert_eq!(sell, 1_900_000_000_000);
+        assert_eq!(buy, 50_000_000);
+    }
+
+    #[test]
+    fn output_amount_with_lower_quote_precision() {
+        let pair = trading_pair(2);
+
+        let sell = pair
+            .output_amount(rate(), Direction::Sell, Amount::ONE_BTC.as_sat())
+            .unwrap();
+        let buy = pair
+            .output_amount(rate(), Direction::Buy, 1_000_000)
+            .unwrap();
+
+        assert_eq!(sell, 1_900_000);
+        assert_eq!(buy, 50_000_000);
+    }
+
+    #[test]
+    fn input_amount_with_max_precision() {
+        let pair = trading_pair(8);
+
+        let sell = pair
+            .input_amount(rate(), Direction::Sell, 1_900_000_000_000)
+            .unwrap();
+        let buy = pair
+            .input_amount(rate(), Direction::Buy, 50_000_000)
+            .unwrap();
+
+        assert_eq!(sell, Amount::ONE_BTC.as_sat());
+        assert_eq!(buy, 1_000_000_000_000);
+    }
+
+    #[test]
+    fn input_amount_with_lower_quote_precision_is_rounded_up() {
+        let pair = trading_pair(2);
+
+        let buy = pair.input_amount(rate(), Direction::Buy, 1).unwrap();
+
+        // 1 satoshi costs 0.0002 L-USDt, which is less than a cent
+        assert_eq!(buy, 1);
+    }
+
+    #[test]
+    fn onchain_amounts_have_max_precision() {
+        let mut pair = trading_pair_with_fee(ServiceFee::Flat(5));
+        pair.quote_precision = 2;
+
+        let amounts = pair
+            .trade_amounts(
+                rate(),
+                Direction::Sell,
+                SwapMode::ExactInput,
+                Amount::ONE_BTC.as_sat(),
+            )
+            .unwrap();
+        let (input, output, service_fee) = pair.onchain_amounts(Direction::Sell, amounts).unwrap();
+
+        assert_eq!(input, Amount::ONE_BTC);
+        // 18,999.95 L-USDt
+        assert_eq!(output, Amount::from_sat(1_899_995_000_000));
+        assert_eq!(service_fee, Amount::from_sat(5_000_000));
+    }
+
+    #[test]
+    fn exact_output_receives_at_least_requested_amount() {
+        let pair = trading_pair(8);
+
+        for direction in [Direction::Buy, Direction::Sell].iter() {
+            let amounts = pair
+                .trade_amounts(rate(), *direction, SwapMode::ExactOutput, 1_234_567)
+                .unwrap();
+
+            assert_eq!(amounts.output, 1_234_567);
+            assert!(
+                pair.output_amount(rate(), *direction, amounts.input)
+                    .unwrap()
+                    >= 1_234_567
+            );
+        }
+    }
+
+    #[test]
+    fn percentage_service_fee_is_taken_out_of_quote_asset() {
+        let pair = trading_pair_with_fee(ServiceFee::Percentage(0.5));
+
+        let buy = pair
+            .trade_amounts(
+                rate(),
+                Direction::Buy,
+                SwapMode::ExactInput,
+                1_000_000_000_000,
+            )
+            .unwrap();
+        let sell = pair
+            .trade_amounts(
+                rate(),
+                Direction::Sell,
+                SwapMode::ExactInput,
+                Amount::ONE_BTC.as_sat(),
+            )
+            .unwrap();
+
+        assert_eq!(buy.service_fee, 5_000_000_000);
+        assert_eq!(buy.output, 49_750_000);
+        assert_eq!(sell.service_fee, 9_500_000_000);
+        assert_eq!(sell.output, 1_890_500_000_000);
+    }
+
+    #[test]
+    fn exact_output_covers_service_fee() {
+        for service_fee in [ServiceFee::Percentage(0.3), ServiceFee::Flat(12_345)].iter() {
+            let pair = trading_pair_with_fee(*service_fee);
+
+            let buy = pair
+                .trade_amounts(rate(), Direction::Buy, SwapMode::ExactOutput, 1_234_567)
+                .unwrap();
+            let sell = pair
+                .trade_amounts(rate(), Direction::Sell, SwapMode::ExactOutput, 1_234_567)
+                .unwrap();
+
+            assert!(buy.service_fee >= service_fee.amount(buy.input));
+            assert!(
+                pair.output_amount(rate(), Direction::Buy, buy.input - buy.service_fee)
+                    .unwrap()
+                    >= 1_234_567
+            );
+            assert!(
+                pair.output_amount(rate(), Direction::Sell, sell.input)
+                    .unwrap()
+                    >= 1_234_567 + sell.service_fee
+            );
+            assert!(sell.service_fee >= service_fee.amount(1_234_567 + sell.service_fee));
+        }
+    }
+
+    #[test]
+    fn flat_service_fee_must_be_covered() {
+        let pair = trading_pair_with_fee(ServiceFee::Flat(1_000));
+
+        let result = pair.trade_amounts(rate(), Direction::Buy, SwapMode::ExactInput, 999);
+
+        assert!(result.unwrap_err().is::<ServiceFeeNotCovered>());
+    }
+
+    #[test]
+    fn service_fee_deserializes_from_config() {
+        let percentage = serde_json::from_str::<ServiceFee>(r#"{"percentage":0.25}"#).unwrap();
+        let flat = serde_json::from_str::<ServiceFee>(r#"{"flat":100}"#).unwrap();
+
+        assert_eq!(percentage, ServiceFee::Percentage(0.25));
+        assert_eq!(flat, ServiceFee::Flat(100));
+    }
+
+    #[test]
+    fn simulation_reports_limit_violations() {
+        let mut pair = trading_pair(8);
+        pair.limits.sell = Limits::new(0, Amount::ONE_BTC.as_sat()).unwrap();
+
+        let within_limits = pair
+            .simulate(
+                rate(),
+                Direction::Sell,
+                SwapMode::ExactInput,
+                Amount::ONE_BTC.as_sat(),
+                Amount::ONE_SAT,
+            )
+            .unwrap();
+        let above_limits = pair
+            .simulate(
+                rate(),
+                Direction::Sell,
+                SwapMode::ExactInput,
+                Amount::ONE_BTC.as_sat() + 1,
+                Amount::ONE_SAT,
+            )
+            .unwrap();
+
+        assert_eq!(within_limits.output_amount, 1_900_000_000_000);
+        assert_eq!(within_limits.spread, LiquidUsdt::try_from(1_000.0).unwrap());
+        assert_eq!(within_limits.limit_violation, None);
+        assert!(above_limits.limit_violation.is_some());
+    }
+
+    #[test]
+    fn simulation_accounts_for_service_fee_output() {
+        let simulate = |pair: &TradingPair<StaticRate>| {
+            pair.simulate(
+                rate(),
+                Direction::Sell,
+                SwapMode::ExactInput,
+                Amount::ONE_BTC.as_sat(),
+                Amount::ONE_SAT,
+            )
+            .unwrap()
+        };
+
+        let without_fee = simulate(&trading_pair(8));
+        let with_fee = simulate(&trading_pair_with_fee(ServiceFee::Flat(100)));
+
+        assert_eq!(
+            with_fee.estimated_fee - without_fee.estimated_fee,
+            Amount::from_sat(estimate_transaction_size::avg_vbytes::OUTPUT)
+        );
+    }
+
+    #[test]
+    fn rate_source_deserializes_from_config() {
+        let kraken = serde_json::from_str::<RateSource>(r#"{"kraken":"XBT/USD"}"#).unwrap();
+        let fixed =
+            serde_json::from_str::<RateSource>(r#"{"fixed":{"ask":1.01,"bid":0.99}}"#).unwrap();
+
+        assert_eq!(kraken, RateSource::Kraken("XBT/USD".to_string()));
+        assert_eq!(
+            fixed,
+            RateSource::Fixed {
+                ask: 1.01,
+                bid: 0.99
+            }
+        );
+    }
+}
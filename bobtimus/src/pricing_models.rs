@@ -1,8 +1,13 @@
+use anyhow::{bail, Context, Result};
 use core::f64;
 use nalgebra::DMatrix;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use rand_distr::{Distribution, Normal};
-use std::{collections::HashMap, usize};
+use serde::Serialize;
+use std::{collections::HashMap, str::FromStr, usize};
+
+/// Liquid produces a block every minute.
+pub const BLOCKS_PER_DAY: u32 = 24 * 60;
 
 struct VolatilitySimulation {
     num_days: usize,
@@ -17,14 +22,13 @@ impl VolatilitySimulation {
         }
     }
 
-    fn wiener_process(&self) -> StatisticalProcessSimulation {
+    fn wiener_process<R: Rng>(&self, rng: &mut R) -> StatisticalProcessSimulation {
         let nsteps = self.num_days * 24;
         let sigma = self.daily_volatility / 24.0;
 
-        let mut rng = thread_rng();
         let normal = Normal::new(0.0, sigma).unwrap();
 
-        let mut brownian = DMatrix::from_fn(1000, nsteps, |_, _| normal.sample(&mut rng));
+        let mut brownian = DMatrix::from_fn(1000, nsteps, |_, _| normal.sample(rng));
         cumsum(&mut brownian, Some(0));
 
         let res: Vec<f64> = brownian.column(nsteps - 1).map(|e| e).data.into();
@@ -95,12 +99,25 @@ struct CreateLenderSuggestions {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum RiskAppetite {
+pub enum RiskAppetite {
     Low,
     Moderate,
     High,
 }
 
+impl FromStr for RiskAppetite {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(RiskAppetite::Low),
+            "moderate" => Ok(RiskAppetite::Moderate),
+            "high" => Ok(RiskAppetite::High),
+            _ => bail!("unknown risk appetite {}", s),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 struct LenderSuggestionParameters {
     mu: f64,
@@ -164,20 +181,94 @@ impl CreateLenderSuggestions {
     }
 }
 
+/// The terms under which we are willing to lend for a given loan
+/// duration.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct LoanTerms {
+    /// The maximum ratio between principal and collateral value.
+    pub max_ltv: f64,
+    /// The interest owed over the whole term, as a fraction of the
+    /// principal.
+    pub interest_rate: f64,
+    pub term_days: u32,
+    /// The term expressed in blocks, which is what borrowers request.
+    pub term_blocks: u32,
+}
+
+impl LoanTerms {
+    /// Suggest loan terms by simulating the price of L-BTC over the
+    /// loan term and betting on the 95% confidence interval of the
+    /// outcome.
+    pub fn suggest(
+        risk_appetite: RiskAppetite,
+        term_days: u32,
+        daily_volatility: f64,
+    ) -> Result<Self> {
+        Self::suggest_with_rng(
+            risk_appetite,
+            term_days,
+            daily_volatility,
+            &mut thread_rng(),
+        )
+    }
+
+    fn suggest_with_rng<R: Rng>(
+        risk_appetite: RiskAppetite,
+        term_days: u32,
+        daily_volatility: f64,
+        rng: &mut R,
+    ) -> Result<Self> {
+        if term_days == 0 {
+            bail!("loan term must be at least 1 day")
+        }
+
+        let term_blocks = term_days
+            .checked_mul(BLOCKS_PER_DAY)
+            .with_context(|| format!("loan term of {} days is too long", term_days))?;
+
+        if !daily_volatility.is_finite() || daily_volatility <= 0.0 {
+            bail!(
+                "daily volatility must be positive, got {}",
+                daily_volatility
+            )
+        }
+
+        let simulation = VolatilitySimulation::new(term_days as usize, daily_volatility);
+        let quantiles = simulation
+            .wiener_process(rng)
+            .quantiles(Some(&[0.025, 0.975]));
+
+        let bet_low = *quantiles.get("2.5").context("missing 2.5% quantile")?;
+        let bet_high = *quantiles.get("97.5").context("missing 97.5% quantile")?;
+
+        let parameters =
+            CreateLenderSuggestions::new(risk_appetite, bet_low, bet_high).suggest_parameters(None);
+
+        Ok(Self {
+            max_ltv: parameters.lvr,
+            interest_rate: parameters.max_interest_rate,
+            term_days,
+            term_blocks,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn seeded_rng() -> StdRng {
+        StdRng::seed_from_u64(42)
+    }
 
     #[test]
     fn check_plausible_wiener() {
         let ndays: usize = 30;
         let volatility: f64 = 0.046;
         let simulation = VolatilitySimulation::new(ndays, volatility);
-        let quants = simulation.wiener_process().quantiles(None);
+        let quants = simulation.wiener_process(&mut seeded_rng()).quantiles(None);
 
-        // NOTE: the better way to do this is set a seed value and get
-        // deterministic values, but I don't know how to actually do
-        // this at this point in time.
         let q_testval_025 = *quants.get("2.5").unwrap();
         let q_testval_250 = *quants.get("25").unwrap();
         let q_testval_500 = *quants.get("50").unwrap();
@@ -204,4 +295,21 @@ mod tests {
             assert!(params.lvr <= 10e-6);
         }
     }
+
+    #[test]
+    fn suggested_loan_terms_are_plausible() {
+        let terms =
+            LoanTerms::suggest_with_rng(RiskAppetite::Moderate, 30, 0.046, &mut seeded_rng())
+                .unwrap();
+
+        assert!(terms.max_ltv > 0.0 && terms.max_ltv < 1.0);
+        assert!(terms.interest_rate > 0.0);
+        assert_eq!(terms.term_days, 30);
+        assert_eq!(terms.term_blocks, 30 * BLOCKS_PER_DAY);
+    }
+
+    #[test]
+    fn loan_term_cannot_be_zero() {
+        assert!(LoanTerms::suggest(RiskAppetite::Moderate, 0, 0.046).is_err());
+    }
 }
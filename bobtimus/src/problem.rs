@@ -1,3 +1,13 @@
+use crate::{
+    batch::{
+        BatchingDisabled, ForeignSignatures, InvalidSignature, TakerInputsTooSmall, UnknownBatch,
+    },
+    limits::{TradeTooLarge, TradeTooSmall},
+    oracle::UnknownAttestation,
+    pair::ServiceFeeNotCovered,
+    quote::{QuoteExpired, QuoteMismatch, TooManyQuotes, UnknownQuote},
+    LoanHandshakeExpired, LoanTermMismatch, UnknownLoanTerm, UnknownPair,
+};
 use baru::swap::{ChangeAmountTooSmall, InputAmountTooSmall, InvalidAssetTypes};
 use http_api_problem::HttpApiProblem;
 use std::error::Error;
@@ -24,6 +34,65 @@ pub fn from_anyhow(e: anyhow::Error) -> HttpApiProblem {
             HttpApiProblem::new("Change amount too small to cover fee.")
                 .set_status(StatusCode::BAD_REQUEST)
         }
+        e if e.is::<UnknownPair>() => {
+            HttpApiProblem::new("Unknown trading pair.").set_status(StatusCode::NOT_FOUND)
+        }
+        e if e.is::<UnknownAttestation>() => {
+            HttpApiProblem::new("Unknown attestation.").set_status(StatusCode::NOT_FOUND)
+        }
+        e if e.is::<UnknownQuote>() => {
+            HttpApiProblem::new("Unknown quote.").set_status(StatusCode::BAD_REQUEST)
+        }
+        e if e.is::<QuoteExpired>() => {
+            HttpApiProblem::new("Quote expired.").set_status(StatusCode::BAD_REQUEST)
+        }
+        e if e.is::<QuoteMismatch>() => {
+            HttpApiProblem::new("Swap does not match quote.").set_status(StatusCode::BAD_REQUEST)
+        }
+        e if e.is::<TooManyQuotes>() => {
+            HttpApiProblem::new("Too many open quotes.").set_status(StatusCode::SERVICE_UNAVAILABLE)
+        }
+        e if e.is::<TradeTooSmall>() => HttpApiProblem::new("Trade amount too small.")
+            .set_status(StatusCode::BAD_REQUEST)
+            .set_detail(format!("{}", e)),
+        e if e.is::<TradeTooLarge>() => HttpApiProblem::new("Trade amount too large.")
+            .set_status(StatusCode::BAD_REQUEST)
+            .set_detail(format!("{}", e)),
+        e if e.is::<ServiceFeeNotCovered>() => {
+            HttpApiProblem::new("Trade amount too small to cover service fee.")
+                .set_status(StatusCode::BAD_REQUEST)
+                .set_detail(format!("{}", e))
+        }
+        e if e.is::<UnknownLoanTerm>() => HttpApiProblem::new("Loan term not offered.")
+            .set_status(StatusCode::BAD_REQUEST)
+            .set_detail(format!("{}", e)),
+        e if e.is::<LoanTermMismatch>() => {
+            HttpApiProblem::new("Loan locktime does not match term.")
+                .set_status(StatusCode::BAD_REQUEST)
+                .set_detail(format!("{}", e))
+        }
+        e if e.is::<LoanHandshakeExpired>() => {
+            HttpApiProblem::new("Loan request expired.").set_status(StatusCode::BAD_REQUEST)
+        }
+        e if e.is::<TakerInputsTooSmall>() => {
+            HttpApiProblem::new("Inputs too small to cover swap and fee.")
+                .set_status(StatusCode::BAD_REQUEST)
+        }
+        e if e.is::<UnknownBatch>() => {
+            HttpApiProblem::new("Unknown batch.").set_status(StatusCode::NOT_FOUND)
+        }
+        e if e.is::<InvalidSignature>() => {
+            HttpApiProblem::new("Invalid signature for batch transaction.")
+                .set_status(StatusCode::BAD_REQUEST)
+                .set_detail(format!("{}", e))
+        }
+        e if e.is::<ForeignSignatures>() => {
+            HttpApiProblem::new("Signatures for inputs of other takers.")
+                .set_status(StatusCode::BAD_REQUEST)
+        }
+        e if e.is::<BatchingDisabled>() => {
+            HttpApiProblem::new("Swap batching is not enabled.").set_status(StatusCode::NOT_FOUND)
+        }
         e => {
             tracing::error!("unhandled error: {:#}", e);
 
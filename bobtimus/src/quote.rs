@@ -0,0 +1,218 @@
+use crate::{pair::PairId, LiquidUsdt};
+use anyhow::bail;
+use elements::{
+    bitcoin::Amount,
+    secp256k1_zkp::rand::{CryptoRng, RngCore},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The side of the market the taker is on.
+///
+/// - `Buy` means the taker buys the base asset and pays with the quote asset.
+/// - `Sell` means the taker sells the base asset and receives the quote asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Buy,
+    Sell,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Buy => write!(f, "buy"),
+            Direction::Sell => write!(f, "sell"),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "buy" => Ok(Direction::Buy),
+            "sell" => Ok(Direction::Sell),
+            _ => bail!("unknown direction {}", s),
+        }
+    }
+}
+
+/// Which amount of a trade the taker fixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapMode {
+    /// The taker names the amount they send us.
+    ExactInput,
+    /// The taker names the amount they receive from us.
+    ExactOutput,
+}
+
+impl Default for SwapMode {
+    fn default() -> Self {
+        SwapMode::ExactInput
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateQuotePayload {
+    pub direction: Direction,
+    /// The amount the taker wants to spend or, in `ExactOutput` mode,
+    /// to receive, in units of the precision of the asset.
+    pub amount: u64,
+    #[serde(default)]
+    pub mode: SwapMode,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimulateSwapPayload {
+    /// The amount the taker wants to spend or, in `ExactOutput` mode,
+    /// to receive, in units of the precision of the asset.
+    pub amount: u64,
+    #[serde(default)]
+    pub mode: SwapMode,
+}
+
+/// A preview of a swap at the latest rate, which we do not commit to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SwapSimulation {
+    /// The amount the taker would have to send to us.
+    pub input_amount: u64,
+    /// The amount the taker would receive from us.
+    pub output_amount: u64,
+    /// The amount of the quote asset the taker would pay us as a
+    /// service fee, already accounted for in the other amounts.
+    pub service_fee: u64,
+    /// The price per unit of the base asset which would be applied.
+    #[serde(serialize_with = "LiquidUsdt::serialize_to_nominal")]
+    pub rate: LiquidUsdt,
+    /// The difference between our ask and our bid.
+    #[serde(serialize_with = "LiquidUsdt::serialize_to_nominal")]
+    pub spread: LiquidUsdt,
+    /// The fee of the whole swap transaction at our current fee rate,
+    /// assuming both parties contribute a single input.
+    #[serde(with = "::elements::bitcoin::util::amount::serde::as_sat")]
+    pub estimated_fee: Amount,
+    /// Why we would reject the swap because of our trade limits, if
+    /// we would.
+    pub limit_violation: Option<String>,
+}
+
+/// A firm offer to trade at a fixed price until `expires_at`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quote {
+    pub id: QuoteId,
+    pub pair: PairId,
+    pub direction: Direction,
+    /// The amount the taker has to send to us.
+    pub input_amount: u64,
+    /// The amount the taker will receive from us.
+    pub output_amount: u64,
+    /// The amount of the quote asset the taker pays us as a service
+    /// fee, already accounted for in the other amounts.
+    pub service_fee: u64,
+    /// The price per unit of the base asset at which the quote was
+    /// made.
+    #[serde(serialize_with = "LiquidUsdt::serialize_to_nominal")]
+    pub rate: LiquidUsdt,
+    /// Seconds since the UNIX epoch after which the quote can no
+    /// longer be used to create a swap.
+    pub expires_at: u64,
+}
+
+impl Quote {
+    pub fn is_expired(&self, now: u64) -> bool {
+        now > self.expires_at
+    }
+}
+
+/// Number of unexpired quotes we keep at most, so that requesting
+/// quotes cannot exhaust our memory.
+pub const MAX_OPEN_QUOTES: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct QuoteId(String);
+
+impl QuoteId {
+    pub fn random<R>(rng: &mut R) -> Self
+    where
+        R: RngCore + CryptoRng,
+    {
+        let mut bytes = [0u8; 16];
+        rng.fill_bytes(&mut bytes);
+
+        Self(hex::encode(bytes))
+    }
+}
+
+impl fmt::Display for QuoteId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("unknown quote {0}")]
+pub struct UnknownQuote(pub QuoteId);
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("quote {0} has expired")]
+pub struct QuoteExpired(pub QuoteId);
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("swap request does not match quote {0}")]
+pub struct QuoteMismatch(pub QuoteId);
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("too many open quotes")]
+pub struct TooManyQuotes;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::secp256k1_zkp::rand::thread_rng;
+
+    #[test]
+    fn quote_expires_after_expiry_time() {
+        let quote = Quote {
+            id: QuoteId::random(&mut thread_rng()),
+            pair: PairId::new(crate::pair::LBTC_LUSDT),
+            direction: Direction::Buy,
+            input_amount: 1_000,
+            output_amount: 10,
+            service_fee: 0,
+            rate: LiquidUsdt::from_satodollar(100),
+            expires_at: 100,
+        };
+
+        assert!(!quote.is_expired(99));
+        assert!(!quote.is_expired(100));
+        assert!(quote.is_expired(101));
+    }
+
+    #[test]
+    fn direction_serialized_in_lowercase() {
+        let serialized = serde_json::to_string(&Direction::Sell).unwrap();
+
+        assert_eq!(serialized, "\"sell\"")
+    }
+
+    #[test]
+    fn swap_mode_defaults_to_exact_input() {
+        let payload: CreateQuotePayload =
+            serde_json::from_str(r#"{"direction":"buy","amount":1000}"#).unwrap();
+
+        assert_eq!(payload.mode, SwapMode::ExactInput)
+    }
+
+    #[test]
+    fn direction_roundtrips_through_string() {
+        for direction in [Direction::Buy, Direction::Sell].iter() {
+            let parsed = Direction::from_str(&direction.to_string()).unwrap();
+
+            assert_eq!(parsed, *direction)
+        }
+    }
+}
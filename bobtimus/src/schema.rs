@@ -1,7 +1,96 @@
+table! {
+    fee_bumps (child_txid) {
+        child_txid -> Text,
+        parent_txid -> Text,
+        fee -> BigInt,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    lender_states (id) {
+        id -> Text,
+        state -> Text,
+        created_at -> BigInt,
+        collateral_amount -> BigInt,
+        principal_amount -> BigInt,
+    }
+}
+
+table! {
+    liquidation_history (id) {
+        id -> Text,
+        tx_hex -> Text,
+        locktime -> BigInt,
+        status -> Text,
+        collateral_amount -> BigInt,
+        principal_amount -> BigInt,
+        settled_at -> BigInt,
+    }
+}
+
 table! {
     liquidations (id) {
         id -> Text,
         tx_hex -> Text,
         locktime -> BigInt,
+        status -> Text,
+        collateral_amount -> BigInt,
+        principal_amount -> BigInt,
+    }
+}
+
+table! {
+    oracle_keys (address) {
+        address -> Text,
+    }
+}
+
+table! {
+    price_attestations (sequence) {
+        sequence -> BigInt,
+        pair -> Text,
+        ask -> BigInt,
+        bid -> BigInt,
+        timestamp -> BigInt,
+        signature -> Text,
+    }
+}
+
+table! {
+    swaps (txid, batch_index) {
+        txid -> Text,
+        direction -> Text,
+        taker_asset_id -> Text,
+        taker_amount -> BigInt,
+        maker_asset_id -> Text,
+        maker_amount -> BigInt,
+        rate -> BigInt,
+        taker_address -> Text,
+        created_at -> BigInt,
+        status -> Text,
+        invalidation_txid -> Nullable<Text>,
+        batch_index -> Integer,
+        service_fee -> BigInt,
+    }
+}
+
+table! {
+    utxo_reservations (txid, vout) {
+        txid -> Text,
+        vout -> Integer,
+        swap_txid -> Text,
+        expires_at -> BigInt,
     }
 }
+
+allow_tables_to_appear_in_same_query!(
+    fee_bumps,
+    lender_states,
+    liquidation_history,
+    liquidations,
+    oracle_keys,
+    price_attestations,
+    swaps,
+    utxo_reservations,
+);
@@ -1,7 +1,7 @@
 import Debug from "debug";
 import { browser } from "webextension-polyfill-ts";
 import { Direction, Message, MessageKind } from "../messages";
-import { LoanDetails, LoanToSign, SwapToSign } from "../models";
+import { BuyAmount, CreateSwapPayload, LoanDetails, LoanToSign, Quote, SellAmount, SwapToSign } from "../models";
 import {
     createWallet,
     extractLoan,
@@ -10,9 +10,8 @@ import {
     getBalances,
     getOpenLoans,
     getPastTransactions,
-    makeBuyCreateSwapPayload,
     makeLoanRequestPayload,
-    makeSellCreateSwapPayload,
+    makeQuotedCreateSwapPayload,
     repayLoan,
     signAndSendSwap,
     signLoan,
@@ -33,6 +32,7 @@ debug("Hello world from background script");
 
 const walletName = "demo";
 var swapToSign: SwapToSign | undefined;
+var swapQuote: Quote | undefined;
 var loanToSign: LoanToSign | undefined;
 
 browser.runtime.onMessage.addListener(async (msg: Message<any>, sender) => {
@@ -48,13 +48,13 @@ browser.runtime.onMessage.addListener(async (msg: Message<any>, sender) => {
                 break;
             case MessageKind.SellRequest:
                 message = await call_wallet(
-                    async () => await makeSellCreateSwapPayload(walletName, msg.payload),
+                    async () => await makeSellPayload(msg.payload, sender.url),
                     MessageKind.SellResponse,
                 );
                 break;
             case MessageKind.BuyRequest:
                 message = await call_wallet(
-                    async () => await makeBuyCreateSwapPayload(walletName, msg.payload),
+                    async () => await makeBuyPayload(msg.payload, sender.url),
                     MessageKind.BuyResponse,
                 );
                 break;
@@ -66,14 +66,19 @@ browser.runtime.onMessage.addListener(async (msg: Message<any>, sender) => {
                 break;
             case MessageKind.LoanRequest:
                 message = await call_wallet(
-                    async () => await makeLoanRequestPayload(walletName, msg.payload),
+                    async () => await makeLoanRequestPayload(walletName, msg.payload.collateral, msg.payload.term),
                     MessageKind.LoanResponse,
                 );
                 break;
             case MessageKind.SignAndSendSwap:
                 try {
-                    const txHex = msg.payload;
-                    const decoded = await extractTrade(walletName, txHex);
+                    const { txHex, serviceFeeSecrets } = msg.payload;
+                    const decoded = await extractTrade(
+                        walletName,
+                        txHex,
+                        swapQuote ?? null,
+                        serviceFeeSecrets ?? null,
+                    );
                     swapToSign = { txHex, decoded, tabId: sender.tab!.id! };
                     updateBadge();
                 } catch (e) {
@@ -96,6 +101,68 @@ browser.runtime.onMessage.addListener(async (msg: Message<any>, sender) => {
     }
 });
 
+// Bobtimus is served from the same origin as the page asking for a swap.
+// If its fee rate cannot be fetched we let the wallet fall back to its default.
+async function getFeeRate(pageUrl: string | undefined): Promise<number | undefined> {
+    if (!pageUrl) {
+        return undefined;
+    }
+    try {
+        const res = await fetch(`${new URL(pageUrl).origin}/api/fee-rate`);
+        if (!res.ok) {
+            return undefined;
+        }
+        const body = await res.json();
+        return body.fee_rate;
+    } catch (e) {
+        error(`Failed to fetch fee rate: ${e}`);
+        return undefined;
+    }
+}
+
+async function makeSellPayload(amount: SellAmount, pageUrl: string | undefined): Promise<CreateSwapPayload> {
+    const feeRate = await getFeeRate(pageUrl);
+    swapQuote = typeof amount === "string"
+        ? await getQuote(pageUrl, "sell", amount, "exact_input")
+        : await getQuote(pageUrl, "sell", amount.usdt_to_receive, "exact_output");
+    return makeQuotedCreateSwapPayload(walletName, swapQuote, feeRate);
+}
+
+async function makeBuyPayload(amount: BuyAmount, pageUrl: string | undefined): Promise<CreateSwapPayload> {
+    const feeRate = await getFeeRate(pageUrl);
+    swapQuote = typeof amount === "string"
+        ? await getQuote(pageUrl, "buy", amount, "exact_input")
+        : await getQuote(pageUrl, "buy", amount.btc_to_receive, "exact_output");
+    return makeQuotedCreateSwapPayload(walletName, swapQuote, feeRate);
+}
+
+// Bobtimus tells us how much we have to send and how much we receive, including
+// its service fee, so that we can select enough coins and later check the swap
+// transaction against it.
+async function getQuote(
+    pageUrl: string | undefined,
+    direction: "buy" | "sell",
+    amount: string,
+    mode: "exact_input" | "exact_output",
+): Promise<Quote> {
+    if (!pageUrl) {
+        throw new Error("Cannot request a quote without knowing Bobtimus' URL");
+    }
+    const res = await fetch(`${new URL(pageUrl).origin}/api/quote/lbtc-lusdt`, {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({
+            direction,
+            amount: Math.round(Number(amount) * 100000000),
+            mode,
+        }),
+    });
+    if (!res.ok) {
+        throw new Error(`Failed to request quote: ${await res.text()}`);
+    }
+    return res.json();
+}
+
 async function call_wallet<T>(wallet_fn: () => Promise<T>, kind: MessageKind): Promise<Message<T | undefined>> {
     let payload;
     let err;
@@ -1,4 +1,4 @@
-import { Box, Button, Heading } from "@chakra-ui/react";
+import { Box, Button, Heading, Text } from "@chakra-ui/react";
 import React from "react";
 import { useAsync } from "react-async";
 import { signAndSendSwap } from "../background-proxy";
@@ -43,6 +43,10 @@ export default function ConfirmSwap(
                     action={"receive"}
                 />
             </Box>
+            {decoded.serviceFee
+                && <Text textStyle="smGray">
+                    Includes a service fee of {decoded.serviceFee.amount} {decoded.serviceFee.ticker}
+                </Text>}
 
             <Button
                 variant="secondary"
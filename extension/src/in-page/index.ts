@@ -1,6 +1,16 @@
 import Debug from "debug";
 import { Direction, Message, MessageKind } from "../messages";
-import { Address, CreateSwapPayload, LoanRequestPayload, Tx, Txid, WalletStatus } from "../models";
+import {
+    Address,
+    BuyAmount,
+    CreateSwapPayload,
+    LoanRequestPayload,
+    SellAmount,
+    Tx,
+    Txid,
+    TxOutSecrets,
+    WalletStatus,
+} from "../models";
 
 Debug.enable("*");
 const debug = Debug("inpage");
@@ -33,7 +43,7 @@ export default class WavesProvider {
         return promise;
     }
 
-    public async getSellCreateSwapPayload(btc: string): Promise<CreateSwapPayload> {
+    public async getSellCreateSwapPayload(amount: SellAmount): Promise<CreateSwapPayload> {
         debug("Getting sell create-swap payload");
         let promise = new Promise<CreateSwapPayload>((resolve, reject) => {
             let listener = async function(event: MessageEvent<Message<CreateSwapPayload>>) {
@@ -56,12 +66,12 @@ export default class WavesProvider {
         window.postMessage({
             kind: MessageKind.SellRequest,
             direction: Direction.ToBackground,
-            payload: btc,
+            payload: amount,
         }, "*");
         return promise;
     }
 
-    public async getBuyCreateSwapPayload(usdt: string): Promise<CreateSwapPayload> {
+    public async getBuyCreateSwapPayload(amount: BuyAmount): Promise<CreateSwapPayload> {
         debug("Getting buy create-swap payload");
         let promise = new Promise<CreateSwapPayload>((resolve, reject) => {
             let listener = async function(event: MessageEvent<Message<CreateSwapPayload>>) {
@@ -84,7 +94,7 @@ export default class WavesProvider {
         window.postMessage({
             kind: MessageKind.BuyRequest,
             direction: Direction.ToBackground,
-            payload: usdt,
+            payload: amount,
         }, "*");
         return promise;
     }
@@ -116,7 +126,7 @@ export default class WavesProvider {
         return promise;
     }
 
-    public async makeLoanRequestPayload(collateral: string): Promise<LoanRequestPayload> {
+    public async makeLoanRequestPayload(collateral: string, term: number): Promise<LoanRequestPayload> {
         debug("Making loan request payload");
         let promise = new Promise<LoanRequestPayload>((resolve, reject) => {
             let listener = async function(event: MessageEvent<Message<LoanRequestPayload>>) {
@@ -139,12 +149,12 @@ export default class WavesProvider {
         window.postMessage({
             kind: MessageKind.LoanRequest,
             direction: Direction.ToBackground,
-            payload: collateral,
+            payload: { collateral, term },
         }, "*");
         return promise;
     }
 
-    public async signAndSendSwap(tx_hex: string): Promise<Txid> {
+    public async signAndSendSwap(tx_hex: string, serviceFeeSecrets?: TxOutSecrets): Promise<Txid> {
         debug("Signing and sending swap");
         let promise = new Promise<Txid>((resolve, reject) => {
             let listener = async function(event: MessageEvent<Message<Txid>>) {
@@ -173,7 +183,7 @@ export default class WavesProvider {
         window.postMessage({
             kind: MessageKind.SignAndSendSwap,
             direction: Direction.ToBackground,
-            payload: tx_hex,
+            payload: { txHex: tx_hex, serviceFeeSecrets },
         }, "*");
         return promise;
     }
@@ -35,9 +35,15 @@ export interface TradeSide {
     balanceAfter: number;
 }
 
+export interface ServiceFee {
+    ticker: string;
+    amount: number;
+}
+
 export interface Trade {
     buy: TradeSide;
     sell: TradeSide;
+    serviceFee?: ServiceFee;
 }
 
 export interface SwapToSign {
@@ -61,10 +67,38 @@ export interface LoanToSign {
 
 export type Tx = string;
 
+/// The asset, value and blinding factors of a transaction output.
+export interface TxOutSecrets {
+    asset: string;
+    asset_bf: string;
+    value: number;
+    value_bf: string;
+}
+
 export interface CreateSwapPayload {
-    alice_inputs: { outpoint: OutPoint; blinding_key: string }[];
+    alice_inputs: { outpoint: OutPoint; secrets: TxOutSecrets }[];
     address: string;
     amount: number;
+    mode: SwapMode;
+    quote_id?: string;
+}
+
+/// Whether `amount` is what the taker sends or receives.
+export type SwapMode = "exact_input" | "exact_output";
+
+/// Either the amount of L-BTC to sell, or the amount of L-USDt to receive for it.
+export type SellAmount = string | { usdt_to_receive: string };
+
+/// Either the amount of L-USDt to sell, or the amount of L-BTC to receive for it.
+export type BuyAmount = string | { btc_to_receive: string };
+
+/// A firm offer from Bobtimus, amounts in satoshi.
+export interface Quote {
+    id: string;
+    direction: "buy" | "sell";
+    input_amount: number;
+    output_amount: number;
+    service_fee: number;
 }
 
 export interface LoanRequestPayload {
@@ -1,5 +1,16 @@
 import Debug from "debug";
-import { Address, BalanceUpdate, CreateSwapPayload, LoanDetails, Status, Trade, Txid, WalletStatus } from "./models";
+import {
+    Address,
+    BalanceUpdate,
+    CreateSwapPayload,
+    LoanDetails,
+    Quote,
+    Status,
+    Trade,
+    Txid,
+    TxOutSecrets,
+    WalletStatus,
+} from "./models";
 
 Debug.enable("*");
 const debug = Debug("wasmProxy");
@@ -48,25 +59,48 @@ export async function getBalances(name: string): Promise<BalanceUpdate> {
     return get_balances(name);
 }
 
-export async function makeSellCreateSwapPayload(name: string, btc: string): Promise<CreateSwapPayload> {
+export async function makeSellCreateSwapPayload(
+    name: string,
+    btc: string,
+    feeRate?: number,
+): Promise<CreateSwapPayload> {
     const { make_sell_create_swap_payload } = await import("./wallet");
 
     debug("makeSellCreateSwapPayload");
-    return make_sell_create_swap_payload(name, btc);
+    return make_sell_create_swap_payload(name, btc, feeRate);
 }
 
-export async function makeBuyCreateSwapPayload(name: string, usdt: string): Promise<CreateSwapPayload> {
+export async function makeBuyCreateSwapPayload(
+    name: string,
+    usdt: string,
+    feeRate?: number,
+): Promise<CreateSwapPayload> {
     const { make_buy_create_swap_payload } = await import("./wallet");
 
     debug("makeBuyCreateSwapPayload");
-    return make_buy_create_swap_payload(name, usdt);
+    return make_buy_create_swap_payload(name, usdt, feeRate);
 }
 
-export async function makeLoanRequestPayload(name: string, collateral: string): Promise<CreateSwapPayload> {
+export async function makeQuotedCreateSwapPayload(
+    name: string,
+    quote: Quote,
+    feeRate?: number,
+): Promise<CreateSwapPayload> {
+    const { make_quoted_create_swap_payload } = await import("./wallet");
+
+    debug("makeQuotedCreateSwapPayload");
+    return make_quoted_create_swap_payload(name, quote, feeRate);
+}
+
+export async function makeLoanRequestPayload(
+    name: string,
+    collateral: string,
+    term: number,
+): Promise<CreateSwapPayload> {
     const { make_loan_request } = await import("./wallet");
 
     debug("makeLoanRequestPayload");
-    return make_loan_request(name, collateral);
+    return make_loan_request(name, collateral, term);
 }
 
 export async function signAndSendSwap(name: string, hex: string): Promise<Txid> {
@@ -78,12 +112,17 @@ export async function signAndSendSwap(name: string, hex: string): Promise<Txid>
     return sign_and_send_swap_transaction(name, tx);
 }
 
-export async function extractTrade(name: string, hex: string): Promise<Trade> {
+export async function extractTrade(
+    name: string,
+    hex: string,
+    quote: Quote | null,
+    serviceFeeSecrets: TxOutSecrets | null,
+): Promise<Trade> {
     const { extract_trade } = await import("./wallet");
 
     debug("extractTrade");
     const tx = { inner: hex };
-    return extract_trade(name, tx);
+    return extract_trade(name, tx, quote, serviceFeeSecrets);
 }
 
 // TODO: Replace any with actual LoanResponse interface
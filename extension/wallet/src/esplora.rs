@@ -45,6 +45,33 @@ pub async fn fetch_utxos(address: &Address) -> Result<Vec<Utxo>> {
         .context("failed to deserialize response")
 }
 
+/// Fetch the height of the current chain tip.
+pub async fn fetch_block_height() -> Result<u32> {
+    let esplora_url = {
+        let guard = ESPLORA_API_URL.lock().expect_throw("can get lock");
+        guard.clone()
+    };
+
+    let esplora_url = esplora_url.join("blocks/tip/height")?;
+    let response = reqwest::get(esplora_url.clone())
+        .await
+        .context("failed to fetch block height")?;
+
+    if !response.status().is_success() {
+        let error_body = response.text().await?;
+        return Err(anyhow!(
+            "failed to fetch block height, esplora returned '{}'",
+            error_body
+        ));
+    }
+
+    response
+        .text()
+        .await?
+        .parse()
+        .context("failed to parse response body as block height")
+}
+
 /// Fetch transaction history for the specified address.
 ///
 /// Returns up to 50 mempool transactions plus the first 25 confirmed
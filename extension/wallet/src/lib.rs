@@ -166,14 +166,25 @@ pub async fn withdraw_everything_to(name: String, address: String) -> Result<JsV
 /// Constructs a new [`CreateSwapPayload`] with the given USDt amount.
 ///
 /// This will select UTXOs from the wallet to cover the given amount.
+///
+/// The `fee_rate` in sat/vB should be the one advertised by Bobtimus
+/// at `/api/fee-rate`. It defaults to 1 sat/vB if not provided.
 #[wasm_bindgen]
 pub async fn make_buy_create_swap_payload(
     wallet_name: String,
     usdt: String,
+    fee_rate: Option<u32>,
 ) -> Result<JsValue, JsValue> {
     let usdt = map_err_from_anyhow!(Amount::from_str_in(&usdt, Denomination::Bitcoin))?;
+    let fee_rate = fee_rate.map_or(DEFAULT_SAT_PER_VBYTE, u64::from);
     let payload = map_err_from_anyhow!(
-        wallet::make_buy_create_swap_payload(wallet_name, &LOADED_WALLET, usdt).await
+        wallet::make_buy_create_swap_payload(
+            wallet_name,
+            &LOADED_WALLET,
+            usdt,
+            Amount::from_sat(fee_rate)
+        )
+        .await
     )?;
     let payload = map_err_from_anyhow!(JsValue::from_serde(&payload))?;
 
@@ -183,14 +194,55 @@ pub async fn make_buy_create_swap_payload(
 /// Constructs a new [`CreateSwapPayload`] with the given Bitcoin amount.
 ///
 /// This will select UTXOs from the wallet to cover the given amount.
+///
+/// The `fee_rate` in sat/vB should be the one advertised by Bobtimus
+/// at `/api/fee-rate`. It defaults to 1 sat/vB if not provided.
 #[wasm_bindgen]
 pub async fn make_sell_create_swap_payload(
     wallet_name: String,
     btc: String,
+    fee_rate: Option<u32>,
 ) -> Result<JsValue, JsValue> {
     let btc = map_err_from_anyhow!(Amount::from_str_in(&btc, Denomination::Bitcoin))?;
+    let fee_rate = fee_rate.map_or(DEFAULT_SAT_PER_VBYTE, u64::from);
+    let payload = map_err_from_anyhow!(
+        wallet::make_sell_create_swap_payload(
+            wallet_name,
+            &LOADED_WALLET,
+            btc,
+            Amount::from_sat(fee_rate)
+        )
+        .await
+    )?;
+    let payload = map_err_from_anyhow!(JsValue::from_serde(&payload))?;
+
+    Ok(payload)
+}
+
+/// Constructs a new [`CreateSwapPayload`] for a `quote` handed out by
+/// Bobtimus at `/api/quote/{pair}`.
+///
+/// This will select UTXOs from the wallet to cover the quoted input
+/// amount and asks Bobtimus for exactly the quoted output amount.
+///
+/// The `fee_rate` in sat/vB should be the one advertised by Bobtimus
+/// at `/api/fee-rate`. It defaults to 1 sat/vB if not provided.
+#[wasm_bindgen]
+pub async fn make_quoted_create_swap_payload(
+    wallet_name: String,
+    quote: JsValue,
+    fee_rate: Option<u32>,
+) -> Result<JsValue, JsValue> {
+    let quote = map_err_from_anyhow!(quote.into_serde())?;
+    let fee_rate = fee_rate.map_or(DEFAULT_SAT_PER_VBYTE, u64::from);
     let payload = map_err_from_anyhow!(
-        wallet::make_sell_create_swap_payload(wallet_name, &LOADED_WALLET, btc).await
+        wallet::make_quoted_create_swap_payload(
+            wallet_name,
+            &LOADED_WALLET,
+            quote,
+            Amount::from_sat(fee_rate)
+        )
+        .await
     )?;
     let payload = map_err_from_anyhow!(JsValue::from_serde(&payload))?;
 
@@ -200,6 +252,8 @@ pub async fn make_sell_create_swap_payload(
 /// Constructs a new [`CreateSwapPayload`] with the given Bitcoin amount.
 ///
 /// This will select UTXOs from the wallet to cover the given amount.
+/// The loan can be liquidated once `term` blocks have been mined on
+/// top of the current chain tip.
 ///
 /// Additionally, sets the state of the loan protocol so that we can
 /// continue after the lender sends back a response to our loan
@@ -208,10 +262,11 @@ pub async fn make_sell_create_swap_payload(
 pub async fn make_loan_request(
     wallet_name: String,
     collateral: String,
+    term: u32,
 ) -> Result<JsValue, JsValue> {
     let collateral = map_err_from_anyhow!(Amount::from_str_in(&collateral, Denomination::Bitcoin))?;
     let loan_request = map_err_from_anyhow!(
-        wallet::make_loan_request(wallet_name, &LOADED_WALLET, collateral).await
+        wallet::make_loan_request(wallet_name, &LOADED_WALLET, collateral, term).await
     )?;
     let loan_request = map_err_from_anyhow!(JsValue::from_serde(&loan_request))?;
 
@@ -254,11 +309,29 @@ pub async fn sign_and_send_swap_transaction(
 /// - Buy amount, buy balance before and buy balance after.
 ///
 /// To do so we unblind confidential `TxOut`s whenever necessary.
+///
+/// The service fee Bob takes must match the one of the `quote` the
+/// swap was made for, if any. If Bob blinded the output paying it,
+/// it is checked against the `service_fee_secrets` Bob handed out.
 #[wasm_bindgen]
-pub async fn extract_trade(wallet_name: String, transaction: JsValue) -> Result<JsValue, JsValue> {
+pub async fn extract_trade(
+    wallet_name: String,
+    transaction: JsValue,
+    quote: JsValue,
+    service_fee_secrets: JsValue,
+) -> Result<JsValue, JsValue> {
     let transaction: Transaction = map_err_from_anyhow!(transaction.into_serde())?;
+    let quote = map_err_from_anyhow!(quote.into_serde())?;
+    let service_fee_secrets = map_err_from_anyhow!(service_fee_secrets.into_serde())?;
     let trade = map_err_from_anyhow!(
-        wallet::extract_trade(wallet_name, &LOADED_WALLET, transaction.into()).await
+        wallet::extract_trade(
+            wallet_name,
+            &LOADED_WALLET,
+            transaction.into(),
+            quote,
+            service_fee_secrets
+        )
+        .await
     )?;
     let trade = map_err_from_anyhow!(JsValue::from_serde(&trade))?;
 
@@ -17,7 +17,7 @@ use elements::{
     },
     confidential,
     secp256k1_zkp::{rand, PublicKey},
-    Address, AssetId, OutPoint, TxOut, Txid,
+    Address, AssetId, OutPoint, TxOut, TxOutSecrets, Txid,
 };
 use futures::{
     lock::{MappedMutexGuard, Mutex, MutexGuard},
@@ -47,7 +47,8 @@ pub use get_status::{get_status, WalletStatus};
 pub use get_transaction_history::get_transaction_history;
 pub use load_existing::load_existing;
 pub use make_create_swap_payload::{
-    make_buy_create_swap_payload, make_sell_create_swap_payload, Error as MakePayloadError,
+    make_buy_create_swap_payload, make_quoted_create_swap_payload, make_sell_create_swap_payload,
+    Error as MakePayloadError,
 };
 pub use make_loan_request::{make_loan_request, Error as MakeLoanRequestError};
 pub use repay_loan::{repay_loan, Error as RepayLoanError};
@@ -293,12 +294,53 @@ pub struct CreateSwapPayload {
     pub address: Address,
     #[serde(with = "bdk::bitcoin::util::amount::serde::as_sat")]
     pub amount: bdk::bitcoin::Amount,
+    pub mode: SwapMode,
+    /// The quote whose price we expect Bob to honour.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_id: Option<String>,
 }
 
+/// A quote handed out by Bob at `/api/quote/{pair}`.
+///
+/// Amounts are in satoshi, as returned by Bob.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Quote {
+    pub id: String,
+    pub direction: QuoteDirection,
+    /// The amount we have to send to Bob.
+    pub input_amount: u64,
+    /// The amount Bob will send to us.
+    pub output_amount: u64,
+    /// The amount of L-USDt Bob keeps as a service fee, already
+    /// accounted for in the other amounts.
+    pub service_fee: u64,
+}
+
+/// Whether we buy or sell L-BTC for L-USDt.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteDirection {
+    Buy,
+    Sell,
+}
+
+/// Whether the `amount` of a [`CreateSwapPayload`] is what we send or
+/// what we receive.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapMode {
+    ExactInput,
+    ExactOutput,
+}
+
+/// An input we contribute to a swap.
+///
+/// Only the secrets of the spent output are shared, not our blinding
+/// key.
 #[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
 pub struct SwapUtxo {
     pub outpoint: OutPoint,
-    pub blinding_key: SecretKey,
+    pub secrets: TxOutSecrets,
 }
 
 /// A single balance entry as returned by [`get_balances`].
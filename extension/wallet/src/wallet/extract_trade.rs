@@ -1,18 +1,23 @@
 use crate::{
-    wallet::{compute_balances, current, get_txouts, Wallet},
-    TradeSide,
+    assets,
+    wallet::{compute_balances, current, get_txouts, Quote, Wallet},
+    TradeSide, USDT_ASSET_ID,
 };
 use anyhow::{bail, Context, Result};
-use elements::{confidential, secp256k1_zkp::SECP256K1, Transaction, TxOut};
+use elements::{confidential, secp256k1_zkp::SECP256K1, AssetId, Transaction, TxOut, TxOutSecrets};
 use futures::lock::Mutex;
 use itertools::Itertools;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use wasm_bindgen::UnwrapThrowExt;
 
 // TODO: Public APIs should return specific error struct/enum
 pub async fn extract_trade(
     name: String,
     current_wallet: &Mutex<Option<Wallet>>,
     transaction: Transaction,
+    quote: Option<Quote>,
+    service_fee_secrets: Option<TxOutSecrets>,
 ) -> Result<Trade> {
     let wallet = current(&name, current_wallet).await?;
 
@@ -68,6 +73,45 @@ pub async fn extract_trade(
         .context("expected single input asset type")?;
 
     let our_address = wallet.get_address();
+
+    // The maker pays its service fee to an output which, unlike the
+    // transaction fee output, has a script. It is either explicit or
+    // opened by the secrets the maker handed us. Anything else would
+    // let it take more than it quoted us.
+    let usdt_asset_id = {
+        let guard = USDT_ASSET_ID.lock().expect_throw("can get lock");
+        *guard
+    };
+    let expected_service_fee = quote.map_or(0, |quote| quote.service_fee);
+    let service_fee = transaction
+        .output
+        .iter()
+        .filter_map(|txout| match txout {
+            TxOut {
+                asset: confidential::Asset::Explicit(asset),
+                value: confidential::Value::Explicit(value),
+                script_pubkey,
+                ..
+            } if !script_pubkey.is_empty() && script_pubkey != &our_address.script_pubkey() => {
+                Some((*asset, *value))
+            }
+            txout => service_fee_secrets
+                .filter(|secrets| opens_blinded(txout, secrets))
+                .map(|secrets| (secrets.asset, secrets.value)),
+        })
+        .into_grouping_map()
+        .fold(0, |sum, _asset, value| sum + value)
+        .into_iter()
+        .at_most_one()
+        .context("expected service fee in a single asset")?;
+    let service_fee = match service_fee {
+        None if expected_service_fee == 0 => None,
+        Some((asset, amount)) if asset == usdt_asset_id && amount == expected_service_fee => {
+            Some(ServiceFee::new(asset, amount)?)
+        }
+        _ => bail!("service fee does not match the quote"),
+    };
+
     let our_outputs = transaction
         .output
         .iter()
@@ -135,11 +179,56 @@ pub async fn extract_trade(
     Ok(Trade {
         sell: TradeSide::new_sell(sell_asset, sell_amount, sell_balance)?,
         buy: TradeSide::new_buy(buy_asset, buy_amount, buy_balance)?,
+        service_fee,
     })
 }
 
+/// Whether `secrets` open the asset and value commitments of the
+/// blinded `txout`.
+fn opens_blinded(txout: &TxOut, secrets: &TxOutSecrets) -> bool {
+    let asset = confidential::Asset::new_confidential(SECP256K1, secrets.asset, secrets.asset_bf);
+    let generator = match asset.commitment() {
+        Some(generator) => generator,
+        None => return false,
+    };
+    let value = confidential::Value::new_confidential(
+        SECP256K1,
+        secrets.value,
+        generator,
+        secrets.value_bf,
+    );
+
+    txout.asset == asset && txout.value == value
+}
+
 #[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct Trade {
     pub sell: TradeSide,
     pub buy: TradeSide,
+    /// The fee the maker charges on top of the spread, already
+    /// accounted for in the amounts of the trade.
+    pub service_fee: Option<ServiceFee>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+pub struct ServiceFee {
+    pub ticker: String,
+    pub amount: Decimal,
+}
+
+impl ServiceFee {
+    fn new(asset: AssetId, amount: u64) -> Result<Self> {
+        let (ticker, precision) = assets::lookup(asset).context("asset not found")?;
+
+        let mut amount = Decimal::from(amount);
+        amount
+            .set_scale(precision as u32)
+            .expect("precision must be < 28");
+
+        Ok(Self {
+            ticker: ticker.to_owned(),
+            amount,
+        })
+    }
 }
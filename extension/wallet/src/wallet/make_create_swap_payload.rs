@@ -1,5 +1,7 @@
 use crate::{
-    wallet::{current, get_txouts, CreateSwapPayload, SwapUtxo, Wallet},
+    wallet::{
+        current, get_txouts, CreateSwapPayload, Quote, QuoteDirection, SwapMode, SwapUtxo, Wallet,
+    },
     BTC_ASSET_ID, USDT_ASSET_ID,
 };
 use bdk::bitcoin::Amount;
@@ -13,6 +15,7 @@ pub async fn make_buy_create_swap_payload(
     name: String,
     current_wallet: &Mutex<Option<Wallet>>,
     sell_amount: Amount,
+    bobs_fee_rate: Amount,
 ) -> Result<CreateSwapPayload, Error> {
     let btc_asset_id = {
         let guard = BTC_ASSET_ID.lock().expect_throw("can get lock");
@@ -29,6 +32,7 @@ pub async fn make_buy_create_swap_payload(
         sell_amount,
         usdt_asset_id,
         btc_asset_id,
+        bobs_fee_rate,
     )
     .await
 }
@@ -37,6 +41,7 @@ pub async fn make_sell_create_swap_payload(
     name: String,
     current_wallet: &Mutex<Option<Wallet>>,
     sell_amount: Amount,
+    bobs_fee_rate: Amount,
 ) -> Result<CreateSwapPayload, Error> {
     let btc_asset_id = {
         let guard = BTC_ASSET_ID.lock().expect_throw("can get lock");
@@ -48,16 +53,59 @@ pub async fn make_sell_create_swap_payload(
         sell_amount,
         btc_asset_id,
         btc_asset_id,
+        bobs_fee_rate,
     )
     .await
 }
 
+/// Ask Bob for exactly the output amount of a `quote` he handed out,
+/// selecting enough coins to pay its input amount.
+///
+/// Bob honours the amounts of the quote whatever mode it was asked
+/// for, so pinning the output lets us check what we receive.
+pub async fn make_quoted_create_swap_payload(
+    name: String,
+    current_wallet: &Mutex<Option<Wallet>>,
+    quote: Quote,
+    bobs_fee_rate: Amount,
+) -> Result<CreateSwapPayload, Error> {
+    let btc_asset_id = {
+        let guard = BTC_ASSET_ID.lock().expect_throw("can get lock");
+        *guard
+    };
+    let sell_asset = match quote.direction {
+        QuoteDirection::Buy => {
+            let guard = USDT_ASSET_ID.lock().expect_throw("can get lock");
+            *guard
+        }
+        QuoteDirection::Sell => btc_asset_id,
+    };
+
+    let payload = make_create_swap_payload(
+        name,
+        current_wallet,
+        Amount::from_sat(quote.input_amount),
+        sell_asset,
+        btc_asset_id,
+        bobs_fee_rate,
+    )
+    .await?;
+
+    Ok(CreateSwapPayload {
+        amount: Amount::from_sat(quote.output_amount),
+        mode: SwapMode::ExactOutput,
+        quote_id: Some(quote.id),
+        ..payload
+    })
+}
+
 async fn make_create_swap_payload(
     name: String,
     current_wallet: &Mutex<Option<Wallet>>,
     sell_amount: Amount,
     sell_asset: AssetId,
     fee_asset: AssetId,
+    bobs_fee_rate: Amount,
 ) -> Result<CreateSwapPayload, Error> {
     let wallet = current(&name, current_wallet)
         .await
@@ -74,12 +122,15 @@ async fn make_create_swap_payload(
             let candidate_asset = unblinded_txout.asset;
 
             if candidate_asset == sell_asset {
-                Some(coin_selection::Utxo {
-                    outpoint,
-                    value: unblinded_txout.value,
-                    script_pubkey: txout.script_pubkey,
-                    asset: candidate_asset,
-                })
+                Some((
+                    coin_selection::Utxo {
+                        outpoint,
+                        value: unblinded_txout.value,
+                        script_pubkey: txout.script_pubkey,
+                        asset: candidate_asset,
+                    },
+                    unblinded_txout,
+                ))
             } else {
                 log::debug!(
                     "utxo {} with asset id {} is not the sell asset, ignoring",
@@ -94,11 +145,9 @@ async fn make_create_swap_payload(
     .map_err(Error::GetTxOuts)?;
 
     let (bobs_fee_rate, fee_offset) = if fee_asset == sell_asset {
-        // Bob currently hardcodes a fee-rate of 1 sat / vbyte, hence
-        // there is no need for us to perform fee estimation. Later
-        // on, both parties should probably agree on a block-target
-        // and use the same estimation service.
-        let bobs_fee_rate = Amount::from_sat(1);
+        // Bob advertises the fee rate he uses for the swap
+        // transaction, so we use the same one to make sure that we
+        // select enough coins to pay our share of the fee
         let fee_offset = calculate_fee_offset(bobs_fee_rate);
 
         (bobs_fee_rate, fee_offset)
@@ -107,7 +156,7 @@ async fn make_create_swap_payload(
     };
 
     let output = coin_select(
-        utxos,
+        utxos.iter().map(|(utxo, _)| utxo).cloned().collect(),
         sell_amount,
         bobs_fee_rate.as_sat() as f32,
         fee_offset,
@@ -119,12 +168,21 @@ async fn make_create_swap_payload(
         alice_inputs: output
             .coins
             .into_iter()
-            .map(|utxo| SwapUtxo {
-                outpoint: utxo.outpoint,
-                blinding_key,
+            .map(|coin| {
+                let secrets = utxos
+                    .iter()
+                    .find_map(|(utxo, secrets)| (utxo.outpoint == coin.outpoint).then(|| *secrets))
+                    .expect("same source of utxos");
+
+                SwapUtxo {
+                    outpoint: coin.outpoint,
+                    secrets,
+                }
             })
             .collect(),
         amount: output.target_amount,
+        mode: SwapMode::ExactInput,
+        quote_id: None,
     })
 }
 
@@ -1,4 +1,5 @@
 use crate::{
+    esplora,
     storage::Storage,
     wallet::{current, get_txouts, Wallet},
     BTC_ASSET_ID, DEFAULT_SAT_PER_VBYTE, USDT_ASSET_ID,
@@ -18,6 +19,7 @@ pub async fn make_loan_request(
     name: String,
     current_wallet: &Mutex<Option<Wallet>>,
     collateral_amount: Amount,
+    term: u32,
 ) -> Result<LoanRequest, Error> {
     let btc_asset_id = {
         let guard = BTC_ASSET_ID.lock().expect_throw("can get lock");
@@ -109,6 +111,11 @@ pub async fn make_loan_request(
         }
     };
 
+    let block_height = esplora::fetch_block_height()
+        .await
+        .map_err(Error::FetchBlockHeight)?;
+    let timelock = u64::from(block_height) + u64::from(term);
+
     let borrower = Borrower0::new(
         &mut thread_rng(),
         coin_selector,
@@ -116,9 +123,7 @@ pub async fn make_loan_request(
         blinding_key,
         collateral_amount,
         Amount::from_sat(DEFAULT_SAT_PER_VBYTE),
-        // TODO: This must be chosen explicitly either by the borrower
-        // through the UI or by Bobtimus via configuration
-        0,
+        timelock,
         btc_asset_id,
         usdt_asset_id,
     )
@@ -140,6 +145,8 @@ pub async fn make_loan_request(
 pub enum Error {
     #[error("Wallet is not loaded {0}")]
     LoadWallet(anyhow::Error),
+    #[error("Failed to fetch block height: {0}")]
+    FetchBlockHeight(anyhow::Error),
     #[error("Failed to construct borrower state: {0}")]
     BuildBorrowerState(anyhow::Error),
     #[error("Storage error: {0}")]
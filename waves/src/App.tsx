@@ -24,6 +24,7 @@ export type AssetSide = "Alpha" | "Beta";
 export type Action =
     | { type: "UpdateAlphaAmount"; value: string }
     | { type: "UpdatePrincipalAmount"; value: string }
+    | { type: "UpdateLoanTerm"; value: number }
     | { type: "UpdateAlphaAssetType"; value: Asset }
     | { type: "UpdateBetaAssetType"; value: Asset }
     | {
@@ -198,6 +199,14 @@ export function reducer(state: State = initialState, action: Action) {
                     principalAmount: action.value,
                 },
             };
+        case "UpdateLoanTerm":
+            return {
+                ...state,
+                borrow: {
+                    ...state.borrow,
+                    loanTerm: action.value,
+                },
+            };
         default:
             throw new Error("Unknown update action received");
     }
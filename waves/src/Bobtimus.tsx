@@ -1,7 +1,7 @@
 import Debug from "debug";
 import React, { ReactElement } from "react";
 import { SSEProvider } from "react-hooks-sse";
-import { CreateSwapPayload, LoanRequestPayload } from "./waves-provider/wavesProvider";
+import { CreateSwapPayload, LoanRequestPayload, TxOutSecrets } from "./waves-provider/wavesProvider";
 
 const debug = Debug("bobtimus");
 
@@ -19,14 +19,37 @@ export async function postBuyPayload(payload: CreateSwapPayload) {
     return await postPayload(payload, "buy");
 }
 
-export async function postLoanRequest(payload: LoanRequestPayload) {
+export interface LoanTerms {
+    max_ltv: number;
+    interest_rate: number;
+    term_days: number;
+    term_blocks: number;
+}
+
+export interface LoanOffer {
+    rate: number;
+    terms: LoanTerms[];
+}
+
+export async function getLoanOffer(): Promise<LoanOffer> {
+    let res = await fetch(`/api/loan/lbtc-lusdt/offer`);
+
+    if (res.status !== 200) {
+        debug("failed to get loan offer");
+        throw new Error("failed to get loan offer");
+    }
+
+    return await res.json();
+}
+
+export async function postLoanRequest(payload: LoanRequestPayload, term: number) {
     let res = await fetch(`/api/loan/lbtc-lusdt`, {
         method: "POST",
         headers: {
             "Content-Type": "application/json",
             Accept: "application/json",
         },
-        body: JSON.stringify(payload),
+        body: JSON.stringify({ ...payload, term }),
     });
 
     if (res.status !== 200) {
@@ -70,7 +93,14 @@ async function postPayload(payload: CreateSwapPayload, path: string) {
         throw new Error("failed to create new swap");
     }
 
-    return await res.text();
+    // The output paying Bobtimus' service fee is blinded, so the wallet
+    // needs its secrets to check it
+    const serviceFeeSecrets = res.headers.get("service-fee-secrets");
+
+    return {
+        txHex: await res.text(),
+        serviceFeeSecrets: serviceFeeSecrets ? JSON.parse(serviceFeeSecrets) as TxOutSecrets : undefined,
+    };
 }
 
 interface RateProviderProps {
@@ -1,10 +1,10 @@
-import { Button, Center, useToast, VStack } from "@chakra-ui/react";
+import { Button, Center, Select, useToast, VStack } from "@chakra-ui/react";
 import Debug from "debug";
 import React, { Dispatch } from "react";
 import { AsyncState, useAsync } from "react-async";
 import { useHistory } from "react-router-dom";
 import { Action, Asset, BorrowState, Rate } from "./App";
-import { postLoanFinalization, postLoanRequest } from "./Bobtimus";
+import { getLoanOffer, postLoanFinalization, postLoanRequest } from "./Bobtimus";
 import calculateBetaAmount from "./calculateBetaAmount";
 import NumberInput from "./components/NumberInput";
 import RateInfo from "./components/RateInfo";
@@ -28,13 +28,18 @@ function Borrow({ dispatch, state, rate, wavesProvider, walletStatusAsyncState }
 
     let { data: walletStatus, reload: reloadWalletStatus, error: walletStatusError } = walletStatusAsyncState;
 
-    // TODO: We should get an up-to-date interest rate from Bobtimus
-    let interestRate = 0.10;
+    let { data: loanOffer } = useAsync({ promiseFn: getLoanOffer });
+    let loanTerms = loanOffer
+        ? loanOffer.terms.find((terms) => terms.term_days === state.loanTerm) || loanOffer.terms[0]
+        : undefined;
+    let interestRate = loanTerms ? loanTerms.interest_rate : 0;
+    let maxLtv = loanTerms ? loanTerms.max_ltv : 1;
 
     const principalAmount = Number.parseFloat(state.principalAmount);
+    // We are only lent a fraction of the value of our collateral
     let collateralAmount = calculateBetaAmount(
         Asset.USDT,
-        principalAmount,
+        principalAmount / maxLtv,
         rate,
     );
 
@@ -53,10 +58,17 @@ function Borrow({ dispatch, state, rate, wavesProvider, walletStatusAsyncState }
                 error("Cannot borrow. Waves provider not found.");
                 return;
             }
+            if (!loanTerms) {
+                error("Cannot borrow. No loan terms offered.");
+                return;
+            }
 
             try {
-                let loanRequest = await wavesProvider.makeLoanRequestPayload(collateralAmount.toString());
-                let loanResponse = await postLoanRequest(loanRequest);
+                let loanRequest = await wavesProvider.makeLoanRequestPayload(
+                    collateralAmount.toString(),
+                    loanTerms.term_blocks,
+                );
+                let loanResponse = await postLoanRequest(loanRequest, loanTerms.term_blocks);
                 let loanTransaction = await wavesProvider.signLoan(loanResponse);
                 let txid = await postLoanFinalization(loanTransaction);
 
@@ -156,7 +168,7 @@ function Borrow({ dispatch, state, rate, wavesProvider, walletStatusAsyncState }
                         isDisabled={true}
                         dataCy={"data-cy-collateral"}
                     />
-                    <p>Interest {interestRate}%:</p>
+                    <p>Interest {(interestRate * 100).toFixed(2)}%:</p>
                     <NumberInput
                         currency="₿"
                         value={interestAmount}
@@ -166,7 +178,22 @@ function Borrow({ dispatch, state, rate, wavesProvider, walletStatusAsyncState }
                         isDisabled={true}
                         dataCy={"data-cy-interest"}
                     />
-                    <p>Loan term (in days): {state.loanTerm}</p>
+                    <p>Loan term (in days):</p>
+                    <Select
+                        value={loanTerms ? loanTerms.term_days : state.loanTerm}
+                        onChange={(event) =>
+                            dispatch({
+                                type: "UpdateLoanTerm",
+                                value: Number.parseInt(event.target.value),
+                            })}
+                        data-cy={"data-cy-loan-term"}
+                    >
+                        {loanOffer?.terms.map((terms) => (
+                            <option key={terms.term_days} value={terms.term_days}>
+                                {terms.term_days}
+                            </option>
+                        ))}
+                    </Select>
                 </VStack>
             </Center>
 
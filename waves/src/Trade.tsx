@@ -43,17 +43,17 @@ function Trade({ state, dispatch, rate, walletStatusAsyncState, wavesProvider }:
                 error("Cannot swap. Waves provider not found.");
                 return;
             }
-            let tx;
+            let swap;
             try {
                 if (state.alpha.type === Asset.LBTC) {
                     const payload = await wavesProvider.getSellCreateSwapPayload(state.alpha.amount.toString());
-                    tx = await postSellPayload(payload);
+                    swap = await postSellPayload(payload);
                 } else {
                     const payload = await wavesProvider.getBuyCreateSwapPayload(state.alpha.amount.toString());
-                    tx = await postBuyPayload(payload);
+                    swap = await postBuyPayload(payload);
                 }
 
-                let txid = await wavesProvider.signAndSendSwap(tx);
+                let txid = await wavesProvider.signAndSendSwap(swap.txHex, swap.serviceFeeSecrets);
 
                 history.push(`/trade/swapped/${txid}`);
             } catch (e) {
@@ -1,4 +1,14 @@
-import { Address, CreateSwapPayload, LoanRequestPayload, LoanTx, Txid, WalletStatus } from "./wavesProvider";
+import {
+    Address,
+    BuyAmount,
+    CreateSwapPayload,
+    LoanRequestPayload,
+    LoanTx,
+    SellAmount,
+    Txid,
+    TxOutSecrets,
+    WalletStatus,
+} from "./wavesProvider";
 
 declare global {
     interface Window {
@@ -9,15 +19,15 @@ declare global {
 export default class WavesProvider {
     public async walletStatus(): Promise<WalletStatus>;
 
-    public async getSellCreateSwapPayload(btc: string): Promise<CreateSwapPayload>;
+    public async getSellCreateSwapPayload(amount: SellAmount): Promise<CreateSwapPayload>;
 
-    public async getBuyCreateSwapPayload(usdt: string): Promise<CreateSwapPayload>;
+    public async getBuyCreateSwapPayload(amount: BuyAmount): Promise<CreateSwapPayload>;
 
     public async getNewAddress(): Promise<Address>;
 
-    public async makeLoanRequestPayload(collateral: string): Promise<LoanRequestPayload>;
+    public async makeLoanRequestPayload(collateral: string, term: number): Promise<LoanRequestPayload>;
 
-    public async signAndSendSwap(tx_hex: string): Promise<Txid>;
+    public async signAndSendSwap(tx_hex: string, serviceFeeSecrets?: TxOutSecrets): Promise<Txid>;
 
     public async signLoan(loan_response: any): Promise<LoanTx>;
 }
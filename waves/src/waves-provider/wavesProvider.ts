@@ -23,12 +23,31 @@ export enum Status {
     NotLoaded = "NotLoaded",
 }
 
+/// The asset, value and blinding factors of a transaction output.
+export interface TxOutSecrets {
+    asset: string;
+    asset_bf: string;
+    value: number;
+    value_bf: string;
+}
+
 export interface CreateSwapPayload {
-    alice_inputs: { outpoint: OutPoint; blinding_key: string }[];
+    alice_inputs: { outpoint: OutPoint; secrets: TxOutSecrets }[];
     address: string;
     amount: number;
+    mode: SwapMode;
+    quote_id?: string;
 }
 
+/// Whether `amount` is what the taker sends or receives.
+export type SwapMode = "exact_input" | "exact_output";
+
+/// Either the amount of L-BTC to sell, or the amount of L-USDt to receive for it.
+export type SellAmount = string | { usdt_to_receive: string };
+
+/// Either the amount of L-USDt to sell, or the amount of L-BTC to receive for it.
+export type BuyAmount = string | { btc_to_receive: string };
+
 export interface LoanRequestPayload {
     collateral_amount: number;
     // TODO: Replace `any` with concrete type or get rid of `original_txout` field